@@ -1,38 +1,369 @@
-use crate::config;
+use crate::config::{self, AskConfig};
+use crate::editor::edit_text;
+use crate::git::find_git_root;
+use crate::sessions::{lock_file, write_atomic};
+use crate::tools::command_risk;
+use anyhow::{Context, Result, bail};
+use chrono::{DateTime, Local};
 use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
 use std::collections::HashSet;
 use std::io::Write;
+use std::path::{Path, PathBuf};
 use std::sync::Mutex;
 
 static AUTO_APPROVED_TOOLS: Lazy<Mutex<HashSet<String>>> = Lazy::new(|| Mutex::new(HashSet::new()));
 
+static CONTEXT: Lazy<Mutex<ApprovalContext>> = Lazy::new(|| Mutex::new(ApprovalContext::default()));
+
+/// Where the current run's approvals are granted.
+#[derive(Default)]
+struct ApprovalContext {
+    session: Option<String>,
+    /// How long new grants last; forever when unset.
+    expiry: Option<chrono::Duration>,
+}
+
+/// How far an "always" approval reaches.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ApprovalScope {
+    /// The rest of the current run; never stored.
+    Run,
+    Session,
+    /// The git repository, or the directory outside of one.
+    Project,
+    Global,
+}
+
+impl std::fmt::Display for ApprovalScope {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            ApprovalScope::Run => "run",
+            ApprovalScope::Session => "session",
+            ApprovalScope::Project => "project",
+            ApprovalScope::Global => "global",
+        })
+    }
+}
+
+/// A stored approval, in `~/.ask/approvals.json`.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ApprovalGrant {
+    /// The tool name, or the exact command for `execute_command`.
+    pub tool: String,
+    pub scope: ApprovalScope,
+    /// The session name or project directory the grant is limited to.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub target: Option<String>,
+    pub granted: DateTime<Local>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires: Option<DateTime<Local>>,
+}
+
+impl ApprovalGrant {
+    fn is_expired(&self) -> bool {
+        self.expires.is_some_and(|expires| expires <= Local::now())
+    }
+
+    /// Whether the grant covers runs in the current directory and `session`.
+    fn applies(&self, session: Option<&str>) -> bool {
+        match self.scope {
+            ApprovalScope::Run => false,
+            ApprovalScope::Session => session.is_some() && self.target.as_deref() == session,
+            ApprovalScope::Project => {
+                current_project().is_some_and(|project| self.target.as_deref() == Some(&project))
+            }
+            ApprovalScope::Global => true,
+        }
+    }
+}
+
 #[derive(Debug, PartialEq)]
 pub enum ApprovalResponse {
     Yes,
     No,
     AutoApprove,
+    Edit,
+    Deny,
+    Explain,
 }
 
-pub fn initialize_from_config(tools: &[String]) {
+/// What approved or refused a tool call.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ApprovalSource {
+    /// The user, at the approval prompt.
+    Prompt,
+    /// A stored approval or the config's `autoApprovedTools`.
+    Rule,
+    /// Decided without asking, e.g. a call rejected for invalid arguments.
+    Auto,
+}
+
+/// What the user decided about a tool call.
+pub enum Decision {
+    /// Run the call with this text: the original or the user's edit.
+    Approve(String, ApprovalSource),
+    /// Don't run the call. The feedback, if any, is passed on to the model.
+    Deny(Option<String>),
+}
+
+/// A tool call waiting for approval.
+pub struct ApprovalRequest<'a> {
+    /// The name auto-approvals are stored under. `None` for commands, which are
+    /// approved by their text.
+    pub tool_name: Option<&'a str>,
+    /// What the user can edit before the call runs: the command or the JSON arguments.
+    pub text: String,
+    /// The file the text is edited in; editors pick the highlighting from its extension.
+    pub file_name: &'a str,
+    /// Shows the call for the current text.
+    pub render: &'a dyn Fn(&str) -> String,
+    /// Checks edited text, returning the problem if it can't be used.
+    pub validate: &'a dyn Fn(&str) -> Result<(), String>,
+    /// Why running the text is dangerous. Dangerous calls are never auto-approved.
+    pub dangers: &'a dyn Fn(&str) -> Vec<String>,
+}
+
+/// Loads the approvals that apply to this run: the config's `autoApprovedTools` and the
+/// stored grants for `session`, the current project and everywhere.
+pub fn initialize(config: &AskConfig, session: Option<&str>) {
+    let expiry =
+        config
+            .approval_expiry
+            .as_deref()
+            .and_then(|age| match crate::sessions::parse_age(age) {
+                Ok(age) => Some(age),
+                Err(e) => {
+                    eprintln!("Warning: Ignoring approvalExpiry: {e}");
+                    None
+                }
+            });
+    *CONTEXT.lock().unwrap() = ApprovalContext {
+        session: session.map(str::to_string),
+        expiry,
+    };
+
     let mut approved = AUTO_APPROVED_TOOLS.lock().unwrap();
-    for tool in tools {
+    for tool in &config.auto_approved_tools {
         approved.insert(tool.clone());
     }
+    match load_grants() {
+        Ok(grants) => {
+            for grant in grants.into_iter().filter(|grant| grant.applies(session)) {
+                approved.insert(grant.tool);
+            }
+        }
+        Err(e) => eprintln!("Warning: Failed to load approvals: {e:#}"),
+    }
 }
 
 pub fn is_auto_approved(tool_name: &str) -> bool {
     AUTO_APPROVED_TOOLS.lock().unwrap().contains(tool_name)
 }
 
-fn add_to_session_auto_approved(tool_name: &str) {
+fn add_to_run_auto_approved(tool_name: &str) {
     AUTO_APPROVED_TOOLS
         .lock()
         .unwrap()
         .insert(tool_name.to_string());
 }
 
-fn prompt_user_approval(prompt_message: &str, tool_name: &str) -> ApprovalResponse {
-    print!("{}\nExecute '{}'? [y/N/A]: ", prompt_message, tool_name);
+/// The git repository containing the current directory, or the directory itself.
+fn current_project() -> Option<String> {
+    let cwd = std::env::current_dir().ok()?;
+    let project = find_git_root(&cwd).unwrap_or(cwd);
+    Some(project.display().to_string())
+}
+
+fn approvals_path() -> Result<PathBuf> {
+    shellexpand::tilde("~/.ask/approvals.json")
+        .into_owned()
+        .parse()
+        .context("Failed to parse approvals file path")
+}
+
+/// All stored grants that haven't expired.
+pub fn load_grants() -> Result<Vec<ApprovalGrant>> {
+    let path = approvals_path()?;
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    let _lock = lock_file(&path, false)?;
+    read_grants(&path)
+}
+
+fn read_grants(path: &Path) -> Result<Vec<ApprovalGrant>> {
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    let json =
+        std::fs::read_to_string(path).context(format!("Failed to read approvals {:?}", path))?;
+    let grants: Vec<ApprovalGrant> =
+        serde_json::from_str(&json).context(format!("Failed to parse approvals {:?}", path))?;
+    Ok(grants
+        .into_iter()
+        .filter(|grant| !grant.is_expired())
+        .collect())
+}
+
+/// Replaces the stored grants with `change(grants)`, holding the lock throughout so
+/// concurrent runs don't lose each other's changes. Returns what `change` returned.
+fn update_grants<T>(change: impl FnOnce(&mut Vec<ApprovalGrant>) -> T) -> Result<T> {
+    let path = approvals_path()?;
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir).context(format!("Failed to create directory {:?}", dir))?;
+    }
+    let _lock = lock_file(&path, true)?;
+    let mut grants = read_grants(&path)?;
+    let result = change(&mut grants);
+    let json = serde_json::to_string_pretty(&grants)?;
+    write_atomic(&path, &json).context(format!("Failed to write approvals {:?}", path))?;
+    Ok(result)
+}
+
+/// Stores an approval of `tool` for `scope`, replacing an earlier one for the same
+/// session or project. `session` is required for session grants.
+pub fn grant(
+    tool: &str,
+    scope: ApprovalScope,
+    session: Option<&str>,
+    expires_in: Option<chrono::Duration>,
+) -> Result<ApprovalGrant> {
+    let target = match scope {
+        ApprovalScope::Run => bail!("Run approvals are not stored"),
+        ApprovalScope::Session => Some(
+            session
+                .context("Session approvals need a session")?
+                .to_string(),
+        ),
+        ApprovalScope::Project => {
+            Some(current_project().context("Failed to get the current directory")?)
+        }
+        ApprovalScope::Global => None,
+    };
+    let now = Local::now();
+    let grant = ApprovalGrant {
+        tool: tool.to_string(),
+        scope,
+        target,
+        granted: now,
+        expires: expires_in.map(|age| now + age),
+    };
+
+    update_grants(|grants| {
+        grants.retain(|existing| {
+            existing.tool != grant.tool
+                || existing.scope != grant.scope
+                || existing.target != grant.target
+        });
+        grants.push(grant.clone());
+    })?;
+    Ok(grant)
+}
+
+/// Removes the approvals of `tool` that apply here, limited to `scope` if given, or in
+/// every session and project with `everywhere`. Global approvals include the config's
+/// `autoApprovedTools`. Returns how many were removed.
+pub fn revoke(
+    tool: &str,
+    scope: Option<ApprovalScope>,
+    session: Option<&str>,
+    everywhere: bool,
+) -> Result<usize> {
+    let mut count = update_grants(|grants| {
+        let before = grants.len();
+        grants.retain(|grant| {
+            grant.tool != tool
+                || scope.is_some_and(|scope| grant.scope != scope)
+                || !(everywhere || grant.applies(session))
+        });
+        before - grants.len()
+    })?;
+
+    if scope.is_none_or(|scope| scope == ApprovalScope::Global)
+        && let Ok(config) = config::load_config()
+        && config
+            .auto_approved_tools
+            .iter()
+            .any(|approved| approved == tool)
+    {
+        config::remove_auto_approved_tool(tool)?;
+        count += 1;
+    }
+    Ok(count)
+}
+
+/// Asks how far an "always" approval should reach. Session scope is only offered when
+/// the run belongs to a named session.
+fn prompt_scope(tool_name: &str) -> ApprovalScope {
+    let has_session = CONTEXT.lock().unwrap().session.is_some();
+    let options = if has_session {
+        "[r]est of this run / [s]ession / [p]roject / [g]lobally"
+    } else {
+        "[r]est of this run / [p]roject / [g]lobally"
+    };
+    let answer = prompt_line(&format!("Approve '{tool_name}' for {options}? [r]: ")).to_lowercase();
+    // Anything but an explicit wider scope, including no answer, stores nothing.
+    match answer.as_str() {
+        "s" | "session" if has_session => ApprovalScope::Session,
+        "p" | "project" => ApprovalScope::Project,
+        "g" | "global" | "globally" => ApprovalScope::Global,
+        _ => ApprovalScope::Run,
+    }
+}
+
+/// Approves `tool_name` for the rest of the run and stores the approval for `scope`.
+fn approve_always(tool_name: &str, scope: ApprovalScope, verbose: bool) {
+    add_to_run_auto_approved(tool_name);
+    if scope == ApprovalScope::Run {
+        if verbose {
+            println!("All future '{tool_name}' calls in this run will be auto-approved.");
+        }
+        return;
+    }
+
+    let (session, expiry) = {
+        let context = CONTEXT.lock().unwrap();
+        (context.session.clone(), context.expiry)
+    };
+    match grant(tool_name, scope, session.as_deref(), expiry) {
+        Ok(grant) if verbose => {
+            let until = grant
+                .expires
+                .map(|expires| format!(" until {}", expires.format("%Y-%m-%d %H:%M")))
+                .unwrap_or_default();
+            println!(
+                "All future '{tool_name}' calls will be auto-approved for this {scope}{until}."
+            );
+        }
+        Ok(_) => {}
+        Err(e) => {
+            eprintln!("Warning: Failed to save approval: {e:#}");
+            println!("All future '{tool_name}' calls in this run will be auto-approved.");
+        }
+    }
+}
+
+fn prompt_user_approval(question: &str) -> ApprovalResponse {
+    let trimmed = prompt_line(&format!(
+        "{question} [y]es / [N]o / [a]lways / [e]dit / [d]eny with feedback / e[x]plain: "
+    ))
+    .to_lowercase();
+    match trimmed.as_str() {
+        "y" | "yes" => ApprovalResponse::Yes,
+        "a" | "all" | "always" => ApprovalResponse::AutoApprove,
+        "e" | "edit" => ApprovalResponse::Edit,
+        "d" | "deny" => ApprovalResponse::Deny,
+        "x" | "explain" => ApprovalResponse::Explain,
+        _ => ApprovalResponse::No,
+    }
+}
+
+/// Prints `prompt` and returns the trimmed line the user typed.
+pub fn prompt_line(prompt: &str) -> String {
+    print!("{}", prompt);
 
     if let Err(e) = std::io::stdout().flush() {
         eprintln!("Warning: Failed to flush stdout: {}", e);
@@ -41,49 +372,189 @@ fn prompt_user_approval(prompt_message: &str, tool_name: &str) -> ApprovalRespon
     let mut input = String::new();
     if let Err(e) = std::io::stdin().read_line(&mut input) {
         eprintln!("Error: Failed to read user input: {}", e);
-        return ApprovalResponse::No;
     }
+    input.trim().to_string()
+}
 
-    let trimmed = input.trim().to_lowercase();
-    match trimmed.as_str() {
-        "y" | "yes" => ApprovalResponse::Yes,
-        "a" | "all" => ApprovalResponse::AutoApprove,
-        _ => ApprovalResponse::No,
-    }
+/// Asks a yes/no question, defaulting to no.
+pub fn confirm(prompt_message: &str) -> bool {
+    let answer = prompt_line(&format!("{} [y/N]: ", prompt_message)).to_lowercase();
+    answer == "y" || answer == "yes"
 }
 
-pub fn check_approval(tool_name: &str, prompt_message: &str, verbose: bool) -> bool {
-    if is_auto_approved(tool_name) {
+/// Shows the call and asks whether to run it, unless it is auto-approved. The user can
+/// edit the call first, deny it with feedback for the model or have `explain` ask the
+/// model why it wants to make it.
+pub fn check_approval(
+    request: ApprovalRequest,
+    explain: &mut dyn FnMut(&str) -> anyhow::Result<String>,
+    verbose: bool,
+) -> Decision {
+    let mut text = request.text;
+    let key = |text: &str| request.tool_name.unwrap_or(text).to_string();
+    let mut dangers = (request.dangers)(&text);
+
+    if is_auto_approved(&key(&text)) && dangers.is_empty() {
         if verbose {
-            println!("{}\n[Auto-approved]", prompt_message);
+            println!("{}\n[Auto-approved]", (request.render)(&text));
         } else {
-            println!("{}", prompt_message);
+            println!("{}", (request.render)(&text));
+        }
+        return Decision::Approve(text, ApprovalSource::Rule);
+    }
+
+    let question = match request.tool_name {
+        Some(tool_name) => format!("Execute '{tool_name}'?"),
+        None => "Run this command?".to_string(),
+    };
+    let mut edited = false;
+    loop {
+        println!("{}", (request.render)(&text));
+        if edited {
+            println!("\x1b[2m(edited)\x1b[0m");
         }
-        return true;
-    }
-
-    match prompt_user_approval(prompt_message, tool_name) {
-        ApprovalResponse::Yes => true,
-        ApprovalResponse::No => false,
-        ApprovalResponse::AutoApprove => {
-            add_to_session_auto_approved(tool_name);
-
-            if let Err(e) = config::add_auto_approved_tool(tool_name) {
-                if verbose {
-                    eprintln!("Warning: Failed to save auto-approval to config: {}", e);
-                    println!(
-                        "All future '{}' calls will be auto-approved for this session only.",
-                        tool_name
-                    );
+        if !dangers.is_empty() {
+            println!("{}", command_risk::warning(&dangers));
+        }
+
+        match prompt_user_approval(&question) {
+            ApprovalResponse::Yes => return Decision::Approve(text, ApprovalSource::Prompt),
+            ApprovalResponse::No => return Decision::Deny(None),
+            ApprovalResponse::AutoApprove if !dangers.is_empty() => {
+                println!("Dangerous calls are never auto-approved; approving this one only.");
+                return Decision::Approve(text, ApprovalSource::Prompt);
+            }
+            ApprovalResponse::AutoApprove => {
+                let tool_name = key(&text);
+                approve_always(&tool_name, prompt_scope(&tool_name), verbose);
+                return Decision::Approve(text, ApprovalSource::Prompt);
+            }
+            ApprovalResponse::Edit => match edit_text(&text, request.file_name) {
+                Ok(new_text) => {
+                    let new_text = new_text.trim().to_string();
+                    match (request.validate)(&new_text) {
+                        Ok(()) => {
+                            edited = new_text != text || edited;
+                            text = new_text;
+                            dangers = (request.dangers)(&text);
+                        }
+                        Err(problem) => {
+                            eprintln!("Error: {problem}. Keeping the previous version.")
+                        }
+                    }
                 }
-            } else if verbose {
-                println!(
-                    "All future '{}' calls will be auto-approved (saved to config).",
-                    tool_name
-                );
+                Err(e) => eprintln!("Error: {:#}", e),
+            },
+            ApprovalResponse::Deny => {
+                let feedback = prompt_line("Feedback for the model: ");
+                return Decision::Deny(Some(feedback).filter(|feedback| !feedback.is_empty()));
             }
+            ApprovalResponse::Explain => {
+                println!("\x1b[2mAsking the model why...\x1b[0m");
+                match explain(&text) {
+                    Ok(explanation) => println!("\n{}\n", explanation.trim()),
+                    Err(e) => eprintln!("Error: Failed to get an explanation: {:#}", e),
+                }
+            }
+        }
+    }
+}
+
+/// Colours a shell command: command names, options, strings, variables, operators and
+/// comments.
+pub fn highlight_command(command: &str) -> String {
+    const RESET: &str = "\x1b[0m";
+    let chars: Vec<char> = command.chars().collect();
+    let mut output = String::new();
+    let mut command_position = true;
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let start = i;
+        if c.is_whitespace() {
+            output.push(c);
+            if c == '\n' {
+                command_position = true;
+            }
+            i += 1;
+            continue;
+        }
 
-            true
+        let (color, is_word) = match c {
+            '#' if start == 0 || chars[start - 1].is_whitespace() => {
+                i = chars[i..]
+                    .iter()
+                    .position(|&c| c == '\n')
+                    .map_or(chars.len(), |end| i + end);
+                ("\x1b[2m", false)
+            }
+            '\'' | '"' => {
+                i += 1;
+                while i < chars.len() && chars[i] != c {
+                    if c == '"' && chars[i] == '\\' {
+                        i += 1;
+                    }
+                    i += 1;
+                }
+                i = (i + 1).min(chars.len());
+                ("\x1b[33m", true)
+            }
+            '$' => {
+                i += 1;
+                if i < chars.len() && chars[i] == '{' {
+                    i = chars[i..]
+                        .iter()
+                        .position(|&c| c == '}')
+                        .map_or(chars.len(), |end| i + end + 1);
+                } else if i < chars.len() && chars[i] == '(' {
+                    i += 1;
+                    command_position = true;
+                } else {
+                    while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+                        i += 1;
+                    }
+                }
+                ("\x1b[36m", false)
+            }
+            '|' | '&' | ';' | '(' | ')' | '<' | '>' | '`' => {
+                while i < chars.len() && "|&;<>".contains(chars[i]) && i - start < 3 {
+                    i += 1;
+                }
+                i = i.max(start + 1);
+                if c != '<' && c != '>' {
+                    command_position = true;
+                }
+                ("\x1b[35m", false)
+            }
+            _ => {
+                while i < chars.len()
+                    && !chars[i].is_whitespace()
+                    && !"|&;()<>`'\"$".contains(chars[i])
+                {
+                    i += 1;
+                }
+                let word: String = chars[start..i].iter().collect();
+                let color = if command_position && !word.contains('=') {
+                    "\x1b[1m\x1b[32m"
+                } else if word.starts_with('-') {
+                    "\x1b[34m"
+                } else {
+                    ""
+                };
+                (color, true)
+            }
+        };
+
+        let token: String = chars[start..i].iter().collect();
+        if color.is_empty() {
+            output.push_str(&token);
+        } else {
+            output.push_str(&format!("{color}{token}{RESET}"));
+        }
+        // `NAME=value` prefixes keep the next word in command position.
+        if is_word && !token.contains('=') {
+            command_position = false;
         }
     }
+    output
 }
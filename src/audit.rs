@@ -0,0 +1,203 @@
+use crate::approval::ApprovalSource;
+use crate::config::AuditLogSettings;
+use crate::git::find_git_root;
+use anyhow::{Context, Result};
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+const DEFAULT_AUDIT_LOG: &str = "~/.ask/audit.log";
+
+/// Longer outputs are cut off in the log.
+const MAX_OUTPUT_CHARS: usize = 2000;
+
+/// What happened to a tool call.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditDecision {
+    Approved,
+    Denied,
+    /// Refused before approval: an unknown tool or invalid arguments.
+    Rejected,
+    /// Recorded by `--dry-run` without running.
+    DryRun,
+}
+
+/// A tool call as it is filled in while it runs.
+pub struct CallRecord {
+    pub tool: String,
+    /// The arguments the call ran with, after validation and edits.
+    pub arguments: String,
+    pub decision: AuditDecision,
+    pub source: ApprovalSource,
+    /// The command's exit code, for `execute_command`.
+    pub exit_status: Option<i32>,
+    /// The call failed to run or its server returned an error.
+    pub error: bool,
+    /// How long the call ran, not counting approval.
+    pub duration: Option<Duration>,
+    /// Why the command was classified as dangerous.
+    pub dangers: Vec<String>,
+}
+
+impl CallRecord {
+    pub fn new(tool: &str, arguments: &str) -> Self {
+        CallRecord {
+            tool: tool.to_string(),
+            arguments: arguments.to_string(),
+            decision: AuditDecision::Rejected,
+            source: ApprovalSource::Auto,
+            exit_status: None,
+            error: false,
+            duration: None,
+            dangers: Vec::new(),
+        }
+    }
+}
+
+/// One line of the audit log.
+#[derive(Serialize, Deserialize)]
+pub struct AuditEntry {
+    pub timestamp: DateTime<Local>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub session: Option<String>,
+    pub model: String,
+    pub cwd: String,
+    pub tool: String,
+    pub arguments: Value,
+    pub decision: AuditDecision,
+    pub source: ApprovalSource,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub exit_status: Option<i32>,
+    #[serde(default)]
+    pub error: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub duration_ms: Option<u64>,
+    #[serde(default)]
+    pub output: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub dangers: Vec<String>,
+}
+
+/// Appends every tool call of a run to the audit log, and to the project's log if
+/// `projectPath` is set.
+pub struct AuditLog {
+    paths: Vec<PathBuf>,
+    session: Option<String>,
+    model: String,
+    cwd: String,
+}
+
+impl AuditLog {
+    /// `None` when the audit log is disabled.
+    pub fn new(
+        settings: Option<&AuditLogSettings>,
+        session: Option<String>,
+        model: String,
+    ) -> Option<Self> {
+        if settings.and_then(|settings| settings.enabled) == Some(false) {
+            return None;
+        }
+        let mut paths = vec![log_path(settings)];
+        paths.extend(project_log_path(settings));
+        Some(AuditLog {
+            paths,
+            session,
+            model,
+            cwd: std::env::current_dir()
+                .map(|cwd| cwd.display().to_string())
+                .unwrap_or_default(),
+        })
+    }
+
+    pub fn record(&self, call: &CallRecord, output: &str) {
+        let entry = AuditEntry {
+            timestamp: Local::now(),
+            session: self.session.clone(),
+            model: self.model.clone(),
+            cwd: self.cwd.clone(),
+            tool: call.tool.clone(),
+            arguments: serde_json::from_str(&call.arguments)
+                .unwrap_or_else(|_| Value::String(call.arguments.clone())),
+            decision: call.decision,
+            source: call.source,
+            exit_status: call.exit_status,
+            error: call.error,
+            duration_ms: call.duration.map(|duration| duration.as_millis() as u64),
+            output: truncate(output),
+            dangers: call.dangers.clone(),
+        };
+        let line = match serde_json::to_string(&entry) {
+            Ok(line) => line,
+            Err(e) => {
+                eprintln!("Warning: Failed to write audit log: {e}");
+                return;
+            }
+        };
+        for path in &self.paths {
+            if let Err(e) = append_line(path, &line) {
+                eprintln!("Warning: Failed to write audit log: {e:#}");
+            }
+        }
+    }
+}
+
+/// The audit log: `path` from the settings, or `~/.ask/audit.log`.
+pub fn log_path(settings: Option<&AuditLogSettings>) -> PathBuf {
+    let path = settings
+        .and_then(|settings| settings.path.as_deref())
+        .unwrap_or(DEFAULT_AUDIT_LOG);
+    PathBuf::from(shellexpand::tilde(path).into_owned())
+}
+
+/// The current project's audit log, `projectPath` relative to the git repository or the
+/// current directory.
+pub fn project_log_path(settings: Option<&AuditLogSettings>) -> Option<PathBuf> {
+    let project_path = settings?.project_path.as_deref()?;
+    let cwd = std::env::current_dir().ok()?;
+    let root = find_git_root(&cwd).unwrap_or(cwd);
+    Some(root.join(project_path))
+}
+
+fn append_line(path: &Path, line: &str) -> Result<()> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir).context(format!("Failed to create directory {:?}", dir))?;
+    }
+    let mut file = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .context(format!("Failed to open {:?}", path))?;
+    file.write_all(format!("{line}\n").as_bytes())
+        .context(format!("Failed to write {:?}", path))
+}
+
+/// The lines of the audit log at `path`, oldest first. A missing log has none.
+pub fn read_lines(path: &Path) -> Result<Vec<String>> {
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    let text =
+        std::fs::read_to_string(path).context(format!("Failed to read audit log {:?}", path))?;
+    Ok(text
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(str::to_string)
+        .collect())
+}
+
+fn truncate(output: &str) -> String {
+    let count = output.chars().count();
+    if count <= MAX_OUTPUT_CHARS {
+        output.to_string()
+    } else {
+        format!(
+            "{}… ({} more characters)",
+            output.chars().take(MAX_OUTPUT_CHARS).collect::<String>(),
+            count - MAX_OUTPUT_CHARS
+        )
+    }
+}
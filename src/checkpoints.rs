@@ -0,0 +1,556 @@
+use anyhow::{Context, Result, bail};
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// Older checkpoints are deleted when a new one is saved.
+const MAX_CHECKPOINTS: usize = 50;
+
+/// Directories never scanned for changes made by shell commands.
+pub const SKIPPED_DIRS: [&str; 6] = [
+    ".git",
+    "target",
+    "node_modules",
+    ".venv",
+    "__pycache__",
+    ".ask",
+];
+
+/// Shell commands aren't tracked in directories with more entries than this.
+const MAX_SCAN_ENTRIES: usize = 20_000;
+
+/// Files larger than this are recorded as changed but not backed up.
+const MAX_BACKUP_FILE_BYTES: u64 = 1024 * 1024;
+
+/// Total size of the copies kept for one run.
+const MAX_BACKUP_BYTES: u64 = 64 * 1024 * 1024;
+
+/// The state of one path before the run first changed it.
+#[derive(Serialize, Deserialize)]
+pub struct CheckpointEntry {
+    pub path: PathBuf,
+    /// The tool call that changed it: a tool name or the command.
+    pub changed_by: String,
+    pub existed: bool,
+    #[serde(default)]
+    pub is_dir: bool,
+    /// Name of the copy in the checkpoint's `files` directory. Missing for directories,
+    /// new paths and files too large to back up.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub backup: Option<String>,
+    /// The path's state when the run finished, or after it was last restored. Missing
+    /// when the run didn't finish.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub after_run: Option<PathState>,
+}
+
+impl CheckpointEntry {
+    /// Whether something other than the run changed the path since, so restoring it
+    /// would discard that change.
+    pub fn changed_since_run(&self) -> bool {
+        self.after_run
+            .as_ref()
+            .is_some_and(|state| *state != PathState::of(&self.path))
+    }
+}
+
+#[derive(Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum PathState {
+    Missing,
+    Present(FileState),
+}
+
+impl PathState {
+    fn of(path: &Path) -> Self {
+        match fs::metadata(path) {
+            Ok(metadata) => PathState::Present(FileState::of(&metadata)),
+            Err(_) => PathState::Missing,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Manifest {
+    pub run_id: String,
+    pub created: DateTime<Local>,
+    pub cwd: PathBuf,
+    pub question: String,
+    pub entries: Vec<CheckpointEntry>,
+    #[serde(default)]
+    pub undone: bool,
+}
+
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct FileState {
+    modified: Option<SystemTime>,
+    len: u64,
+    is_dir: bool,
+}
+
+impl FileState {
+    fn of(metadata: &fs::Metadata) -> Self {
+        FileState {
+            // A directory's own time changes with its contents; only its existence
+            // matters.
+            modified: if metadata.is_dir() {
+                None
+            } else {
+                metadata.modified().ok()
+            },
+            len: if metadata.is_dir() { 0 } else { metadata.len() },
+            is_dir: metadata.is_dir(),
+        }
+    }
+}
+
+/// Files and directories under the scanned directory with their modification times.
+pub struct Scan(HashMap<PathBuf, FileState>);
+
+struct StoredCopy {
+    state: FileState,
+    file: String,
+}
+
+/// Records the original contents of everything a run changes, so `ask undo` can put
+/// it back. Nothing is written until the first change.
+pub struct Checkpoint {
+    manifest: Manifest,
+    dir: PathBuf,
+    /// Paths with an entry; only the state before the first change is kept.
+    recorded: HashSet<PathBuf>,
+    /// Copies taken before shell commands, by path.
+    store: HashMap<PathBuf, StoredCopy>,
+    /// Copies an entry points to.
+    referenced: HashSet<String>,
+    next_file: usize,
+    stored_bytes: u64,
+    /// Cleared when command tracking is off or the directory is too large to scan
+    /// before every command.
+    track_commands: bool,
+    /// Set once a file wasn't copied because the run's budget was used up.
+    budget_exhausted: bool,
+}
+
+fn checkpoints_dir() -> Result<PathBuf> {
+    shellexpand::tilde("~/.ask/checkpoints")
+        .into_owned()
+        .parse()
+        .context("Failed to parse checkpoint directory path")
+}
+
+impl Checkpoint {
+    /// `track_commands` enables `before_command`, which copies the current directory's
+    /// files so changes made by shell commands can be undone.
+    pub fn new(question: &str, track_commands: bool) -> Result<Self> {
+        let run_id = format!(
+            "{}-{}",
+            Local::now().format("%Y%m%d-%H%M%S"),
+            std::process::id()
+        );
+        let cwd = std::env::current_dir().context("Failed to get current directory")?;
+        Ok(Checkpoint {
+            dir: checkpoints_dir()?.join(&run_id),
+            manifest: Manifest {
+                run_id,
+                created: Local::now(),
+                cwd,
+                question: question.trim().to_string(),
+                entries: Vec::new(),
+                undone: false,
+            },
+            recorded: HashSet::new(),
+            store: HashMap::new(),
+            referenced: HashSet::new(),
+            next_file: 0,
+            stored_bytes: 0,
+            track_commands,
+            budget_exhausted: false,
+        })
+    }
+
+    /// Snapshots the paths a filesystem MCP tool is about to change. Unknown tools are
+    /// ignored.
+    pub fn before_file_tool(&mut self, tool: &str, tool_name: &str, arguments: &str) -> Result<()> {
+        let keys: &[&str] = match tool_name {
+            "write_file" | "edit_file" | "create_directory" => &["path"],
+            "move_file" => &["source", "destination"],
+            _ => return Ok(()),
+        };
+        let Ok(arguments) = serde_json::from_str::<serde_json::Value>(arguments) else {
+            return Ok(());
+        };
+
+        for key in keys {
+            if let Some(path) = arguments.get(*key).and_then(|value| value.as_str()) {
+                let path = shellexpand::tilde(path).into_owned();
+                self.snapshot_path(&self.manifest.cwd.join(path), tool)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Snapshots the absolute paths a built-in file tool is about to change.
+    pub fn before_paths(&mut self, tool: &str, paths: &[PathBuf]) -> Result<()> {
+        for path in paths {
+            self.snapshot_path(path, tool)?;
+        }
+        Ok(())
+    }
+
+    fn snapshot_path(&mut self, path: &Path, changed_by: &str) -> Result<()> {
+        if self.recorded.contains(path) {
+            return Ok(());
+        }
+
+        let entry = match fs::metadata(path) {
+            Ok(metadata) if metadata.is_dir() => CheckpointEntry {
+                path: path.to_path_buf(),
+                changed_by: changed_by.to_string(),
+                existed: true,
+                is_dir: true,
+                backup: None,
+                after_run: None,
+            },
+            Ok(metadata) => CheckpointEntry {
+                path: path.to_path_buf(),
+                changed_by: changed_by.to_string(),
+                existed: true,
+                is_dir: false,
+                backup: self.copy_file(path, metadata.len())?,
+                after_run: None,
+            },
+            Err(_) => CheckpointEntry {
+                path: path.to_path_buf(),
+                changed_by: changed_by.to_string(),
+                existed: false,
+                is_dir: false,
+                backup: None,
+                after_run: None,
+            },
+        };
+        self.add_entry(entry)
+    }
+
+    fn add_entry(&mut self, entry: CheckpointEntry) -> Result<()> {
+        if let Some(backup) = &entry.backup {
+            self.referenced.insert(backup.clone());
+        }
+        self.recorded.insert(entry.path.clone());
+        self.manifest.entries.push(entry);
+        self.write_manifest()
+    }
+
+    /// Copies `path` into the checkpoint, unless it is too large or the run's budget is
+    /// used up.
+    fn copy_file(&mut self, path: &Path, len: u64) -> Result<Option<String>> {
+        if len > MAX_BACKUP_FILE_BYTES {
+            return Ok(None);
+        }
+        if self.stored_bytes + len > MAX_BACKUP_BYTES {
+            self.budget_exhausted = true;
+            return Ok(None);
+        }
+
+        let files_dir = self.dir.join("files");
+        fs::create_dir_all(&files_dir)
+            .context(format!("Failed to create directory {:?}", files_dir))?;
+        let name = self.next_file.to_string();
+        self.next_file += 1;
+        fs::copy(path, files_dir.join(&name)).context(format!("Failed to back up {:?}", path))?;
+        self.stored_bytes += len;
+        Ok(Some(name))
+    }
+
+    /// Scans the directory the run started in and backs up files that changed since
+    /// the last command, so whatever the next command changes can be restored. Does
+    /// nothing unless command tracking is enabled.
+    pub fn before_command(&mut self) -> Option<Scan> {
+        if !self.track_commands {
+            return None;
+        }
+        let Some(scan) = scan(&self.manifest.cwd) else {
+            eprintln!(
+                "Warning: Changes made by commands are not tracked: {} has more than {MAX_SCAN_ENTRIES} files",
+                self.manifest.cwd.display()
+            );
+            self.track_commands = false;
+            return None;
+        };
+
+        for (path, state) in &scan.0 {
+            if state.is_dir
+                || self.recorded.contains(path)
+                || self
+                    .store
+                    .get(path)
+                    .is_some_and(|copy| copy.state == *state)
+            {
+                continue;
+            }
+            let Ok(Some(file)) = self.copy_file(path, state.len) else {
+                continue;
+            };
+            if let Some(old) = self.store.insert(
+                path.clone(),
+                StoredCopy {
+                    state: *state,
+                    file,
+                },
+            ) {
+                self.remove_copy(&old.file);
+            }
+        }
+        Some(scan)
+    }
+
+    /// Records everything `command` created, changed or deleted since `before`.
+    pub fn after_command(&mut self, before: Scan, command: &str) -> Result<()> {
+        let Some(after) = scan(&self.manifest.cwd) else {
+            return Ok(());
+        };
+
+        let mut changed: Vec<(&PathBuf, Option<&FileState>)> = before
+            .0
+            .iter()
+            .filter(|(path, state)| after.0.get(*path) != Some(state))
+            .map(|(path, state)| (path, Some(state)))
+            .collect();
+        changed.extend(
+            after
+                .0
+                .keys()
+                .filter(|path| !before.0.contains_key(*path))
+                .map(|path| (path, None)),
+        );
+        changed.sort_by(|a, b| a.0.cmp(b.0));
+
+        for (path, previous) in changed {
+            if self.recorded.contains(path) {
+                continue;
+            }
+            let entry = match previous {
+                Some(state) => {
+                    let backup = self
+                        .store
+                        .get(path)
+                        .filter(|copy| !state.is_dir && copy.state == *state)
+                        .map(|copy| copy.file.clone());
+                    if !state.is_dir && backup.is_none() {
+                        let reason = if state.len > MAX_BACKUP_FILE_BYTES {
+                            "it was too large to back up"
+                        } else if self.budget_exhausted {
+                            "the run's backup budget was exhausted"
+                        } else {
+                            "it wasn't backed up"
+                        };
+                        eprintln!("Warning: {} changed but {reason}", path.display());
+                    }
+                    CheckpointEntry {
+                        path: path.clone(),
+                        changed_by: command.to_string(),
+                        existed: true,
+                        is_dir: state.is_dir,
+                        backup,
+                        after_run: None,
+                    }
+                }
+                None => CheckpointEntry {
+                    path: path.clone(),
+                    changed_by: command.to_string(),
+                    existed: false,
+                    is_dir: after.0.get(path).is_some_and(|state| state.is_dir),
+                    backup: None,
+                    after_run: None,
+                },
+            };
+            self.add_entry(entry)?;
+        }
+        Ok(())
+    }
+
+    fn remove_copy(&mut self, file: &str) {
+        if self.referenced.contains(file) {
+            return;
+        }
+        let path = self.dir.join("files").join(file);
+        if let Ok(metadata) = fs::metadata(&path) {
+            self.stored_bytes = self.stored_bytes.saturating_sub(metadata.len());
+        }
+        let _ = fs::remove_file(path);
+    }
+
+    fn write_manifest(&self) -> Result<()> {
+        fs::create_dir_all(&self.dir)
+            .context(format!("Failed to create directory {:?}", self.dir))?;
+        let json = serde_json::to_string_pretty(&self.manifest)?;
+        fs::write(self.dir.join("manifest.json"), json)
+            .context("Failed to write checkpoint manifest")
+    }
+
+    /// Drops the copies no entry needs and records how the run left each path. Returns
+    /// the run id when the run changed anything.
+    pub fn finish(mut self) -> Option<String> {
+        let unreferenced: Vec<String> = self.store.drain().map(|(_, copy)| copy.file).collect();
+        for file in unreferenced {
+            self.remove_copy(&file);
+        }
+
+        if self.manifest.entries.is_empty() {
+            let _ = fs::remove_dir_all(&self.dir);
+            return None;
+        }
+        for entry in &mut self.manifest.entries {
+            entry.after_run = Some(PathState::of(&entry.path));
+        }
+        if let Err(e) = self.write_manifest() {
+            eprintln!("Warning: {e:#}");
+        }
+        if let Err(e) = prune_checkpoints() {
+            eprintln!("Warning: Failed to prune old checkpoints: {e}");
+        }
+        Some(self.manifest.run_id.clone())
+    }
+}
+
+/// Lists the entries under `root`, skipping build and VCS directories. Returns `None`
+/// when there are too many to scan before every command.
+fn scan(root: &Path) -> Option<Scan> {
+    let mut entries = HashMap::new();
+    let mut dirs = vec![root.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        let Ok(read_dir) = fs::read_dir(&dir) else {
+            continue;
+        };
+        for entry in read_dir.flatten() {
+            let Ok(metadata) = entry.metadata() else {
+                continue;
+            };
+            let path = entry.path();
+            if metadata.is_dir() {
+                if SKIPPED_DIRS.contains(&entry.file_name().to_string_lossy().as_ref()) {
+                    continue;
+                }
+                dirs.push(path.clone());
+            } else if !metadata.is_file() {
+                continue;
+            }
+
+            entries.insert(path, FileState::of(&metadata));
+            if entries.len() > MAX_SCAN_ENTRIES {
+                return None;
+            }
+        }
+    }
+    Some(Scan(entries))
+}
+
+/// All checkpoints, newest first.
+pub fn list_checkpoints() -> Result<Vec<Manifest>> {
+    let dir = checkpoints_dir()?;
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+
+    let mut manifests = Vec::new();
+    for entry in fs::read_dir(&dir).context(format!("Failed to read directory {:?}", dir))? {
+        let path = entry?.path().join("manifest.json");
+        let Ok(json) = fs::read_to_string(&path) else {
+            continue;
+        };
+        match serde_json::from_str::<Manifest>(&json) {
+            Ok(manifest) => manifests.push(manifest),
+            Err(e) => eprintln!("Warning: Failed to parse {:?}: {e}", path),
+        }
+    }
+    manifests.sort_by(|a, b| b.run_id.cmp(&a.run_id));
+    Ok(manifests)
+}
+
+fn prune_checkpoints() -> Result<()> {
+    let dir = checkpoints_dir()?;
+    for manifest in list_checkpoints()?.into_iter().skip(MAX_CHECKPOINTS) {
+        fs::remove_dir_all(dir.join(&manifest.run_id))
+            .context(format!("Failed to delete checkpoint {}", manifest.run_id))?;
+    }
+    Ok(())
+}
+
+/// The checkpoint `run_id`, or the newest one that hasn't been undone.
+pub fn find_checkpoint(run_id: Option<&str>) -> Result<Manifest> {
+    let checkpoints = list_checkpoints()?;
+    match run_id {
+        Some(run_id) => checkpoints
+            .into_iter()
+            .find(|manifest| manifest.run_id == run_id)
+            .context(format!("Checkpoint '{run_id}' not found")),
+        None => checkpoints
+            .into_iter()
+            .find(|manifest| !manifest.undone)
+            .context("No checkpoint to undo"),
+    }
+}
+
+/// Puts every path of the checkpoint back the way it was before the run. Paths changed
+/// since the run are left alone unless `overwrite_changed` is set. Returns the paths
+/// that were not restored; the checkpoint only counts as undone when there are none.
+pub fn restore_checkpoint(manifest: &mut Manifest, overwrite_changed: bool) -> Result<Vec<String>> {
+    if manifest.undone {
+        bail!("Checkpoint '{}' was already undone", manifest.run_id);
+    }
+    let dir = checkpoints_dir()?.join(&manifest.run_id);
+    let mut problems = Vec::new();
+
+    // Files first, then new directories deepest first so they are empty by then.
+    let mut new_dirs: Vec<&mut CheckpointEntry> = Vec::new();
+    for entry in manifest.entries.iter_mut().rev() {
+        if !overwrite_changed && entry.changed_since_run() {
+            problems.push(format!(
+                "{}: changed since the run; left as it is",
+                entry.path.display()
+            ));
+            continue;
+        }
+        let path = &entry.path;
+        let result = match (entry.existed, entry.is_dir, &entry.backup) {
+            (false, true, _) => {
+                new_dirs.push(entry);
+                continue;
+            }
+            (false, false, _) => match fs::remove_file(path) {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.to_string()),
+                _ => Ok(()),
+            },
+            (true, true, _) => fs::create_dir_all(path).map_err(|e| e.to_string()),
+            (true, false, Some(backup)) => path
+                .parent()
+                .map_or(Ok(()), fs::create_dir_all)
+                .and_then(|_| fs::copy(dir.join("files").join(backup), path).map(|_| ()))
+                .map_err(|e| e.to_string()),
+            (true, false, None) => Err("it was too large to back up".to_string()),
+        };
+        match result {
+            // A later attempt then doesn't mistake the restored state for a new change.
+            Ok(()) => entry.after_run = Some(PathState::of(path)),
+            Err(e) => problems.push(format!("{}: {e}", path.display())),
+        }
+    }
+
+    new_dirs.sort_by_key(|entry| std::cmp::Reverse(entry.path.components().count()));
+    for entry in new_dirs {
+        match fs::remove_dir(&entry.path) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                problems.push(format!("{}: not removed: {e}", entry.path.display()));
+            }
+            _ => entry.after_run = Some(PathState::Missing),
+        }
+    }
+
+    manifest.undone = problems.is_empty();
+    let json = serde_json::to_string_pretty(&*manifest)?;
+    fs::write(dir.join("manifest.json"), json).context("Failed to write checkpoint manifest")?;
+    Ok(problems)
+}
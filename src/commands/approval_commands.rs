@@ -0,0 +1,100 @@
+use crate::approval::{self, ApprovalGrant, ApprovalScope};
+use crate::commands::{ApprovalCommands, GrantScope};
+use crate::config::load_config;
+use crate::sessions::parse_age;
+
+pub fn handle_approval_commands(command: ApprovalCommands) {
+    match command {
+        ApprovalCommands::List => {
+            let grants = match approval::load_grants() {
+                Ok(grants) => grants,
+                Err(e) => {
+                    eprintln!("Error: Failed to load approvals: {:#}", e);
+                    std::process::exit(1);
+                }
+            };
+            let config_tools = load_config()
+                .map(|config| config.auto_approved_tools)
+                .unwrap_or_default();
+            if grants.is_empty() && config_tools.is_empty() {
+                println!("No approvals");
+                return;
+            }
+
+            for grant in &grants {
+                print_grant(grant);
+            }
+            for tool in config_tools {
+                println!("{:<8} {:<40} (autoApprovedTools in config)", "global", tool);
+            }
+        }
+        ApprovalCommands::Grant {
+            tool,
+            scope,
+            session,
+            expires,
+        } => {
+            let expires_in = match expires
+                .or_else(|| load_config().ok().and_then(|config| config.approval_expiry))
+            {
+                Some(age) => match parse_age(&age) {
+                    Ok(age) => Some(age),
+                    Err(e) => {
+                        eprintln!("Error: Invalid expiry '{}': {}", age, e);
+                        std::process::exit(1);
+                    }
+                },
+                None => None,
+            };
+
+            match approval::grant(&tool, scope.into(), session.as_deref(), expires_in) {
+                Ok(grant) => {
+                    print!("Approved: ");
+                    print_grant(&grant);
+                }
+                Err(e) => {
+                    eprintln!("Error: Failed to save approval: {:#}", e);
+                    std::process::exit(1);
+                }
+            }
+        }
+        ApprovalCommands::Revoke {
+            tool,
+            scope,
+            session,
+            everywhere,
+        } => match approval::revoke(&tool, scope.map(Into::into), session.as_deref(), everywhere) {
+            Ok(0) => println!("No approvals of '{}' found", tool),
+            Ok(count) => println!("Revoked {} approval(s) of '{}'", count, tool),
+            Err(e) => {
+                eprintln!("Error: Failed to revoke approval: {:#}", e);
+                std::process::exit(1);
+            }
+        },
+    }
+}
+
+fn print_grant(grant: &ApprovalGrant) {
+    let expires = grant
+        .expires
+        .map(|expires| format!("until {}", expires.format("%Y-%m-%d %H:%M")))
+        .unwrap_or_else(|| "no expiry".to_string());
+    println!(
+        "{:<8} {:<40} {}  granted {}  {}",
+        grant.scope.to_string(),
+        grant.tool,
+        expires,
+        grant.granted.format("%Y-%m-%d %H:%M"),
+        grant.target.as_deref().unwrap_or("")
+    );
+}
+
+impl From<GrantScope> for ApprovalScope {
+    fn from(scope: GrantScope) -> Self {
+        match scope {
+            GrantScope::Session => ApprovalScope::Session,
+            GrantScope::Project => ApprovalScope::Project,
+            GrantScope::Global => ApprovalScope::Global,
+        }
+    }
+}
@@ -0,0 +1,173 @@
+use crate::audit::{AuditDecision, AuditEntry, log_path, project_log_path, read_lines};
+use crate::commands::{AuditCommands, AuditOutput};
+use crate::config::load_config;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+
+pub fn handle_audit_commands(command: AuditCommands) {
+    match command {
+        AuditCommands::Tail {
+            lines: count,
+            follow,
+            output,
+        } => {
+            let path = audit_path(&output);
+            let lines = read_or_exit(&path);
+            for line in &lines[lines.len().saturating_sub(count)..] {
+                print_line(line, output.json);
+            }
+            if follow {
+                follow_log(&path, output.json);
+            }
+        }
+        AuditCommands::Search {
+            query,
+            tool,
+            session,
+            limit,
+            output,
+        } => {
+            let path = audit_path(&output);
+            let query = query.to_lowercase();
+            let matches: Vec<String> = read_or_exit(&path)
+                .into_iter()
+                .filter(|line| {
+                    let Ok(entry) = serde_json::from_str::<AuditEntry>(line) else {
+                        return line.to_lowercase().contains(&query);
+                    };
+                    tool.as_ref().is_none_or(|tool| entry.tool == *tool)
+                        && session
+                            .as_ref()
+                            .is_none_or(|session| entry.session.as_ref() == Some(session))
+                        && (entry.tool.to_lowercase().contains(&query)
+                            || entry.arguments.to_string().to_lowercase().contains(&query)
+                            || entry.output.to_lowercase().contains(&query))
+                })
+                .collect();
+            if matches.is_empty() {
+                println!("No matching tool calls");
+                return;
+            }
+            for line in &matches[matches.len().saturating_sub(limit)..] {
+                print_line(line, output.json);
+            }
+        }
+    }
+}
+
+fn audit_path(output: &AuditOutput) -> PathBuf {
+    let config = load_config().ok();
+    let settings = config.as_ref().and_then(|config| config.audit_log.as_ref());
+    if !output.project {
+        return log_path(settings);
+    }
+    match project_log_path(settings) {
+        Some(path) => path,
+        None => {
+            eprintln!("Error: Set auditLog.projectPath in ~/.ask/config to keep a project log.");
+            std::process::exit(1);
+        }
+    }
+}
+
+fn read_or_exit(path: &Path) -> Vec<String> {
+    match read_lines(path) {
+        Ok(lines) => lines,
+        Err(e) => {
+            eprintln!("Error: {:#}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Prints the lines appended to the log until interrupted.
+fn follow_log(path: &Path, json: bool) {
+    let mut offset = std::fs::metadata(path).map(|m| m.len()).unwrap_or(0);
+    let mut partial = String::new();
+    loop {
+        std::thread::sleep(std::time::Duration::from_millis(500));
+        let Ok(mut file) = std::fs::File::open(path) else {
+            continue;
+        };
+        let len = file.metadata().map(|m| m.len()).unwrap_or(0);
+        if len < offset {
+            // The log was replaced; start over.
+            offset = 0;
+            partial.clear();
+        }
+        if len == offset || file.seek(SeekFrom::Start(offset)).is_err() {
+            continue;
+        }
+        let mut text = String::new();
+        if file.read_to_string(&mut text).is_err() {
+            continue;
+        }
+        offset = len;
+        partial.push_str(&text);
+        while let Some(end) = partial.find('\n') {
+            let line: String = partial.drain(..=end).collect();
+            if !line.trim().is_empty() {
+                print_line(line.trim_end(), json);
+            }
+        }
+    }
+}
+
+fn print_line(line: &str, json: bool) {
+    if json {
+        println!("{line}");
+        return;
+    }
+    let Ok(entry) = serde_json::from_str::<AuditEntry>(line) else {
+        println!("{line}");
+        return;
+    };
+
+    let decision = match entry.decision {
+        AuditDecision::Approved => "\x1b[32mapproved\x1b[0m",
+        AuditDecision::Denied => "\x1b[33mdenied\x1b[0m",
+        AuditDecision::Rejected => "\x1b[33mrejected\x1b[0m",
+        AuditDecision::DryRun => "\x1b[2mdry run\x1b[0m",
+    };
+    let source = serde_json::to_value(entry.source)
+        .ok()
+        .and_then(|value| value.as_str().map(str::to_string))
+        .unwrap_or_default();
+    let mut details = Vec::new();
+    if let Some(status) = entry.exit_status {
+        details.push(format!("exit {status}"));
+    }
+    if entry.error {
+        details.push("\x1b[31merror\x1b[0m".to_string());
+    }
+    if let Some(duration) = entry.duration_ms {
+        details.push(format!("{duration}ms"));
+    }
+    if let Some(session) = &entry.session {
+        details.push(format!("session {session}"));
+    }
+    println!(
+        "{} {decision} ({source}) \x1b[1m{}\x1b[0m {}",
+        entry.timestamp.format("%Y-%m-%d %H:%M:%S"),
+        entry.tool,
+        details.join(", ")
+    );
+
+    let call = match entry.arguments.get("command").and_then(|c| c.as_str()) {
+        Some(command) if entry.tool == "execute_command" => command.to_string(),
+        _ => entry.arguments.to_string(),
+    };
+    println!("    {}", preview(&call, 120));
+    if !entry.dangers.is_empty() {
+        println!("    \x1b[31mdangerous: {}\x1b[0m", entry.dangers.join("; "));
+    }
+}
+
+fn preview(text: &str, max_chars: usize) -> String {
+    let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    if text.chars().count() > max_chars {
+        format!("{}…", text.chars().take(max_chars - 1).collect::<String>())
+    } else {
+        text
+    }
+}
@@ -0,0 +1,110 @@
+use crate::approval::confirm;
+use crate::checkpoints::{CheckpointEntry, find_checkpoint, list_checkpoints, restore_checkpoint};
+use crate::commands::CheckpointCommands;
+
+pub fn handle_checkpoint_commands(command: CheckpointCommands) {
+    match command {
+        CheckpointCommands::List => {
+            let checkpoints = match list_checkpoints() {
+                Ok(checkpoints) => checkpoints,
+                Err(e) => {
+                    eprintln!("Error: Failed to list checkpoints: {:#}", e);
+                    std::process::exit(1);
+                }
+            };
+            if checkpoints.is_empty() {
+                println!("No checkpoints");
+                return;
+            }
+
+            for checkpoint in checkpoints {
+                let status = if checkpoint.undone { "undone" } else { "" };
+                println!(
+                    "{:<24} {} {:>4} paths {:<6}  {}",
+                    checkpoint.run_id,
+                    checkpoint.created.format("%Y-%m-%d %H:%M"),
+                    checkpoint.entries.len(),
+                    status,
+                    preview(&checkpoint.question)
+                );
+                println!("{:>25}in {}", "", checkpoint.cwd.display());
+            }
+        }
+    }
+}
+
+pub fn handle_undo_command(run_id: Option<String>, yes: bool) {
+    let mut checkpoint = match find_checkpoint(run_id.as_deref()) {
+        Ok(checkpoint) => checkpoint,
+        Err(e) => {
+            eprintln!("Error: {:#}", e);
+            std::process::exit(1);
+        }
+    };
+
+    println!(
+        "Checkpoint {} ({}): {}",
+        checkpoint.run_id,
+        checkpoint.created.format("%Y-%m-%d %H:%M"),
+        preview(&checkpoint.question)
+    );
+    let mut changed = 0;
+    for entry in &checkpoint.entries {
+        if entry.changed_since_run() {
+            changed += 1;
+            println!(
+                "  {} {} (changed since the run)",
+                action(entry),
+                entry.path.display()
+            );
+        } else {
+            println!("  {} {}", action(entry), entry.path.display());
+        }
+    }
+
+    if !yes && !confirm("Restore these paths to their state before the run?") {
+        return;
+    }
+    // Changes made after the run are only discarded when asked; `--yes` keeps them.
+    let overwrite_changed = changed > 0
+        && !yes
+        && confirm(&format!(
+            "{changed} path(s) changed since the run. Discard those changes as well?"
+        ));
+
+    match restore_checkpoint(&mut checkpoint, overwrite_changed) {
+        Ok(problems) if problems.is_empty() => {
+            println!("Restored {} path(s)", checkpoint.entries.len())
+        }
+        Ok(problems) => {
+            eprintln!("Error: Some paths could not be restored:");
+            for problem in problems {
+                eprintln!("  {problem}");
+            }
+            std::process::exit(1);
+        }
+        Err(e) => {
+            eprintln!("Error: {:#}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// What undoing does to the entry's path.
+fn action(entry: &CheckpointEntry) -> &'static str {
+    match (entry.existed, entry.is_dir, entry.backup.is_some()) {
+        (false, _, _) => "delete ",
+        (true, true, _) => "recreate",
+        (true, false, true) => "restore",
+        (true, false, false) => "skip   ",
+    }
+}
+
+fn preview(question: &str) -> String {
+    let question = question.split_whitespace().collect::<Vec<_>>().join(" ");
+    if question.chars().count() > 60 {
+        format!("{}…", question.chars().take(59).collect::<String>())
+    } else {
+        question
+    }
+}
@@ -1,4 +1,5 @@
-use clap::Subcommand;
+use clap::{Args, Subcommand, ValueEnum};
+use std::path::PathBuf;
 
 #[derive(Subcommand)]
 pub enum Commands {
@@ -28,6 +29,91 @@ pub enum Commands {
         command: Presets,
     },
 
+    /// Inspect the system prompt
+    Prompt {
+        #[command(subcommand)]
+        command: PromptCommands,
+    },
+
+    /// Generate a commit message for the staged changes and commit them
+    Commit {
+        /// Use the Conventional Commits format instead of matching recent commits
+        #[arg(long)]
+        conventional: bool,
+
+        /// Rewrite the message of the last commit, including any staged changes
+        #[arg(long)]
+        amend: bool,
+
+        /// The model to use
+        #[arg(short, long)]
+        model: Option<String>,
+
+        /// Extra instructions for the message, e.g. "mention issue #42"
+        #[arg(trailing_var_arg = true)]
+        instructions: Vec<String>,
+    },
+
+    /// Suggest a single shell command, then run, copy or edit it
+    Cmd {
+        /// Only print the command, for shell keybindings (see `ask shell-init`)
+        #[arg(long)]
+        print_only: bool,
+
+        /// The model to use
+        #[arg(short, long)]
+        model: Option<String>,
+
+        /// What the command should do
+        #[arg(trailing_var_arg = true)]
+        description: Vec<String>,
+    },
+
+    /// Print a Ctrl-G keybinding that replaces the command line with `ask cmd`'s suggestion
+    ShellInit {
+        #[arg(value_enum)]
+        shell: InitShell,
+    },
+
+    /// Lists past questions asked in this project (requires the `history` feature)
+    History {
+        /// Include runs from every directory
+        #[arg(long)]
+        all: bool,
+
+        /// Number of runs to show
+        #[arg(short = 'n', long, default_value_t = 20)]
+        limit: usize,
+    },
+
+    /// Files backed up before tool calls changed them
+    Checkpoint {
+        #[command(subcommand)]
+        command: CheckpointCommands,
+    },
+
+    /// Restore the files changed by a run, by default the latest one not undone yet
+    Undo {
+        /// The checkpoint to restore, as shown by `ask checkpoint list`
+        run_id: Option<String>,
+
+        /// Don't ask for confirmation
+        #[arg(short, long)]
+        yes: bool,
+    },
+
+    /// The log of every tool call
+    Audit {
+        #[command(subcommand)]
+        command: AuditCommands,
+    },
+
+    /// Tools and commands approved with "always"
+    Approval {
+        #[command(subcommand)]
+        command: ApprovalCommands,
+    },
+
     /// Initialize ~/.ask/config with default MCP servers
     Init,
 
@@ -69,17 +155,201 @@ pub enum McpCommands {
 
 #[derive(Subcommand)]
 pub enum SessionCommands {
-    /// List all sessions
+    /// List all sessions, most recent first
     List,
 
     /// Shows the conversation for a session
-    Show { name: Option<String> },
+    Show {
+        name: Option<String>,
+
+        /// Print the messages as markdown source, without rendering or boxes
+        #[arg(long)]
+        raw: bool,
+
+        /// Show tool calls and their results in full instead of one line each
+        #[arg(short, long)]
+        expand_tools: bool,
+    },
 
     /// Saves the last chat as a named session
     Save { name: String },
 
     /// Deletes a session
     Delete { name: String },
+
+    /// Searches the messages of all sessions
+    Search {
+        /// Text to search for (case-insensitive)
+        #[arg(required = true)]
+        text: Vec<String>,
+    },
+
+    /// Renames a session
+    Rename { name: String, new_name: String },
+
+    /// Copies a session under a new name
+    Copy { name: String, new_name: String },
+
+    /// Starts a new session from a copy of an existing one
+    Fork {
+        name: String,
+
+        /// Only keep the conversation up to and including this turn (see `session show`)
+        #[arg(long)]
+        at: Option<usize>,
+
+        /// Name of the new session
+        #[arg(long = "as")]
+        new_name: String,
+    },
+
+    /// Exports a session to stdout or a file
+    Export {
+        name: String,
+
+        #[arg(short, long, value_enum, default_value_t = ExportFormat::Markdown)]
+        format: ExportFormat,
+
+        /// Write to this file instead of stdout
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+
+    /// Imports a session exported as JSON
+    Import {
+        file: PathBuf,
+
+        /// Name of the imported session (defaults to the file name)
+        #[arg(short, long)]
+        name: Option<String>,
+    },
+
+    /// Deletes sessions that have not been used for a while
+    Prune {
+        /// Delete sessions last used longer ago than this (e.g. 12h, 30d, 2w)
+        #[arg(long)]
+        older_than: Option<String>,
+
+        /// Always keep this many of the most recent sessions
+        #[arg(long)]
+        keep: Option<usize>,
+
+        /// Only list the sessions that would be deleted
+        #[arg(long)]
+        dry_run: bool,
+    },
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum ExportFormat {
+    Markdown,
+    Json,
+    Html,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum InitShell {
+    Bash,
+    Zsh,
+    Fish,
+}
+
+#[derive(Subcommand)]
+pub enum CheckpointCommands {
+    /// List checkpoints, most recent first
+    List,
+}
+
+#[derive(Subcommand)]
+pub enum AuditCommands {
+    /// Show the most recent tool calls
+    Tail {
+        /// Number of calls to show
+        #[arg(short = 'n', long, default_value_t = 20)]
+        lines: usize,
+
+        /// Keep printing calls as they are logged
+        #[arg(short, long)]
+        follow: bool,
+
+        #[command(flatten)]
+        output: AuditOutput,
+    },
+    /// Show the tool calls whose tool, arguments or output contain a text
+    Search {
+        /// Case-insensitive text to look for
+        query: String,
+
+        /// Only calls of this tool
+        #[arg(long)]
+        tool: Option<String>,
+
+        /// Only calls from this session
+        #[arg(long)]
+        session: Option<String>,
+
+        /// Maximum number of calls to show, most recent last
+        #[arg(short = 'n', long, default_value_t = 50)]
+        limit: usize,
+
+        #[command(flatten)]
+        output: AuditOutput,
+    },
+}
+
+#[derive(Args)]
+pub struct AuditOutput {
+    /// Read the current project's log (auditLog.projectPath) instead of the global one
+    #[arg(long)]
+    pub project: bool,
+
+    /// Print the raw JSON lines
+    #[arg(long)]
+    pub json: bool,
+}
+
+#[derive(Subcommand)]
+pub enum ApprovalCommands {
+    /// List stored approvals and the config's autoApprovedTools
+    List,
+    /// Approve a tool, or an exact command for execute_command, without being asked
+    Grant {
+        tool: String,
+
+        #[arg(long, value_enum, default_value_t = GrantScope::Project)]
+        scope: GrantScope,
+
+        /// The session for session approvals
+        #[arg(long, required_if_eq("scope", "session"))]
+        session: Option<String>,
+
+        /// How long the approval lasts, e.g. 12h or 30d; overrides approvalExpiry
+        #[arg(long)]
+        expires: Option<String>,
+    },
+    /// Remove the approvals of a tool that apply in this directory
+    Revoke {
+        tool: String,
+
+        /// Only remove approvals of this scope
+        #[arg(long, value_enum)]
+        scope: Option<GrantScope>,
+
+        /// The session whose approvals to remove
+        #[arg(long)]
+        session: Option<String>,
+
+        /// Remove the approvals for every session and project
+        #[arg(long)]
+        everywhere: bool,
+    },
+}
+
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+pub enum GrantScope {
+    Session,
+    Project,
+    Global,
 }
 
 #[derive(Subcommand)]
@@ -108,6 +378,24 @@ pub enum BaseUrlCommands {
     SetOpenRouter,
 }
 
+#[derive(Subcommand)]
+pub enum PromptCommands {
+    /// Prints the effective system prompt
+    Show {
+        /// Include the system prompt of this preset
+        #[arg(short, long)]
+        preset: Option<String>,
+
+        /// Instructions to use instead of the built-in ones
+        #[arg(long, conflicts_with = "system_file")]
+        system: Option<String>,
+
+        /// Read the instructions from a file
+        #[arg(long)]
+        system_file: Option<PathBuf>,
+    },
+}
+
 #[derive(Subcommand)]
 pub enum Presets {
     Add { name: String, prompt: Vec<String> },
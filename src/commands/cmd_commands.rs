@@ -0,0 +1,292 @@
+use crate::approval::{confirm, prompt_line};
+use crate::commands::cli::InitShell;
+use crate::editor::edit_text;
+use crate::llms;
+use crate::shell::{CoreUtils, shell_info};
+use crate::tools::command_risk;
+use anyhow::{Context, Result, bail};
+use serde::Deserialize;
+use std::io::Write;
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+#[derive(Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+enum Risk {
+    Low,
+    Medium,
+    High,
+}
+
+#[derive(Deserialize)]
+struct Suggestion {
+    command: String,
+    explanation: String,
+    risk: Risk,
+    #[serde(default)]
+    risk_reason: String,
+}
+
+fn system_prompt() -> String {
+    let shell = shell_info();
+    let cwd = std::env::current_dir()
+        .map(|p| p.display().to_string())
+        .unwrap_or_else(|_| ".".to_string());
+    format!(
+        "You turn requests into a single shell command.\n\
+         The command is run with {syntax} on {os}, in {cwd}.{tools}\n\
+         Prefer one line; chain with pipes or && when needed. Never suggest more than one \
+         alternative.\n\
+         Reply with JSON only, no code fences:\n\
+         {{\"command\": \"...\", \"explanation\": \"one or two sentences\", \
+         \"risk\": \"low|medium|high\", \"risk_reason\": \"...\"}}\n\
+         risk is low for read-only commands, medium for commands that change files or \
+         settings in a recoverable way, and high for commands that delete data, need \
+         elevated privileges, rewrite history or affect the network or other machines.",
+        syntax = shell.command_syntax(),
+        os = shell.os,
+        tools = match shell.core_utils {
+            Some(CoreUtils::Gnu) => " GNU coreutils are installed.",
+            Some(CoreUtils::Bsd) => " The core utilities are the BSD variants.",
+            Some(CoreUtils::BusyBox) => " The core utilities are provided by BusyBox.",
+            None => "",
+        }
+    )
+}
+
+async fn suggest(description: &str, model: Option<String>, verbose: bool) -> Result<Suggestion> {
+    let answer = llms::complete(model, &system_prompt(), description, verbose).await?;
+
+    let json = match (answer.find('{'), answer.rfind('}')) {
+        (Some(start), Some(end)) if start < end => &answer[start..=end],
+        _ => bail!("The model did not reply with a command:\n{}", answer.trim()),
+    };
+    let suggestion: Suggestion = serde_json::from_str(json).context(format!(
+        "Failed to parse the model's reply:\n{}",
+        answer.trim()
+    ))?;
+    if suggestion.command.trim().is_empty() {
+        bail!("The model did not suggest a command");
+    }
+    Ok(suggestion)
+}
+
+fn describe(suggestion: &Suggestion) -> String {
+    let (color, label) = match suggestion.risk {
+        Risk::Low => ("\x1b[32m", "low"),
+        Risk::Medium => ("\x1b[33m", "medium"),
+        Risk::High => ("\x1b[1m\x1b[31m", "HIGH"),
+    };
+    let mut text = format!(
+        "\x1b[1m{}\x1b[0m\n\n{}\nRisk: {color}{label}\x1b[0m",
+        suggestion.command.trim(),
+        suggestion.explanation.trim()
+    );
+    if !suggestion.risk_reason.trim().is_empty() {
+        text.push_str(&format!(" ({})", suggestion.risk_reason.trim()));
+    }
+    text
+}
+
+pub async fn handle_cmd_command(
+    description: Vec<String>,
+    print_only: bool,
+    model: Option<String>,
+    verbose: bool,
+) {
+    let description = description.join(" ");
+    if description.trim().is_empty() {
+        eprintln!("Error: Describe the command you need, e.g. ask cmd find large files");
+        std::process::exit(1);
+    }
+
+    let suggestion = match suggest(&description, model, verbose).await {
+        Ok(suggestion) => suggestion,
+        Err(e) => {
+            eprintln!("Error: {:#}", e);
+            std::process::exit(1);
+        }
+    };
+
+    // Only the command goes to stdout so shell widgets can capture it.
+    if print_only {
+        eprintln!("{}", describe(&suggestion));
+        let dangers = command_risk::classify(&suggestion.command, Path::new("."));
+        if !dangers.is_empty() {
+            eprintln!("{}", command_risk::warning(&dangers));
+        }
+        println!("{}", suggestion.command.trim());
+        return;
+    }
+
+    let mut command = suggestion.command.trim().to_string();
+    let mut edited = false;
+    loop {
+        if edited {
+            println!("\n\x1b[1m{command}\x1b[0m (edited)\n");
+        } else {
+            println!("\n{}\n", describe(&suggestion));
+        }
+        let dangers = command_risk::classify(&command, Path::new("."));
+        if !dangers.is_empty() {
+            println!("{}\n", command_risk::warning(&dangers));
+        }
+
+        let choice = prompt_line("[r]un, [c]opy, [e]dit or [q]uit? ").to_lowercase();
+        match choice.as_str() {
+            "r" | "run" | "y" | "yes" => {
+                if ((suggestion.risk == Risk::High && !edited) || !dangers.is_empty())
+                    && !confirm("This command is high risk. Run it anyway?")
+                {
+                    continue;
+                }
+                std::process::exit(run_command(&command));
+            }
+            "c" | "copy" => {
+                match copy_to_clipboard(&command) {
+                    Ok(()) => println!("Copied to clipboard."),
+                    Err(e) => {
+                        eprintln!("Error: {:#}", e);
+                        std::process::exit(1);
+                    }
+                }
+                return;
+            }
+            "e" | "edit" => match edit_text(&command, "command.sh") {
+                Ok(text) => {
+                    command = text.trim().to_string();
+                    edited = true;
+                }
+                Err(e) => eprintln!("Error: {:#}", e),
+            },
+            _ => return,
+        }
+    }
+}
+
+/// Runs `command` attached to the terminal and returns its exit code.
+fn run_command(command: &str) -> i32 {
+    match shell_info().command(command).status() {
+        Ok(status) => status.code().unwrap_or(1),
+        Err(e) => {
+            eprintln!("Error: Failed to run '{command}': {e}");
+            1
+        }
+    }
+}
+
+/// Copies with the platform's clipboard tool, falling back to the OSC 52 terminal
+/// escape sequence, which also works over SSH in most terminals.
+fn copy_to_clipboard(text: &str) -> Result<()> {
+    let tools: &[(&str, &[&str])] = if cfg!(windows) {
+        &[("clip", &[])]
+    } else if cfg!(target_os = "macos") {
+        &[("pbcopy", &[])]
+    } else {
+        &[
+            ("wl-copy", &[]),
+            ("xclip", &["-selection", "clipboard"]),
+            ("xsel", &["--clipboard", "--input"]),
+        ]
+    };
+
+    for (program, args) in tools {
+        let Ok(mut child) = Command::new(program)
+            .args(*args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+        else {
+            continue;
+        };
+        if let Some(mut stdin) = child.stdin.take() {
+            stdin.write_all(text.as_bytes())?;
+        }
+        if child.wait()?.success() {
+            return Ok(());
+        }
+    }
+
+    if !atty::is(atty::Stream::Stdout) {
+        bail!("No clipboard tool found (tried {})", {
+            let names: Vec<&str> = tools.iter().map(|(program, _)| *program).collect();
+            names.join(", ")
+        });
+    }
+    print!("\x1b]52;c;{}\x07", base64_encode(text.as_bytes()));
+    std::io::stdout().flush()?;
+    Ok(())
+}
+
+fn base64_encode(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut encoded = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let value = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |acc, (i, byte)| acc | (*byte as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(ALPHABET[(value >> (18 - 6 * i) & 63) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+const BASH_INIT: &str = r#"# ask: press Ctrl-G to turn the current line into a command
+_ask_cmd_widget() {
+    [[ -z "$READLINE_LINE" ]] && return
+    local command
+    command=$(ask cmd --print-only -- "$READLINE_LINE" </dev/tty)
+    if [[ -n "$command" ]]; then
+        READLINE_LINE="$command"
+        READLINE_POINT=${#READLINE_LINE}
+    fi
+}
+bind -x '"\C-g": _ask_cmd_widget'
+"#;
+
+const ZSH_INIT: &str = r#"# ask: press Ctrl-G to turn the current line into a command
+_ask_cmd_widget() {
+    [[ -z "$BUFFER" ]] && return
+    zle -I
+    local command
+    command=$(ask cmd --print-only -- "$BUFFER" </dev/tty)
+    if [[ -n "$command" ]]; then
+        BUFFER="$command"
+        CURSOR=${#BUFFER}
+    fi
+    zle reset-prompt
+}
+zle -N _ask_cmd_widget
+bindkey '^G' _ask_cmd_widget
+"#;
+
+const FISH_INIT: &str = r#"# ask: press Ctrl-G to turn the current line into a command
+function _ask_cmd_widget
+    set -l description (commandline)
+    test -z "$description"; and return
+    set -l command (ask cmd --print-only -- "$description" </dev/tty | string collect)
+    if test -n "$command"
+        commandline -r -- $command
+    end
+    commandline -f repaint
+end
+bind \cg _ask_cmd_widget
+"#;
+
+pub fn handle_shell_init(shell: InitShell) {
+    print!(
+        "{}",
+        match shell {
+            InitShell::Bash => BASH_INIT,
+            InitShell::Zsh => ZSH_INIT,
+            InitShell::Fish => FISH_INIT,
+        }
+    );
+}
@@ -0,0 +1,242 @@
+use crate::approval::prompt_line;
+use crate::editor::edit_text;
+use crate::git::{self, run_git, truncate_output};
+use crate::llms;
+use anyhow::{Context, Result, bail};
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+/// Git's empty tree, diffed against when amending the root commit.
+const EMPTY_TREE: &str = "4b825dc642cb6eb9a060e54bf8d69288fbee4904";
+
+/// Number of recent commit messages shown to the model as a style reference.
+const STYLE_COMMITS: usize = 10;
+
+const SYSTEM_PROMPT: &str = "You write git commit messages.\n\
+     Reply with the commit message only: no code fences, quotes or commentary.\n\
+     Write a concise subject line of at most 72 characters. If the change needs \
+     explaining, add a blank line and a body wrapped at 72 characters that says what \
+     changed and why.";
+
+const STYLE_MATCHING: &str = "Match the conventions of the recent commits: \
+     capitalization, tense, prefixes, issue references and whether they have a body.";
+
+const CONVENTIONAL: &str = "Use the Conventional Commits format: \
+     `type(optional scope): description`, where type is one of feat, fix, docs, style, \
+     refactor, perf, test, build, ci or chore. Mark breaking changes with `!` after the \
+     type or scope and a `BREAKING CHANGE:` footer.";
+
+struct CommitContext {
+    diff: String,
+    stat: String,
+    recent_messages: Vec<String>,
+    /// The message of the commit being amended.
+    amended_message: Option<String>,
+}
+
+pub async fn handle_commit_command(
+    conventional: bool,
+    amend: bool,
+    model: Option<String>,
+    instructions: Vec<String>,
+    verbose: bool,
+) {
+    let context = match gather_context(amend) {
+        Ok(context) => context,
+        Err(e) => {
+            eprintln!("Error: {:#}", e);
+            std::process::exit(1);
+        }
+    };
+
+    let system_prompt = format!(
+        "{SYSTEM_PROMPT}\n{}",
+        if conventional {
+            CONVENTIONAL
+        } else {
+            STYLE_MATCHING
+        }
+    );
+    let base_prompt = build_prompt(&context, &instructions.join(" "));
+
+    let mut prompt = base_prompt.clone();
+    loop {
+        println!("Generating commit message...");
+        let mut message =
+            match llms::complete(model.clone(), &system_prompt, &prompt, verbose).await {
+                Ok(answer) => clean_message(&answer),
+                Err(e) => {
+                    eprintln!("Error: Failed to generate commit message: {:#}", e);
+                    std::process::exit(1);
+                }
+            };
+
+        loop {
+            println!("\n{}\n", message.trim_end());
+            let choice = prompt_line("[a]ccept, [e]dit, [r]egenerate or [q]uit? ").to_lowercase();
+            match choice.as_str() {
+                "a" | "accept" | "y" | "yes" => {
+                    if let Err(e) = run_commit(&message, amend) {
+                        eprintln!("Error: {:#}", e);
+                        std::process::exit(1);
+                    }
+                    return;
+                }
+                "e" | "edit" => match edit_text(&message, "COMMIT_EDITMSG") {
+                    Ok(edited) => message = edited,
+                    Err(e) => eprintln!("Error: {:#}", e),
+                },
+                "r" | "regenerate" => {
+                    let feedback = prompt_line("What should change? (optional): ");
+                    prompt = format!(
+                        "{base_prompt}\n\nA previous attempt was:\n{}\n\nWrite a different message.",
+                        message.trim_end()
+                    );
+                    if !feedback.is_empty() {
+                        prompt.push_str(&format!(" {feedback}"));
+                    }
+                    break;
+                }
+                _ => {
+                    println!("Aborted, nothing was committed.");
+                    return;
+                }
+            }
+        }
+    }
+}
+
+fn gather_context(amend: bool) -> Result<CommitContext> {
+    let cwd = std::env::current_dir().context("Failed to get current directory")?;
+    if git::find_git_root(&cwd).is_none() {
+        bail!("{} is not inside a git repository", cwd.display());
+    }
+
+    // When amending, the new message describes the amended commit as a whole.
+    let base = if amend {
+        run_git(&["rev-parse", "--verify", "HEAD"]).context("There is no commit to amend")?;
+        if run_git(&["rev-parse", "--verify", "HEAD~1"]).is_ok() {
+            "HEAD~1"
+        } else {
+            EMPTY_TREE
+        }
+    } else {
+        "HEAD"
+    };
+
+    let (diff, stat) = if base == "HEAD" {
+        (
+            run_git(&["diff", "--staged"])?,
+            run_git(&["diff", "--staged", "--stat"])?,
+        )
+    } else {
+        (
+            run_git(&["diff", "--staged", base])?,
+            run_git(&["diff", "--staged", "--stat", base])?,
+        )
+    };
+    if diff.trim().is_empty() {
+        if amend {
+            bail!("The amended commit would be empty");
+        }
+        bail!("No staged changes. Stage files with `git add` first.");
+    }
+
+    // A repository without commits has no log; that only costs the style reference.
+    let log_count = format!("-n{STYLE_COMMITS}");
+    let recent_messages = run_git(&["log", &log_count, "--no-merges", "--pretty=format:%B%x00"])
+        .map(|log| {
+            log.split('\0')
+                .map(|message| message.trim().to_string())
+                .filter(|message| !message.is_empty())
+                .collect()
+        })
+        .unwrap_or_default();
+
+    let amended_message = if amend {
+        Some(
+            run_git(&["log", "-1", "--pretty=format:%B"])?
+                .trim()
+                .to_string(),
+        )
+    } else {
+        None
+    };
+
+    Ok(CommitContext {
+        diff: truncate_output(&diff, git::MAX_CONTEXT_BYTES),
+        stat,
+        recent_messages,
+        amended_message,
+    })
+}
+
+fn build_prompt(context: &CommitContext, instructions: &str) -> String {
+    let mut prompt = String::new();
+    if !context.recent_messages.is_empty() {
+        prompt.push_str("Recent commit messages, newest first:\n");
+        for message in &context.recent_messages {
+            prompt.push_str(&format!("---\n{message}\n"));
+        }
+        prompt.push_str("---\n\n");
+    }
+    if let Some(message) = &context.amended_message {
+        prompt.push_str(&format!(
+            "The commit being amended currently has this message:\n{message}\n\n"
+        ));
+    }
+    prompt.push_str(&format!(
+        "Changed files:\n{}\nDiff:\n```diff\n{}\n```\n",
+        context.stat.trim_end(),
+        context.diff.trim_end()
+    ));
+    if !instructions.is_empty() {
+        prompt.push_str(&format!("\nAdditional instructions: {instructions}\n"));
+    }
+    prompt
+}
+
+/// Drops code fences or quotes the model wrapped the message in.
+fn clean_message(answer: &str) -> String {
+    let mut message = answer.trim();
+    if let Some(rest) = message.strip_prefix("```") {
+        message = rest
+            .split_once('\n')
+            .map_or("", |(_, body)| body)
+            .trim_end()
+            .trim_end_matches("```")
+            .trim();
+    }
+    if let Some(unquoted) = message
+        .strip_prefix('"')
+        .and_then(|rest| rest.strip_suffix('"'))
+    {
+        message = unquoted.trim();
+    }
+    format!("{message}\n")
+}
+
+fn run_commit(message: &str, amend: bool) -> Result<()> {
+    // The message goes through stdin rather than a temporary file other users could read.
+    let mut command = Command::new("git");
+    command
+        .arg("commit")
+        .arg("-F")
+        .arg("-")
+        .stdin(Stdio::piped());
+    if amend {
+        command.arg("--amend");
+    }
+    let mut child = command.spawn().context("Failed to run git commit")?;
+    let written = match child.stdin.take() {
+        // Dropping stdin closes it, so git sees the end of the message.
+        Some(mut stdin) => stdin.write_all(message.as_bytes()),
+        None => Ok(()),
+    };
+    let status = child.wait().context("Failed to run git commit")?;
+    written.context("Failed to pass the commit message to git")?;
+    if !status.success() {
+        bail!("git commit exited with {status}");
+    }
+    Ok(())
+}
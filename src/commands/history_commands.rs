@@ -0,0 +1,43 @@
+use crate::history::recent_runs;
+
+pub fn handle_history_command(all: bool, limit: usize) {
+    let runs = match recent_runs(all, limit) {
+        Ok(runs) => runs,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    if runs.is_empty() {
+        println!("No runs recorded for this project yet");
+        return;
+    }
+
+    // Oldest first, so the latest question ends up next to the prompt.
+    for run in runs.iter().rev() {
+        let status = if run.succeeded { " " } else { "✗" };
+        let question = run
+            .question
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" ");
+        let question: String = if question.chars().count() > 60 {
+            format!("{}…", question.chars().take(59).collect::<String>())
+        } else {
+            question
+        };
+        println!(
+            "{} {status} {:<16} {:<16} {:>7} tok {:>2} tools  {}",
+            run.started.format("%Y-%m-%d %H:%M"),
+            run.session.as_deref().unwrap_or("-"),
+            run.model,
+            run.total_tokens,
+            run.tool_calls.len(),
+            question
+        );
+        if all {
+            println!("{:>19}in {}", "", run.cwd);
+        }
+    }
+}
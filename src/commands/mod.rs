@@ -1,7 +1,17 @@
+pub mod approval_commands;
+pub mod audit_commands;
 pub mod base_url_commands;
+pub mod checkpoint_commands;
 mod cli;
+pub mod cmd_commands;
+pub mod commit_commands;
+pub mod history_commands;
 pub mod mcp_commands;
 pub mod model_commands;
 pub mod preset_commands;
+pub mod prompt_commands;
 pub mod session_commands;
-pub use cli::{Commands, McpCommands, SessionCommands};
+pub use cli::{
+    ApprovalCommands, AuditCommands, AuditOutput, CheckpointCommands, Commands, ExportFormat,
+    GrantScope, McpCommands, SessionCommands,
+};
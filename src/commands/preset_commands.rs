@@ -1,5 +1,6 @@
 use crate::commands::cli::Presets;
-use crate::config::{self, list_prompt_presets, remove_prompt_presets};
+use crate::config::{self, remove_prompt_presets};
+use crate::presets::{PresetSource, list_presets};
 
 pub fn handle_preset_commands(command: Presets) {
     match command {
@@ -22,8 +23,40 @@ fn handle_add(name: String, prompt_rest: Vec<String>) {
 }
 
 fn handle_list() {
-    match list_prompt_presets() {
-        Ok(_res) => {}
+    match list_presets() {
+        Ok(presets) => {
+            for preset in presets {
+                let settings = &preset.settings;
+                let mut details = vec![];
+                if let Some(model) = &settings.model {
+                    details.push(format!("model={model}"));
+                }
+                if let Some(tools) = &settings.tools {
+                    details.push(format!("tools={}", tools.join(",")));
+                }
+                if let Some(stream) = settings.stream {
+                    details.push(format!("stream={stream}"));
+                }
+                if settings.system_prompt.is_some() {
+                    details.push("system prompt".to_string());
+                }
+                if let PresetSource::File(path) = &preset.source {
+                    details.push(path.display().to_string());
+                }
+
+                let prompt = settings.prompt.split_whitespace().collect::<Vec<_>>();
+                if details.is_empty() {
+                    println!("{}: {}", preset.name, prompt.join(" "));
+                } else {
+                    println!(
+                        "{}: {} ({})",
+                        preset.name,
+                        prompt.join(" "),
+                        details.join(", ")
+                    );
+                }
+            }
+        }
         Err(err) => {
             eprintln!("{err}")
         }
@@ -0,0 +1,39 @@
+use crate::commands::cli::PromptCommands;
+use crate::config::load_config;
+use crate::presets::load_preset;
+use crate::prompt::{build_system_prompt, resolve_instructions};
+use crate::shell::shell_info;
+
+pub fn handle_prompt_commands(command: PromptCommands) {
+    match command {
+        PromptCommands::Show {
+            preset,
+            system,
+            system_file,
+        } => {
+            let preset_prompt = match preset {
+                Some(name) => match load_preset(&name) {
+                    Ok(preset) => preset.settings.system_prompt,
+                    Err(e) => {
+                        eprintln!("Error: Failed to load preset '{}': {}", name, e);
+                        std::process::exit(1);
+                    }
+                },
+                None => None,
+            };
+            let config_prompt = load_config().ok().and_then(|config| config.system_prompt);
+
+            match resolve_instructions(system, system_file.as_deref(), preset_prompt, config_prompt)
+            {
+                Ok(instructions) => print!(
+                    "{}",
+                    build_system_prompt(shell_info(), instructions.as_deref())
+                ),
+                Err(e) => {
+                    eprintln!("Error: {:#}", e);
+                    std::process::exit(1);
+                }
+            }
+        }
+    }
+}
@@ -1,6 +1,8 @@
-use crate::commands::SessionCommands;
+use crate::commands::{ExportFormat, SessionCommands};
 use crate::sessions::{
-    delete_session, get_all_sessions, get_last_session_name, get_session, save_session,
+    LAST_SESSION, SearchHit, SessionData, TurnInfo, copy_session, delete_session, fork_session,
+    get_all_sessions, get_last_session_name, get_session_data, import_session, message_text,
+    parse_age, prune_sessions, rename_session, save_session_data, search_sessions,
 };
 use async_openai::types::{
     ChatCompletionRequestAssistantMessageContent, ChatCompletionRequestMessage,
@@ -12,8 +14,25 @@ pub fn handle_session_commands(command: SessionCommands) {
     match command {
         SessionCommands::List => match get_all_sessions() {
             Ok(sessions) => {
+                let total_size: u64 = sessions.iter().map(|session| session.size).sum();
+                let count = sessions.len();
                 for session in sessions {
-                    println!("{:<20} {}", session.name, session.created);
+                    let preview = session
+                        .preview
+                        .as_deref()
+                        .map(|p| truncate_chars(&single_line(p), 50))
+                        .unwrap_or_default();
+                    println!(
+                        "{:<20} {:<18} {:>4} msgs {:>9}  {}",
+                        session.name,
+                        session.created,
+                        session.message_count,
+                        format_size(session.size),
+                        preview
+                    );
+                }
+                if count > 0 {
+                    println!("{count} session(s), {} total", format_size(total_size));
                 }
             }
             Err(e) => {
@@ -21,13 +40,18 @@ pub fn handle_session_commands(command: SessionCommands) {
                 std::process::exit(1);
             }
         },
-        SessionCommands::Show { name } => {
-            let name = name
-                .unwrap_or_else(|| get_last_session_name().unwrap_or_else(|| "last".to_string()));
-            handle_show_session(name);
+        SessionCommands::Show {
+            name,
+            raw,
+            expand_tools,
+        } => {
+            let name = name.unwrap_or_else(|| {
+                get_last_session_name().unwrap_or_else(|| LAST_SESSION.to_string())
+            });
+            handle_show_session(name, raw, expand_tools);
         }
-        SessionCommands::Save { name } => match get_session("last") {
-            Some(session) => match save_session(&name, &session, None) {
+        SessionCommands::Save { name } => match get_session_data(LAST_SESSION) {
+            Some(session) => match save_session_data(&name, &session) {
                 Ok(_) => println!("Saved session as {name}"),
                 Err(e) => {
                     eprintln!("Error: Failed to save session: {}", e);
@@ -46,106 +70,559 @@ pub fn handle_session_commands(command: SessionCommands) {
                 std::process::exit(1);
             }
         },
+        SessionCommands::Search { text } => handle_search(&text.join(" ")),
+        SessionCommands::Rename { name, new_name } => match rename_session(&name, &new_name) {
+            Ok(_) => println!("Renamed session {name} to {new_name}"),
+            Err(e) => {
+                eprintln!("Error: Failed to rename session: {}", e);
+                std::process::exit(1);
+            }
+        },
+        SessionCommands::Copy { name, new_name } => match copy_session(&name, &new_name) {
+            Ok(_) => println!("Copied session {name} to {new_name}"),
+            Err(e) => {
+                eprintln!("Error: Failed to copy session: {}", e);
+                std::process::exit(1);
+            }
+        },
+        SessionCommands::Fork { name, at, new_name } => match fork_session(&name, at, &new_name) {
+            Ok(_) => match at {
+                Some(turn) => println!("Forked session {name} at turn {turn} as {new_name}"),
+                None => println!("Forked session {name} as {new_name}"),
+            },
+            Err(e) => {
+                eprintln!("Error: Failed to fork session: {}", e);
+                std::process::exit(1);
+            }
+        },
+        SessionCommands::Export {
+            name,
+            format,
+            output,
+        } => handle_export(&name, format, output),
+        SessionCommands::Import { file, name } => {
+            let name = name.unwrap_or_else(|| {
+                file.file_stem()
+                    .map(|s| s.to_string_lossy().to_string())
+                    .unwrap_or_else(|| "imported".to_string())
+            });
+            match import_session(&file, &name) {
+                Ok(_) => println!("Imported {} as session {name}", file.display()),
+                Err(e) => {
+                    eprintln!("Error: Failed to import session: {}", e);
+                    std::process::exit(1);
+                }
+            }
+        }
+        SessionCommands::Prune {
+            older_than,
+            keep,
+            dry_run,
+        } => handle_prune(older_than.as_deref(), keep, dry_run),
+    }
+}
+
+fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["B", "KiB", "MiB", "GiB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{bytes} B")
+    } else {
+        format!("{size:.1} {}", UNITS[unit])
+    }
+}
+
+fn handle_prune(older_than: Option<&str>, keep: Option<usize>, dry_run: bool) {
+    let older_than = match older_than.map(parse_age).transpose() {
+        Ok(age) => age,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    let pruned = match prune_sessions(older_than, keep, dry_run) {
+        Ok(pruned) => pruned,
+        Err(e) => {
+            eprintln!("Error: Failed to prune sessions: {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    if pruned.is_empty() {
+        println!("No sessions to prune");
+        return;
+    }
+
+    for session in &pruned {
+        println!("{:<30} {:>9}", session.name, format_size(session.size));
+    }
+    let freed = format_size(pruned.iter().map(|session| session.size).sum());
+    if dry_run {
+        println!("Would delete {} session(s), {freed}", pruned.len());
+    } else {
+        println!("Deleted {} session(s), freed {freed}", pruned.len());
+    }
+}
+
+fn single_line(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+fn truncate_chars(text: &str, max_chars: usize) -> String {
+    if text.chars().count() <= max_chars {
+        text.to_string()
+    } else {
+        let truncated: String = text.chars().take(max_chars.saturating_sub(1)).collect();
+        format!("{truncated}…")
+    }
+}
+
+fn handle_search(query: &str) {
+    let hits = match search_sessions(query) {
+        Ok(hits) => hits,
+        Err(e) => {
+            eprintln!("Error: Failed to search sessions: {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    if hits.is_empty() {
+        println!("No sessions match '{query}'");
+        return;
+    }
+
+    let use_colors = atty::is(atty::Stream::Stdout);
+    for hit in hits {
+        println!(
+            "{:<20} {:<10} {}",
+            hit.session,
+            hit.role,
+            snippet(&hit, use_colors)
+        );
+    }
+}
+
+/// Cuts ~40 characters of context on either side of the match and highlights it.
+fn snippet(hit: &SearchHit, use_colors: bool) -> String {
+    const CONTEXT_CHARS: usize = 40;
+    let (start, end) = hit.match_range;
+
+    let before: String = {
+        let chars: Vec<char> = hit.text[..start].chars().collect();
+        let skip = chars.len().saturating_sub(CONTEXT_CHARS);
+        let prefix = if skip > 0 { "…" } else { "" };
+        format!("{prefix}{}", chars[skip..].iter().collect::<String>())
+    };
+    let after: String = {
+        let rest = &hit.text[end..];
+        let suffix = if rest.chars().count() > CONTEXT_CHARS {
+            "…"
+        } else {
+            ""
+        };
+        format!(
+            "{}{suffix}",
+            rest.chars().take(CONTEXT_CHARS).collect::<String>()
+        )
+    };
+    let matched = &hit.text[start..end];
+    let flatten = |text: &str| text.replace(['\n', '\r', '\t'], " ");
+
+    if use_colors {
+        format!(
+            "{}\x1b[1m\x1b[33m{}\x1b[0m{}",
+            flatten(&before),
+            matched,
+            flatten(&after)
+        )
+    } else {
+        format!("{}[{}]{}", flatten(&before), matched, flatten(&after))
+    }
+}
+
+fn handle_export(name: &str, format: ExportFormat, output: Option<std::path::PathBuf>) {
+    let Some(session) = get_session_data(name) else {
+        eprintln!("Error: Session '{name}' not found");
+        std::process::exit(1);
+    };
+
+    let exported = match format {
+        ExportFormat::Json => match serde_json::to_string_pretty(&session) {
+            Ok(json) => json,
+            Err(e) => {
+                eprintln!("Error: Failed to serialize session: {}", e);
+                std::process::exit(1);
+            }
+        },
+        ExportFormat::Markdown => export_markdown(name, &session.messages),
+        ExportFormat::Html => export_html(name, &session.messages),
+    };
+
+    match output {
+        Some(path) => match std::fs::write(&path, exported) {
+            Ok(_) => println!("Exported session {name} to {}", path.display()),
+            Err(e) => {
+                eprintln!("Error: Failed to write {}: {}", path.display(), e);
+                std::process::exit(1);
+            }
+        },
+        None => print!("{exported}"),
+    }
+}
+
+fn export_markdown(name: &str, session: &[ChatCompletionRequestMessage]) -> String {
+    let mut output = format!("# Session: {name}\n\n");
+    for message in session {
+        match message_text(message) {
+            Some(("System", _)) | None => {}
+            Some(("Tool", text)) => {
+                output.push_str("<details><summary>Tool result</summary>\n\n```\n");
+                output.push_str(text.trim_end());
+                output.push_str("\n```\n\n</details>\n\n");
+            }
+            Some((role, text)) => {
+                output.push_str(&format!("## {role}\n\n{}\n\n", text.trim_end()));
+            }
+        }
     }
+    output
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn export_html(name: &str, session: &[ChatCompletionRequestMessage]) -> String {
+    let mut body = String::new();
+    for message in session {
+        match message_text(message) {
+            Some(("System", _)) | None => {}
+            Some((role, text)) => {
+                body.push_str(&format!(
+                    "<section class=\"{}\"><h2>{role}</h2><pre>{}</pre></section>\n",
+                    role.to_lowercase(),
+                    escape_html(text.trim_end())
+                ));
+            }
+        }
+    }
+
+    format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>Session: {title}</title>\n\
+         <style>\n\
+         body {{ font-family: sans-serif; max-width: 50em; margin: 2em auto; }}\n\
+         pre {{ white-space: pre-wrap; font-family: inherit; }}\n\
+         section {{ border-radius: 8px; padding: 0.5em 1em; margin: 1em 0; }}\n\
+         .user {{ background: #e8f4f8; margin-left: 20%; }}\n\
+         .assistant {{ background: #eef8e8; }}\n\
+         .tool {{ background: #f4f4f4; font-size: 0.9em; }}\n\
+         </style>\n</head>\n<body>\n<h1>Session: {title}</h1>\n{body}</body>\n</html>\n",
+        title = escape_html(name)
+    )
 }
 
 struct MessageBoxConfig {
-    label: &'static str,
+    label: String,
     color: &'static str,
     max_width_percent: f32,
     align_right: bool,
     left_margin: usize,
 }
 
-fn handle_show_session(name: String) {
+fn handle_show_session(name: String, raw: bool, expand_tools: bool) {
     use std::fmt::Write as FmtWrite;
 
-    let session = get_session(&name);
-    match session {
-        Some(session) => {
-            let is_interactive = atty::is(atty::Stream::Stdout);
-            let (width, _) = terminal::size().unwrap_or((80, 24));
-            let mut output = String::new();
-
-            writeln!(&mut output).unwrap();
-
-            // Display session name header (centered in interactive mode)
-            if is_interactive {
-                let header_text = format!("═══ Session: {} ═══", name);
-                let header_len = header_text.chars().count();
-                let left_padding = if header_len < width as usize {
-                    (width as usize - header_len) / 2
-                } else {
-                    0
-                };
-                writeln!(
-                    &mut output,
-                    "{}\x1b[1m\x1b[35m{}\x1b[0m",
-                    " ".repeat(left_padding),
-                    header_text
-                )
-                .unwrap();
-            } else {
-                writeln!(&mut output, "=== Session: {} ===", name).unwrap();
-            }
-            writeln!(&mut output).unwrap();
-
-            for message in session {
-                match message {
-                    ChatCompletionRequestMessage::User(message) => {
-                        if let ChatCompletionRequestUserMessageContent::Text(text) = message.content
-                        {
-                            render_message_box(
-                                &mut output,
-                                &text,
-                                width,
-                                MessageBoxConfig {
-                                    label: "User",
-                                    color: "\x1b[36m",
-                                    max_width_percent: 0.6,
-                                    align_right: true,
-                                    left_margin: 0,
-                                },
-                                is_interactive,
-                            );
-                        }
-                    }
-                    ChatCompletionRequestMessage::Assistant(message) => {
-                        if let Some(content) = &message.content
-                            && let ChatCompletionRequestAssistantMessageContent::Text(text) =
-                                content
-                        {
-                            render_message_box(
-                                &mut output,
-                                text,
-                                width,
-                                MessageBoxConfig {
-                                    label: "Assistant",
-                                    color: "\x1b[32m",
-                                    max_width_percent: 0.8,
-                                    align_right: false,
-                                    left_margin: 2,
-                                },
-                                is_interactive,
-                            );
-                        }
-                    }
-                    _ => {}
+    let Some(session) = get_session_data(&name) else {
+        println!("Session not found");
+        return;
+    };
+
+    if raw {
+        print!("{}", render_raw(&session));
+        return;
+    }
+
+    let is_interactive = atty::is(atty::Stream::Stdout);
+    let (width, _) = terminal::size().unwrap_or((80, 24));
+    let mut output = String::new();
+
+    writeln!(&mut output).unwrap();
+
+    // Display session name header (centered in interactive mode)
+    if is_interactive {
+        let header_text = format!("═══ Session: {} ═══", name);
+        let header_len = display_width(&header_text);
+        let left_padding = if header_len < width as usize {
+            (width as usize - header_len) / 2
+        } else {
+            0
+        };
+        writeln!(
+            &mut output,
+            "{}\x1b[1m\x1b[35m{}\x1b[0m",
+            " ".repeat(left_padding),
+            header_text
+        )
+        .unwrap();
+    } else {
+        writeln!(&mut output, "=== Session: {} ===", name).unwrap();
+    }
+    writeln!(&mut output).unwrap();
+
+    let mut turn = 0;
+    for message in &session.messages {
+        match message {
+            ChatCompletionRequestMessage::User(message) => {
+                turn += 1;
+                if let ChatCompletionRequestUserMessageContent::Text(text) = &message.content {
+                    render_message_box(
+                        &mut output,
+                        text,
+                        width,
+                        MessageBoxConfig {
+                            label: user_label(turn, session.turns.get(turn.wrapping_sub(1))),
+                            color: "\x1b[36m",
+                            max_width_percent: 0.6,
+                            align_right: true,
+                            left_margin: 0,
+                        },
+                        is_interactive,
+                    );
                 }
             }
-
-            if is_interactive {
-                let pager = minus::Pager::new();
-                pager.set_text(&output).unwrap();
-                minus::page_all(pager).unwrap();
-            } else {
-                print!("{}", output);
+            ChatCompletionRequestMessage::Assistant(message) => {
+                if let Some(content) = &message.content
+                    && let ChatCompletionRequestAssistantMessageContent::Text(text) = content
+                {
+                    render_message_box(
+                        &mut output,
+                        text,
+                        width,
+                        MessageBoxConfig {
+                            label: assistant_label(session.turns.get(turn.wrapping_sub(1))),
+                            color: "\x1b[32m",
+                            max_width_percent: 0.8,
+                            align_right: false,
+                            left_margin: 2,
+                        },
+                        is_interactive,
+                    );
+                }
+                for tool_call in message.tool_calls.iter().flatten() {
+                    render_tool_entry(
+                        &mut output,
+                        &format!("tool call {}", tool_call.function.name),
+                        &tool_call_arguments(&tool_call.function.arguments),
+                        width,
+                        expand_tools,
+                        is_interactive,
+                    );
+                }
+            }
+            ChatCompletionRequestMessage::Tool(_) => {
+                if let Some((_, text)) = message_text(message) {
+                    let label = format!("tool result · {} line(s)", text.lines().count());
+                    render_tool_entry(
+                        &mut output,
+                        &label,
+                        &text,
+                        width,
+                        expand_tools,
+                        is_interactive,
+                    );
+                }
             }
+            _ => {}
         }
-        None => {
-            println!("Session not found");
+    }
+
+    if is_interactive {
+        let pager = minus::Pager::new();
+        pager.set_text(&output).unwrap();
+        minus::page_all(pager).unwrap();
+    } else {
+        print!("{}", output);
+    }
+}
+
+fn format_timestamp(timestamp: &str) -> Option<String> {
+    let datetime = chrono::DateTime::parse_from_rfc3339(timestamp).ok()?;
+    Some(
+        datetime
+            .with_timezone(&chrono::Local)
+            .format("%Y-%m-%d %H:%M")
+            .to_string(),
+    )
+}
+
+fn user_label(turn: usize, info: Option<&TurnInfo>) -> String {
+    match info
+        .and_then(|info| info.timestamp.as_deref())
+        .and_then(format_timestamp)
+    {
+        Some(timestamp) => format!("User · turn {turn} · {timestamp}"),
+        None => format!("User · turn {turn}"),
+    }
+}
+
+fn assistant_label(info: Option<&TurnInfo>) -> String {
+    match info.and_then(|info| info.model.as_deref()) {
+        Some(model) => format!("Assistant · {model}"),
+        None => "Assistant".to_string(),
+    }
+}
+
+/// Pretty-prints tool call arguments when they are valid JSON.
+fn tool_call_arguments(arguments: &str) -> String {
+    serde_json::from_str::<serde_json::Value>(arguments)
+        .and_then(|value| serde_json::to_string_pretty(&value))
+        .unwrap_or_else(|_| arguments.to_string())
+}
+
+/// The session as plain markdown source, including tool calls and results in full.
+fn render_raw(session: &SessionData) -> String {
+    let mut output = String::new();
+    let mut turn = 0;
+    for message in &session.messages {
+        match message {
+            ChatCompletionRequestMessage::User(_) => {
+                turn += 1;
+                if let Some((_, text)) = message_text(message) {
+                    let label = user_label(turn, session.turns.get(turn - 1));
+                    output.push_str(&format!("## {label}\n\n{}\n\n", text.trim_end()));
+                }
+            }
+            ChatCompletionRequestMessage::Assistant(assistant) => {
+                let label = assistant_label(session.turns.get(turn.wrapping_sub(1)));
+                if let Some((_, text)) = message_text(message)
+                    && !text.trim().is_empty()
+                {
+                    output.push_str(&format!("## {label}\n\n{}\n\n", text.trim_end()));
+                }
+                for tool_call in assistant.tool_calls.iter().flatten() {
+                    output.push_str(&format!(
+                        "### tool call {}\n\n```json\n{}\n```\n\n",
+                        tool_call.function.name,
+                        tool_call_arguments(&tool_call.function.arguments)
+                    ));
+                }
+            }
+            ChatCompletionRequestMessage::Tool(_) => {
+                if let Some((_, text)) = message_text(message) {
+                    output.push_str(&format!(
+                        "### tool result\n\n```\n{}\n```\n\n",
+                        text.trim_end()
+                    ));
+                }
+            }
+            _ => {}
         }
     }
+    output
+}
+
+fn display_width(text: &str) -> usize {
+    textwrap::core::display_width(text)
+}
+
+/// Cuts `text` to a single line of at most `max_width` columns.
+fn truncate_display(text: &str, max_width: usize) -> String {
+    let text = single_line(text);
+    if display_width(&text) <= max_width {
+        return text;
+    }
+    let first = textwrap::wrap(
+        &text,
+        textwrap::Options::new(max_width.saturating_sub(1).max(1)),
+    )
+    .into_iter()
+    .next()
+    .map(|line| line.into_owned())
+    .unwrap_or_default();
+    format!("{first}…")
+}
+
+fn render_markdown(text: &str) -> String {
+    let mut rendered = Vec::new();
+    match markterm::render_text(text, None, &mut rendered, true) {
+        Ok(()) => String::from_utf8(rendered).unwrap_or_else(|_| text.to_string()),
+        Err(_) => text.to_string(),
+    }
+}
+
+/// Wraps every line to `width` display columns (escape sequences take no space),
+/// collapses runs of blank lines and drops leading and trailing ones.
+fn wrap_lines(text: &str, width: usize) -> Vec<String> {
+    let options = textwrap::Options::new(width.max(1)).break_words(true);
+    let mut lines: Vec<String> = text
+        .lines()
+        .flat_map(|line| {
+            textwrap::wrap(line, &options)
+                .into_iter()
+                .map(|wrapped| wrapped.into_owned())
+                .collect::<Vec<_>>()
+        })
+        .collect();
+    lines.dedup_by(|line, previous| line.trim().is_empty() && previous.trim().is_empty());
+
+    while lines.first().is_some_and(|line| line.trim().is_empty()) {
+        lines.remove(0);
+    }
+    while lines.last().is_some_and(|line| line.trim().is_empty()) {
+        lines.pop();
+    }
+    lines
+}
+
+/// Tool calls and results are shown as one summary line unless `expand` is set.
+fn render_tool_entry(
+    output: &mut String,
+    label: &str,
+    text: &str,
+    terminal_width: u16,
+    expand: bool,
+    use_colors: bool,
+) {
+    use std::fmt::Write as FmtWrite;
+
+    if expand {
+        render_message_box(
+            output,
+            &format!("```\n{}\n```", text.trim_end()),
+            terminal_width,
+            MessageBoxConfig {
+                label: format!("▾ {label}"),
+                color: "\x1b[90m",
+                max_width_percent: 0.8,
+                align_right: false,
+                left_margin: 2,
+            },
+            use_colors,
+        );
+        return;
+    }
+
+    let summary_width = (terminal_width as usize).saturating_sub(display_width(label) + 8);
+    let summary = truncate_display(text, summary_width.max(10));
+    if use_colors {
+        writeln!(
+            output,
+            "  \x1b[90m▸ {label}\x1b[0m  \x1b[2m{summary}\x1b[0m"
+        )
+        .unwrap();
+    } else {
+        writeln!(output, "▸ {label}  {summary}").unwrap();
+    }
+    writeln!(output).unwrap();
 }
 
 fn render_message_box(
@@ -160,28 +637,30 @@ fn render_message_box(
     let max_box_width = (terminal_width as f32 * config.max_width_percent) as usize;
     let box_padding = 3;
 
-    let lines: Vec<&str> = text.lines().collect();
-    let content_width = lines
-        .iter()
-        .map(|line| line.len())
-        .max()
-        .unwrap_or(0)
-        .min(max_box_width - box_padding * 2);
-
-    let box_width = content_width + box_padding * 2;
-    let left_margin = if config.align_right {
-        terminal_width.saturating_sub(box_width as u16 + 2) as usize
-    } else {
-        config.left_margin
-    };
+    if use_colors {
+        let lines = wrap_lines(
+            &render_markdown(text),
+            max_box_width.saturating_sub(box_padding * 2).max(10),
+        );
+        let content_width = lines
+            .iter()
+            .map(|line| display_width(line))
+            .max()
+            .unwrap_or(0);
 
-    let label_indent = if config.align_right {
-        left_margin + box_width - config.label.len()
-    } else {
-        left_margin
-    };
+        let box_width = content_width + box_padding * 2;
+        let left_margin = if config.align_right {
+            terminal_width.saturating_sub(box_width as u16 + 2) as usize
+        } else {
+            config.left_margin
+        };
+
+        let label_indent = if config.align_right {
+            (left_margin + box_width).saturating_sub(display_width(&config.label))
+        } else {
+            left_margin
+        };
 
-    if use_colors {
         write!(output, "{}", " ".repeat(label_indent)).unwrap();
         writeln!(output, "{}{}\x1b[0m", config.color, config.label).unwrap();
 
@@ -189,22 +668,17 @@ fn render_message_box(
         write!(output, "{}", " ".repeat(left_margin)).unwrap();
         writeln!(output, "{}╭{}╮\x1b[0m", config.color, "─".repeat(box_width)).unwrap();
 
-        // Content
+        // Content, reset after each line so styles never bleed into the border
         for line in lines {
-            let display_line = if line.len() > content_width {
-                &line[..content_width]
-            } else {
-                line
-            };
-            let padding = content_width - display_line.len();
+            let padding = content_width - display_width(&line);
 
             write!(output, "{}", " ".repeat(left_margin)).unwrap();
             write!(output, "{}│\x1b[0m", config.color).unwrap();
             write!(
                 output,
-                "{}{}{}",
+                "{}{}\x1b[0m{}",
                 " ".repeat(box_padding),
-                display_line,
+                line,
                 " ".repeat(padding + box_padding)
             )
             .unwrap();
@@ -217,8 +691,8 @@ fn render_message_box(
     } else {
         // Simple text output without colors and box drawing
         writeln!(output, "{}", config.label).unwrap();
-        writeln!(output, "{}", "-".repeat(config.label.len())).unwrap();
-        for line in lines {
+        writeln!(output, "{}", "-".repeat(display_width(&config.label))).unwrap();
+        for line in text.lines() {
             writeln!(output, "{}", line).unwrap();
         }
     }
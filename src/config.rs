@@ -1,5 +1,6 @@
 use crate::tools::mcp::McpServerConfig;
 use anyhow::{Context, Result, bail};
+use rmcp::model::LoggingLevel;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::fs;
@@ -23,10 +24,134 @@ pub struct AskConfig {
     pub model_aliases: HashMap<String, String>,
 
     #[serde(rename = "promptPresets", default)]
-    pub presets: HashMap<String, String>,
+    pub presets: HashMap<String, PresetDefinition>,
 
     #[serde(rename = "stream", default)]
     pub stream: Option<bool>,
+
+    /// Maximum tokens an MCP server may request per sampling call.
+    #[serde(rename = "samplingMaxTokens", default)]
+    pub sampling_max_tokens: Option<u32>,
+
+    /// Minimum level of MCP server log messages shown in the terminal.
+    #[serde(rename = "mcpLogLevel", default)]
+    pub mcp_log_level: Option<LoggingLevel>,
+
+    /// Replaces the built-in instructions at the start of the system prompt.
+    #[serde(rename = "systemPrompt", default)]
+    pub system_prompt: Option<String>,
+
+    /// Run commands with the user's shell (bash, zsh or fish) as a login shell instead of `sh`,
+    /// reading its rc file with aliases enabled.
+    #[serde(rename = "loginShell", default)]
+    pub login_shell: Option<bool>,
+
+    /// Run `execute_command` calls in one shell per run, so `cd` and exported variables
+    /// carry over between calls.
+    #[serde(rename = "persistentShell", default)]
+    pub persistent_shell: Option<bool>,
+
+    /// Seconds a command may run in the persistent shell before it is killed.
+    #[serde(rename = "commandTimeout", default)]
+    pub command_timeout: Option<u64>,
+
+    /// How long approvals given with "always" last, e.g. `30d`; forever when unset.
+    #[serde(rename = "approvalExpiry", default)]
+    pub approval_expiry: Option<String>,
+
+    /// Back up files before tool calls change them, for `ask undo`. Enabled by default.
+    #[serde(rename = "checkpoints", default)]
+    pub checkpoints: Option<bool>,
+
+    /// Also track what shell commands change, which copies every small file in the
+    /// current directory before the first command. Disabled by default.
+    #[serde(rename = "checkpointCommands", default)]
+    pub checkpoint_commands: Option<bool>,
+
+    /// Sessions pruned automatically after each run.
+    #[serde(rename = "sessionRetention", default)]
+    pub session_retention: Option<SessionRetention>,
+
+    /// Where tool calls are logged; `~/.ask/audit.log` by default.
+    #[serde(rename = "auditLog", default)]
+    pub audit_log: Option<AuditLogSettings>,
+
+    /// The built-in file tools: which are offered and the directories they can access.
+    #[serde(rename = "nativeTools", default)]
+    pub native_tools: Option<NativeToolSettings>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+pub struct SessionRetention {
+    /// Age such as `30d` after which unused sessions are deleted.
+    #[serde(rename = "olderThan", default)]
+    pub older_than: Option<String>,
+
+    /// Number of most recent sessions that are never deleted.
+    #[serde(default)]
+    pub keep: Option<usize>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+pub struct AuditLogSettings {
+    /// Log tool calls at all. Enabled by default.
+    #[serde(default)]
+    pub enabled: Option<bool>,
+
+    /// The log file, instead of `~/.ask/audit.log`.
+    #[serde(default)]
+    pub path: Option<String>,
+
+    /// Also log the calls made in a project to this path, relative to its git
+    /// repository or directory, e.g. `.ask/audit.log`.
+    #[serde(rename = "projectPath", default)]
+    pub project_path: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+pub struct NativeToolSettings {
+    /// Offer the built-in file tools at all. Enabled by default.
+    #[serde(default)]
+    pub enabled: Option<bool>,
+
+    /// Directories the tools can read and write, instead of the current directory.
+    #[serde(default)]
+    pub roots: Vec<String>,
+
+    /// Turns single tools on or off, e.g. `{"write_file": false}`.
+    #[serde(default)]
+    pub tools: HashMap<String, bool>,
+}
+
+/// A prompt preset: either just the prompt template, or the template with settings.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(untagged)]
+pub enum PresetDefinition {
+    Template(String),
+    Detailed(PresetSettings),
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+pub struct PresetSettings {
+    /// Prompt template; see `presets::render_prompt` for the supported placeholders.
+    pub prompt: String,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+
+    #[serde(
+        rename = "systemPrompt",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub system_prompt: Option<String>,
+
+    /// Names of the tools offered to the model; `*` matches any characters.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tools: Option<Vec<String>>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stream: Option<bool>,
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
@@ -153,15 +278,13 @@ pub fn remove_server(name: &str) -> Result<PathBuf> {
     ))
 }
 
-pub fn add_auto_approved_tool(tool_name: &str) -> Result<PathBuf> {
-    let mut config = load_config().unwrap_or_default();
+pub fn remove_auto_approved_tool(tool_name: &str) -> Result<PathBuf> {
+    let mut config = load_config().context("Failed to load config to remove approval")?;
 
-    if !config.auto_approved_tools.contains(&tool_name.to_string()) {
-        config.auto_approved_tools.push(tool_name.to_string());
-    }
+    config.auto_approved_tools.retain(|tool| tool != tool_name);
 
     save_config(&config).context(format!(
-        "Failed to save config after adding auto-approved tool '{}'",
+        "Failed to save config after removing auto-approved tool '{}'",
         tool_name
     ))
 }
@@ -196,7 +319,7 @@ pub fn add_prompt_presets(name: String, prompt: String) -> Result<PathBuf> {
     if presets.contains_key(&name) {
         bail!("Cannot add {name}. It already exists");
     }
-    presets.insert(name, prompt);
+    presets.insert(name, PresetDefinition::Template(prompt));
     config.presets = presets;
     save_config(&config)
 }
@@ -212,30 +335,6 @@ pub fn remove_prompt_presets(name: String) -> Result<PathBuf> {
     save_config(&config)
 }
 
-pub fn list_prompt_presets() -> Result<()> {
-    let config = load_config().context("Failed to load config")?;
-    let presets = config.presets;
-    if presets.is_empty() {
-        bail!("")
-    }
-
-    for (name, value) in presets {
-        println!("{name}: {value}");
-    }
-
-    Ok(())
-}
-
-pub fn get_prompt_preset(name: &str) -> Result<String> {
-    let config = load_config().context("Failed to load config")?;
-    let presets = config.presets;
-    if let Some(preset) = presets.get(name) {
-        Ok(preset.clone())
-    } else {
-        bail!("Prompt preset '{name}' not found")
-    }
-}
-
 /// Expand environment variables in strings
 /// Supports ${VAR} and ${VAR:-default} syntax
 fn expand_env_vars(input: &str) -> String {
@@ -0,0 +1,201 @@
+use serde_json::Value;
+use std::path::Path;
+
+/// Unchanged lines shown around each change.
+const CONTEXT_LINES: usize = 3;
+
+/// Longer previews are cut off.
+const MAX_PREVIEW_LINES: usize = 200;
+
+/// Changed regions larger than this (old lines × new lines) are only summarised; the
+/// diff needs memory for each pair.
+const MAX_DIFF_CELLS: usize = 4_000_000;
+
+enum Line<'a> {
+    Same(&'a str),
+    Removed(&'a str),
+    Added(&'a str),
+}
+
+/// A coloured unified diff of `old` and `new`, e.g. for approving a file edit.
+pub fn unified_diff(old: &str, new: &str) -> String {
+    let old_lines: Vec<&str> = old.lines().collect();
+    let new_lines: Vec<&str> = new.lines().collect();
+    let Some(lines) = diff_lines(&old_lines, &new_lines) else {
+        return format!(
+            "({} lines → {} lines; too large to preview)",
+            old_lines.len(),
+            new_lines.len()
+        );
+    };
+    if !lines.iter().any(|line| !matches!(line, Line::Same(_))) {
+        return "(no changes)".to_string();
+    }
+
+    // Positions of the lines to show: every change plus its context.
+    let mut shown = vec![false; lines.len()];
+    for (index, line) in lines.iter().enumerate() {
+        if !matches!(line, Line::Same(_)) {
+            let start = index.saturating_sub(CONTEXT_LINES);
+            let end = (index + CONTEXT_LINES + 1).min(lines.len());
+            shown[start..end].iter_mut().for_each(|show| *show = true);
+        }
+    }
+
+    let mut output = Vec::new();
+    let (mut old_number, mut new_number) = (1, 1);
+    let mut index = 0;
+    while index < lines.len() {
+        if !shown[index] {
+            if let Line::Same(_) = lines[index] {
+                old_number += 1;
+                new_number += 1;
+            }
+            index += 1;
+            continue;
+        }
+
+        let end = (index..lines.len())
+            .find(|&i| !shown[i])
+            .unwrap_or(lines.len());
+        let hunk = &lines[index..end];
+        let old_count = hunk
+            .iter()
+            .filter(|line| !matches!(line, Line::Added(_)))
+            .count();
+        let new_count = hunk
+            .iter()
+            .filter(|line| !matches!(line, Line::Removed(_)))
+            .count();
+        // Like git, an empty side starts at the line before it.
+        let old_start = if old_count == 0 {
+            old_number - 1
+        } else {
+            old_number
+        };
+        let new_start = if new_count == 0 {
+            new_number - 1
+        } else {
+            new_number
+        };
+        output.push(format!(
+            "\x1b[36m@@ -{old_start},{old_count} +{new_start},{new_count} @@\x1b[0m"
+        ));
+        for line in hunk {
+            output.push(match line {
+                Line::Same(text) => format!(" {text}"),
+                Line::Removed(text) => format!("\x1b[31m-{text}\x1b[0m"),
+                Line::Added(text) => format!("\x1b[32m+{text}\x1b[0m"),
+            });
+        }
+        old_number += old_count;
+        new_number += new_count;
+        index = end;
+    }
+
+    if output.len() > MAX_PREVIEW_LINES {
+        let hidden = output.len() - MAX_PREVIEW_LINES;
+        output.truncate(MAX_PREVIEW_LINES);
+        output.push(format!("… {hidden} more lines"));
+    }
+    output.join("\n")
+}
+
+/// The changes writing `content` to `path` would make; a missing file counts as empty.
+pub fn preview_write(path: &Path, content: &str) -> String {
+    let old = std::fs::read_to_string(path).unwrap_or_default();
+    unified_diff(&old, content)
+}
+
+/// The changes of replacing each edit's `oldText` with its `newText` in `path`, as the
+/// filesystem server's `edit_file` does.
+pub fn preview_edits(path: &Path, edits: &[Value]) -> String {
+    let Ok(old) = std::fs::read_to_string(path) else {
+        return format!("(cannot read {})", path.display());
+    };
+
+    let mut new = old.clone();
+    let mut problems = Vec::new();
+    for (index, edit) in edits.iter().enumerate() {
+        let (Some(old_text), Some(new_text)) = (edit["oldText"].as_str(), edit["newText"].as_str())
+        else {
+            problems.push(format!("edit {}: missing oldText or newText", index + 1));
+            continue;
+        };
+        match new.find(old_text) {
+            Some(start) => new.replace_range(start..start + old_text.len(), new_text),
+            None => problems.push(format!("edit {}: oldText not found", index + 1)),
+        }
+    }
+
+    let mut preview = unified_diff(&old, &new);
+    for problem in problems {
+        preview.push_str(&format!("\n\x1b[33m({problem})\x1b[0m"));
+    }
+    preview
+}
+
+/// The line-by-line changes from `old` to `new`, based on their longest common
+/// subsequence. `None` when the changed region is too large.
+fn diff_lines<'a>(old: &[&'a str], new: &[&'a str]) -> Option<Vec<Line<'a>>> {
+    let prefix = old
+        .iter()
+        .zip(new)
+        .take_while(|(old, new)| old == new)
+        .count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(old, new)| old == new)
+        .count();
+    let (old_middle, new_middle) = (
+        &old[prefix..old.len() - suffix],
+        &new[prefix..new.len() - suffix],
+    );
+    if old_middle.len() * new_middle.len() > MAX_DIFF_CELLS {
+        return None;
+    }
+
+    let mut lines: Vec<Line> = old[..prefix].iter().map(|line| Line::Same(line)).collect();
+    lines.extend(diff_middle(old_middle, new_middle));
+    lines.extend(
+        old[old.len() - suffix..]
+            .iter()
+            .map(|line| Line::Same(line)),
+    );
+    Some(lines)
+}
+
+fn diff_middle<'a>(old: &[&'a str], new: &[&'a str]) -> Vec<Line<'a>> {
+    // lengths[i][j]: LCS length of old[i..] and new[j..].
+    let mut lengths = vec![vec![0u32; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lengths[i][j] = if old[i] == new[j] {
+                lengths[i + 1][j + 1] + 1
+            } else {
+                lengths[i + 1][j].max(lengths[i][j + 1])
+            };
+        }
+    }
+
+    let mut lines = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() && j < new.len() {
+        if old[i] == new[j] {
+            lines.push(Line::Same(old[i]));
+            i += 1;
+            j += 1;
+        } else if lengths[i + 1][j] >= lengths[i][j + 1] {
+            lines.push(Line::Removed(old[i]));
+            i += 1;
+        } else {
+            lines.push(Line::Added(new[j]));
+            j += 1;
+        }
+    }
+    lines.extend(old[i..].iter().map(|line| Line::Removed(line)));
+    lines.extend(new[j..].iter().map(|line| Line::Added(line)));
+    lines
+}
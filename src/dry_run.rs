@@ -0,0 +1,166 @@
+use crate::tools::ExecuteCommandRequest;
+use anyhow::{Context, Result};
+use std::path::PathBuf;
+
+/// Settings for `--dry-run`.
+pub struct DryRunOptions {
+    /// Still run MCP tools that declare themselves read-only.
+    pub run_read_only: bool,
+    /// Write the planned commands to this file as a shell script.
+    pub script: Option<PathBuf>,
+}
+
+struct PlannedCall {
+    tool: String,
+    arguments: String,
+    /// Read-only calls that ran for real.
+    executed: bool,
+}
+
+/// Every tool call the model made during a dry run, in order.
+pub struct DryRunPlan {
+    pub options: DryRunOptions,
+    /// Commands share one shell, so the script doesn't isolate them in subshells.
+    persistent_shell: bool,
+    calls: Vec<PlannedCall>,
+}
+
+/// What the model is told instead of the real result.
+const NOT_EXECUTED: &str = "{\"dry_run\": true, \"status\": \"not_executed\", \"message\": \
+     \"Dry run: this call was recorded but not executed. Assume it would succeed and \
+     continue with the remaining steps. Do not retry it.\"}";
+
+impl DryRunPlan {
+    pub fn new(options: DryRunOptions, persistent_shell: bool) -> Self {
+        DryRunPlan {
+            options,
+            persistent_shell,
+            calls: Vec::new(),
+        }
+    }
+
+    /// Records a call and returns the simulated result, or `None` when the call should
+    /// run because it is read-only and `run_read_only` is set.
+    pub fn intercept(&mut self, tool: &str, arguments: &str, read_only: bool) -> Option<String> {
+        let call = PlannedCall {
+            tool: tool.to_string(),
+            arguments: arguments.to_string(),
+            executed: read_only && self.options.run_read_only,
+        };
+        let result = if call.executed {
+            None
+        } else {
+            println!("[dry run] {}", describe(&call));
+            Some(NOT_EXECUTED.to_string())
+        };
+        self.calls.push(call);
+        result
+    }
+
+    pub fn summary(&self) -> String {
+        if self.calls.is_empty() {
+            return "Dry run: no tool calls were made.".to_string();
+        }
+
+        let executed = self.calls.iter().filter(|call| call.executed).count();
+        let mut summary = format!(
+            "## Dry run plan\n\n{} tool call(s), {} not executed",
+            self.calls.len(),
+            self.calls.len() - executed
+        );
+        if executed > 0 {
+            summary.push_str(&format!(", {executed} read-only call(s) executed"));
+        }
+        summary.push_str("\n\n");
+
+        for (index, call) in self.calls.iter().enumerate() {
+            let marker = if call.executed { "  [executed]" } else { "" };
+            summary.push_str(&format!("{}. {}{marker}\n", index + 1, describe(call)));
+        }
+        summary
+    }
+
+    /// The planned commands as a shell script. Other tool calls are listed as comments.
+    pub fn script(&self, question: &str) -> String {
+        let mut script = format!(
+            "#!/bin/sh\n# Planned by `ask --dry-run` on {}\n",
+            chrono::Local::now().format("%Y-%m-%d %H:%M")
+        );
+        for line in question.trim().lines() {
+            script.push_str(&format!("# {line}\n"));
+        }
+        script.push_str("set -e\n\n");
+
+        for call in &self.calls {
+            let Some(request) = command_of(call) else {
+                script.push_str(&format!(
+                    "# {}: {} {}\n",
+                    if call.executed {
+                        "Tool call (executed)"
+                    } else {
+                        "Tool call"
+                    },
+                    call.tool,
+                    call.arguments
+                ));
+                continue;
+            };
+
+            let directory = Some(request.working_directory.as_str())
+                .filter(|dir| !dir.is_empty() && *dir != ".");
+            match (self.persistent_shell, directory) {
+                (true, Some(dir)) => {
+                    script.push_str(&format!("cd {}\n{}\n", shell_quote(dir), request.command))
+                }
+                (true, None) => script.push_str(&format!("{}\n", request.command)),
+                (false, Some(dir)) => script.push_str(&format!(
+                    "(cd {} && {})\n",
+                    shell_quote(dir),
+                    request.command
+                )),
+                (false, None) => script.push_str(&format!("({})\n", request.command)),
+            }
+        }
+        script
+    }
+
+    pub fn write_script(&self, path: &std::path::Path, question: &str) -> Result<()> {
+        std::fs::write(path, self.script(question))
+            .context(format!("Failed to write plan script to {:?}", path))?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o755))
+                .context(format!("Failed to make {:?} executable", path))?;
+        }
+        Ok(())
+    }
+}
+
+/// The command and its directory, or the tool and its arguments.
+fn describe(call: &PlannedCall) -> String {
+    match command_of(call) {
+        Some(request) if request.working_directory.is_empty() => request.command,
+        Some(request) => format!("{}  (in {})", request.command, request.working_directory),
+        None => format!("{} {}", call.tool, truncate(&call.arguments, 100)),
+    }
+}
+
+fn command_of(call: &PlannedCall) -> Option<ExecuteCommandRequest> {
+    if call.tool != "execute_command" {
+        return None;
+    }
+    serde_json::from_str(&call.arguments).ok()
+}
+
+fn truncate(text: &str, max_chars: usize) -> String {
+    if text.chars().count() <= max_chars {
+        text.to_string()
+    } else {
+        format!("{}…", text.chars().take(max_chars).collect::<String>())
+    }
+}
+
+fn shell_quote(text: &str) -> String {
+    format!("'{}'", text.replace('\'', "'\\''"))
+}
@@ -0,0 +1,101 @@
+use anyhow::{Context, Result, bail};
+use std::fs;
+use std::hash::{BuildHasher, Hasher};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// A directory only the current user can read, removed again when dropped.
+struct PrivateTempDir {
+    path: PathBuf,
+}
+
+impl PrivateTempDir {
+    /// Creates a new directory with an unpredictable name, so other users can neither
+    /// guess nor plant the files written to it.
+    fn new() -> Result<Self> {
+        let mut builder = fs::DirBuilder::new();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::DirBuilderExt;
+            builder.mode(0o700);
+        }
+        for _ in 0..10 {
+            let random = std::collections::hash_map::RandomState::new()
+                .build_hasher()
+                .finish();
+            let path = std::env::temp_dir().join(format!("ask-{random:016x}"));
+            match builder.create(&path) {
+                Ok(()) => return Ok(Self { path }),
+                Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => continue,
+                Err(e) => return Err(e).context(format!("Failed to create {:?}", path)),
+            }
+        }
+        bail!("Failed to create a temporary directory")
+    }
+
+    fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for PrivateTempDir {
+    fn drop(&mut self) {
+        if let Err(e) = fs::remove_dir_all(&self.path) {
+            eprintln!("Warning: Failed to remove {:?}: {}", self.path, e);
+        }
+    }
+}
+
+/// `$VISUAL`, then `$EDITOR`, then the platform default.
+fn editor_command() -> String {
+    std::env::var("VISUAL")
+        .or_else(|_| std::env::var("EDITOR"))
+        .ok()
+        .filter(|editor| !editor.trim().is_empty())
+        .unwrap_or_else(|| {
+            if cfg!(windows) {
+                "notepad".to_string()
+            } else {
+                "vi".to_string()
+            }
+        })
+}
+
+/// Opens `text` in the user's editor and returns the saved result. `file_name` names
+/// the temporary file so editors can pick a syntax, e.g. `COMMIT_EDITMSG`.
+pub fn edit_text(text: &str, file_name: &str) -> Result<String> {
+    let dir = PrivateTempDir::new()?;
+    let path = dir.path().join(file_name);
+    fs::write(&path, text).context(format!("Failed to write {:?}", path))?;
+
+    let editor = editor_command();
+    // The editor may carry arguments (`code --wait`), so it is run through the shell.
+    let status = if cfg!(windows) {
+        Command::new("cmd")
+            .arg("/C")
+            .arg(format!("{} \"{}\"", editor, path.display()))
+            .status()
+    } else {
+        Command::new("sh")
+            .arg("-c")
+            .arg(format!("{editor} \"$1\""))
+            .arg("sh")
+            .arg(&path)
+            .status()
+    };
+
+    let result = match status {
+        Ok(status) if status.success() => {
+            fs::read_to_string(&path).context(format!("Failed to read {:?}", path))
+        }
+        Ok(status) => Err(anyhow::anyhow!("Editor '{editor}' exited with {status}")),
+        Err(e) => Err(anyhow::anyhow!("Failed to start editor '{editor}': {e}")),
+    };
+    drop(dir);
+
+    let edited = result?;
+    if edited.trim().is_empty() {
+        bail!("The edited text is empty");
+    }
+    Ok(edited)
+}
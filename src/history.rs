@@ -0,0 +1,185 @@
+#[cfg(feature = "history")]
+use crate::git::find_git_root;
+#[cfg(feature = "history")]
+use anyhow::Context;
+use anyhow::Result;
+use chrono::{DateTime, Local};
+#[cfg(feature = "history")]
+use std::path::PathBuf;
+
+/// Whether runs are recorded, i.e. whether ask was built with the `history` feature.
+pub const ENABLED: bool = cfg!(feature = "history");
+
+/// Everything recorded about a single `ask` run.
+#[cfg_attr(not(feature = "history"), allow(dead_code))]
+pub struct RunRecord {
+    pub started: DateTime<Local>,
+    pub question: String,
+    /// The session the run was saved to.
+    pub session: Option<String>,
+    pub model: String,
+    pub prompt_tokens: u32,
+    pub completion_tokens: u32,
+    /// Names of the tools called, in order.
+    pub tool_calls: Vec<String>,
+    pub succeeded: bool,
+}
+
+/// A past run as listed by `ask history`.
+#[cfg_attr(not(feature = "history"), allow(dead_code))]
+pub struct HistoryEntry {
+    pub started: DateTime<Local>,
+    pub cwd: String,
+    pub session: Option<String>,
+    pub model: String,
+    pub question: String,
+    pub total_tokens: u32,
+    pub tool_calls: Vec<String>,
+    pub succeeded: bool,
+}
+
+#[cfg(feature = "history")]
+fn open_database() -> Result<rusqlite::Connection> {
+    let db_path: PathBuf = shellexpand::tilde("~/.ask/history.db")
+        .into_owned()
+        .parse()
+        .context("Failed to parse history database path")?;
+    if let Some(dir) = db_path.parent() {
+        std::fs::create_dir_all(dir).context(format!("Failed to create directory {:?}", dir))?;
+    }
+
+    let connection = rusqlite::Connection::open(&db_path)
+        .context(format!("Failed to open history database {:?}", db_path))?;
+    // Several `ask` processes may finish at the same time.
+    connection.busy_timeout(std::time::Duration::from_secs(5))?;
+    connection
+        .execute_batch(
+            "CREATE TABLE IF NOT EXISTS runs (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                started_at TEXT NOT NULL,
+                cwd TEXT NOT NULL,
+                git_root TEXT,
+                session TEXT,
+                model TEXT NOT NULL,
+                question TEXT NOT NULL,
+                prompt_tokens INTEGER NOT NULL,
+                completion_tokens INTEGER NOT NULL,
+                tool_calls TEXT NOT NULL,
+                succeeded INTEGER NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS runs_cwd ON runs (cwd);
+            CREATE INDEX IF NOT EXISTS runs_git_root ON runs (git_root);",
+        )
+        .context("Failed to create history tables")?;
+    Ok(connection)
+}
+
+#[cfg(feature = "history")]
+fn current_dir() -> Result<PathBuf> {
+    std::env::current_dir().context("Failed to get current directory")
+}
+
+/// Stores a finished run together with the current directory and its git repository.
+#[cfg(feature = "history")]
+pub fn record_run(run: &RunRecord) -> Result<()> {
+    let cwd = current_dir()?;
+    let git_root = find_git_root(&cwd).map(|root| root.display().to_string());
+    let tool_calls = serde_json::to_string(&run.tool_calls)?;
+
+    open_database()?
+        .execute(
+            "INSERT INTO runs (started_at, cwd, git_root, session, model, question,
+                prompt_tokens, completion_tokens, tool_calls, succeeded)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+            rusqlite::params![
+                run.started.to_rfc3339(),
+                cwd.display().to_string(),
+                git_root,
+                run.session,
+                run.model,
+                run.question,
+                run.prompt_tokens,
+                run.completion_tokens,
+                tool_calls,
+                run.succeeded,
+            ],
+        )
+        .context("Failed to record run in history")?;
+    Ok(())
+}
+
+/// The most recent runs, newest first. Unless `all_projects` is set, only runs from the
+/// current git repository (or the current directory outside a repository) are included.
+#[cfg(feature = "history")]
+pub fn recent_runs(all_projects: bool, limit: usize) -> Result<Vec<HistoryEntry>> {
+    let cwd = current_dir()?;
+    let git_root = find_git_root(&cwd).map(|root| root.display().to_string());
+
+    let connection = open_database()?;
+    let mut statement = connection.prepare(
+        "SELECT started_at, cwd, session, model, question,
+                prompt_tokens + completion_tokens, tool_calls, succeeded
+         FROM runs
+         WHERE ?1 OR (?2 IS NOT NULL AND git_root = ?2) OR (?2 IS NULL AND cwd = ?3)
+         ORDER BY id DESC
+         LIMIT ?4",
+    )?;
+    let rows = statement.query_map(
+        rusqlite::params![
+            all_projects,
+            git_root,
+            cwd.display().to_string(),
+            limit as i64
+        ],
+        |row| {
+            let started: String = row.get(0)?;
+            let tool_calls: String = row.get(6)?;
+            Ok(HistoryEntry {
+                started: DateTime::parse_from_rfc3339(&started)
+                    .map(|started| started.with_timezone(&Local))
+                    .unwrap_or_else(|_| Local::now()),
+                cwd: row.get(1)?,
+                session: row.get(2)?,
+                model: row.get(3)?,
+                question: row.get(4)?,
+                total_tokens: row.get(5)?,
+                tool_calls: serde_json::from_str(&tool_calls).unwrap_or_default(),
+                succeeded: row.get(7)?,
+            })
+        },
+    )?;
+
+    rows.collect::<rusqlite::Result<Vec<_>>>()
+        .context("Failed to read history")
+}
+
+/// The session of the most recent run in the current directory. `None` when there is
+/// no such run or it used the terminal's default session.
+#[cfg(feature = "history")]
+pub fn last_session_in_dir() -> Option<String> {
+    let cwd = current_dir().ok()?;
+    let session: Option<String> = open_database()
+        .ok()?
+        .query_row(
+            "SELECT session FROM runs WHERE cwd = ?1 ORDER BY id DESC LIMIT 1",
+            [cwd.display().to_string()],
+            |row| row.get(0),
+        )
+        .ok()?;
+    session.filter(|name| name != crate::sessions::LAST_SESSION)
+}
+
+#[cfg(not(feature = "history"))]
+pub fn record_run(_run: &RunRecord) -> Result<()> {
+    Ok(())
+}
+
+#[cfg(not(feature = "history"))]
+pub fn recent_runs(_all_projects: bool, _limit: usize) -> Result<Vec<HistoryEntry>> {
+    anyhow::bail!("History is not available: ask was built without the `history` feature")
+}
+
+#[cfg(not(feature = "history"))]
+pub fn last_session_in_dir() -> Option<String> {
+    None
+}
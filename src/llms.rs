@@ -1,28 +1,44 @@
-use crate::approval;
+use crate::approval::{self, ApprovalSource};
+use crate::audit::{AuditDecision, AuditLog, CallRecord};
+use crate::checkpoints::Checkpoint;
 use crate::config;
-use crate::config::AskConfig;
-use crate::sessions::{get_session, save_session};
-use crate::shell::detect_shell_kind;
-use crate::tools::mcp::execute_mcp_tool_call;
+use crate::config::{AskConfig, SessionRetention};
+use crate::diff;
+use crate::dry_run::{DryRunOptions, DryRunPlan};
+use crate::history::{self, RunRecord};
+use crate::prompt::build_system_prompt;
+use crate::sessions::{self, LAST_SESSION, get_session_data, save_session};
+use crate::shell::{ShellInfo, shell_info};
+use crate::tools::command_risk;
+use crate::tools::mcp::{ToolRoute, execute_mcp_tool_call};
+use crate::tools::mcp_client::{AskClientHandler, DEFAULT_SAMPLING_MAX_TOKENS, SamplingSettings};
+use crate::tools::native::{self, NativeTools};
+use crate::tools::persistent_shell::{DEFAULT_COMMAND_TIMEOUT_SECS, PersistentShell};
+use crate::tools::schema::validate_arguments;
 use crate::tools::tool_cache::{McpRegistry, load_cached_tools, populate_cache_if_needed};
-use crate::tools::{ExecuteCommandRequest, execute_command_tool};
+use crate::tools::{ExecuteCommandRequest, execute_command_tool, matches_tool_pattern};
 use async_openai::types::{
     ChatCompletionMessageToolCall, ChatCompletionRequestAssistantMessageArgs,
     ChatCompletionRequestMessage, ChatCompletionRequestSystemMessageArgs,
     ChatCompletionRequestSystemMessageContent, ChatCompletionRequestToolMessageArgs,
     ChatCompletionRequestToolMessageContent, ChatCompletionRequestUserMessageArgs,
     ChatCompletionRequestUserMessageContent, ChatCompletionResponseMessage,
-    ChatCompletionToolChoiceOption, ChatCompletionToolType, CreateChatCompletionRequestArgs,
-    FinishReason, FunctionCall, Role,
+    ChatCompletionStreamOptions, ChatCompletionToolChoiceOption, ChatCompletionToolType,
+    CompletionUsage, CreateChatCompletionRequestArgs, FinishReason, FunctionCall, Role,
 };
 use async_openai::{Client, config::OpenAIConfig};
 use futures::StreamExt;
 use serde_json::Value;
-use std::collections::HashMap;
+use std::collections::HashSet;
 use std::env;
 use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
 use tokio::sync::Mutex as AsyncMutex;
 
+/// Consecutive tool calls with invalid arguments tolerated before the run is aborted.
+const MAX_INVALID_TOOL_CALLS: usize = 3;
+
 fn get_api_key(base_url: &Option<String>, verbose: bool) -> Result<String, anyhow::Error> {
     if verbose {
         println!("Checking for API keys...");
@@ -74,7 +90,7 @@ fn get_api_key(base_url: &Option<String>, verbose: bool) -> Result<String, anyho
 
     Err(anyhow::anyhow!(error_msg))
 }
-fn get_openai_client(
+pub(crate) fn get_openai_client(
     base_url: &Option<String>,
     verbose: &bool,
 ) -> Result<Client<OpenAIConfig>, anyhow::Error> {
@@ -95,14 +111,57 @@ fn get_openai_client(
     Ok(client)
 }
 
-pub async fn ask_question(
-    question: &str,
-    model: Option<String>,
-    session: Option<String>,
-    mut max_iterations: usize,
+/// Per-run settings for `ask_question`.
+pub struct AskOptions {
+    pub model: Option<String>,
+    pub session: Option<String>,
+    pub max_iterations: usize,
+    pub verbose: bool,
+    pub stream: bool,
+    /// Neither save the run as a session nor record it in the history.
+    pub incognito: bool,
+    /// Replaces the built-in instructions at the start of the system prompt.
+    pub system_prompt: Option<String>,
+    /// Only offer the tools matching one of these names (`*` matches any characters).
+    pub allowed_tools: Option<Vec<String>>,
+    /// Run commands in one shell for the whole run; also enabled by `persistentShell`.
+    pub persistent_shell: bool,
+    /// Record tool calls instead of running them.
+    pub dry_run: Option<DryRunOptions>,
+}
+
+/// State shared by the tool calls of one run.
+struct ToolContext {
+    offered_tools: HashSet<String>,
+    /// Runs `execute_command` calls when the persistent shell is enabled.
+    shell: Option<PersistentShell>,
+    /// The built-in file tools.
+    native_tools: NativeTools,
+    /// The calls planned so far when running with `--dry-run`.
+    dry_run: Option<DryRunPlan>,
+    /// Backs up files before approved calls change them.
+    checkpoint: Option<Checkpoint>,
+    /// Records every tool call, unless the audit log is disabled.
+    audit: Option<AuditLog>,
+    /// Consecutive calls with invalid arguments.
+    invalid_tool_calls: usize,
     verbose: bool,
-    stream: bool,
-) -> Result<String, anyhow::Error> {
+}
+
+pub async fn ask_question(question: &str, options: AskOptions) -> Result<String, anyhow::Error> {
+    let AskOptions {
+        model,
+        session,
+        mut max_iterations,
+        verbose,
+        stream,
+        incognito,
+        system_prompt,
+        allowed_tools,
+        persistent_shell,
+        dry_run,
+    } = options;
+
     let config = config::load_config().unwrap_or_else(|e| {
         if verbose {
             println!("Failed to load MCP config: {e}");
@@ -111,15 +170,7 @@ pub async fn ask_question(
             eprintln!("Warning: Failed to load MCP config: {e}");
             eprintln!("Continuing without MCP tools. Create ~/.ask/config to enable MCP servers.");
         }
-        AskConfig {
-            base_url: None,
-            auto_approved_tools: Vec::new(),
-            mcp_servers: HashMap::new(),
-            model: None,
-            model_aliases: HashMap::new(),
-            presets: HashMap::new(),
-            stream: None,
-        }
+        AskConfig::default()
     });
 
     if verbose {
@@ -155,38 +206,137 @@ pub async fn ask_question(
         println!("  Final model: {}", selected_model);
     }
 
-    // Initialize auto-approved tools from config
-    approval::initialize_from_config(&config.auto_approved_tools);
+    // Approvals stored for this session, this project or everywhere
+    approval::initialize(&config, session.as_deref());
 
     let client = get_openai_client(&config.base_url, &verbose)?;
-    let shell = detect_shell_kind();
-
-    let mut registry = McpRegistry::from_servers(config::config_to_servers(&config));
+    let shell = shell_info();
+
+    let sampling = SamplingSettings {
+        base_url: config.base_url.clone(),
+        model: selected_model.clone(),
+        max_tokens: config
+            .sampling_max_tokens
+            .unwrap_or(DEFAULT_SAMPLING_MAX_TOKENS),
+    };
+    let native_tools = NativeTools::new(config.native_tools.as_ref());
+    let mut registry = McpRegistry::from_servers(config::config_to_servers(&config))?
+        .with_handler(AskClientHandler::new(
+            Some(sampling),
+            config.mcp_log_level,
+            verbose,
+        ))
+        .with_builtin_tools(
+            std::iter::once("execute_command").chain(native_tools.names().iter().copied()),
+        );
 
     // Populate cache if needed (first run only)
     if let Err(e) = populate_cache_if_needed(&mut registry, verbose).await {
         eprintln!("Warning: Failed to populate cache: {e}");
     }
 
+    // Held until the run returns, so another run can't continue the same session
+    // meanwhile. Incognito runs never write the session back and don't need it.
+    let _reservation = if incognito {
+        None
+    } else {
+        Some(sessions::reserve_session(
+            session.as_deref().unwrap_or(LAST_SESSION),
+        )?)
+    };
+
+    let (session_messages, shell_state) = match session.as_deref().and_then(get_session_data) {
+        Some(data) => (Some(data.messages), data.shell),
+        None => (None, None),
+    };
+
+    // A session continues in the directory and environment its shell was left in.
+    let command_shell = if persistent_shell || config.persistent_shell.unwrap_or(false) {
+        let timeout = std::time::Duration::from_secs(
+            config
+                .command_timeout
+                .unwrap_or(DEFAULT_COMMAND_TIMEOUT_SECS),
+        );
+        match PersistentShell::new(shell_state, timeout) {
+            Ok(shell) => Some(shell),
+            Err(e) => {
+                eprintln!("Warning: {e:#}. Each command runs in a new shell.");
+                None
+            }
+        }
+    } else {
+        None
+    };
+
     // Load tools from cache (fast)
-    let mut tools = vec![execute_command_tool()];
-    tools.extend(load_cached_tools(&registry, verbose));
+    let mut tools = vec![execute_command_tool(command_shell.is_some())];
+    tools.extend(native_tools.definitions());
+    tools.extend(load_cached_tools(&mut registry, verbose)?);
+    if let Some(patterns) = &allowed_tools {
+        tools.retain(|tool| {
+            patterns
+                .iter()
+                .any(|pattern| matches_tool_pattern(pattern, &tool.function.name))
+        });
+        if verbose {
+            println!("Tools limited by preset to {} tool(s)", tools.len());
+        }
+    }
+    // Incognito runs read the session they continue but never write anything back.
+    let save_target = if incognito {
+        None
+    } else {
+        Some(session.clone().unwrap_or_else(|| LAST_SESSION.to_string()))
+    };
 
-    let mut messages = match &session {
-        Some(session_name) => {
-            let session_messages = get_session(session_name);
+    let mut tool_context = ToolContext {
+        offered_tools: tools
+            .iter()
+            .map(|tool| tool.function.name.clone())
+            .collect(),
+        dry_run: dry_run.map(|options| DryRunPlan::new(options, command_shell.is_some())),
+        checkpoint: if config.checkpoints.unwrap_or(true) {
+            Checkpoint::new(question, config.checkpoint_commands.unwrap_or(false))
+                .inspect_err(|e| eprintln!("Warning: Changes will not be checkpointed: {e:#}"))
+                .ok()
+        } else {
+            None
+        },
+        shell: command_shell,
+        native_tools,
+        audit: AuditLog::new(
+            config.audit_log.as_ref(),
+            save_target.clone(),
+            selected_model.clone(),
+        ),
+        invalid_tool_calls: 0,
+        verbose,
+    };
 
+    let mut messages = match &session {
+        Some(_) => {
             match session_messages {
-                Some(messages) => messages,
+                Some(mut messages) => {
+                    // The system prompt is rebuilt on every run so instructions, project
+                    // files and the environment are current.
+                    let system_message = get_system_message(shell, system_prompt.as_deref());
+                    match messages.first_mut() {
+                        Some(first @ ChatCompletionRequestMessage::System(_)) => {
+                            *first = system_message
+                        }
+                        _ => messages.insert(0, system_message),
+                    }
+                    messages
+                }
                 None => {
                     if verbose {
                         eprintln!("Session not loaded");
                     }
-                    get_base_messages(&shell)
+                    get_base_messages(shell, system_prompt.as_deref())
                 }
             }
         }
-        None => get_base_messages(&shell),
+        None => get_base_messages(shell, system_prompt.as_deref()),
     };
 
     messages.push(
@@ -223,227 +373,490 @@ pub async fn ask_question(
     // Wrap registry in async Mutex for interior mutability (safe across await points)
     let registry = AsyncMutex::new(registry);
 
-    let mut i = 0;
-    let mut last_response_message: Option<ChatCompletionResponseMessage>;
-    loop {
-        let (should_continue, result) = if stream {
-            let stream_result =
-                stream_chat_completion(&client, &req, verbose, &selected_model).await?;
-
-            #[allow(deprecated)]
-            let base_response_message =
-                |content: Option<String>,
-                 tool_calls: Option<Vec<ChatCompletionMessageToolCall>>| {
-                    ChatCompletionResponseMessage {
-                        content,
-                        refusal: None,
-                        tool_calls,
-                        role: Role::Assistant,
-                        function_call: None,
-                        audio: None,
-                    }
-                };
-
-            match stream_result.finish_reason {
-                None | Some(FinishReason::Stop) => {
-                    let response_message =
-                        base_response_message(Some(stream_result.content.clone()), None);
-                    last_response_message = Some(response_message.clone());
-
-                    save_session_if_needed(&session, &req.messages, &response_message, verbose);
+    let mut run = RunRecord {
+        started: chrono::Local::now(),
+        question: question.to_string(),
+        session: save_target.clone(),
+        model: selected_model.clone(),
+        prompt_tokens: 0,
+        completion_tokens: 0,
+        tool_calls: Vec::new(),
+        succeeded: false,
+    };
 
-                    (false, Some(stream_result.content))
+    // Token counts are only kept by the history, so they are only requested for it.
+    let record_usage = history::ENABLED && !incognito;
+
+    let result: Result<String, anyhow::Error> = async {
+        let mut i = 0;
+        let mut last_response_message: Option<ChatCompletionResponseMessage>;
+        loop {
+            let (should_continue, result) = if stream {
+                let stream_result =
+                    stream_chat_completion(&client, &req, verbose, &selected_model, record_usage)
+                        .await?;
+                if let Some(usage) = &stream_result.usage {
+                    run.prompt_tokens += usage.prompt_tokens;
+                    run.completion_tokens += usage.completion_tokens;
                 }
-                Some(FinishReason::Length) => {
-                    let response_message =
-                        base_response_message(Some(stream_result.content.clone()), None);
-                    last_response_message = Some(response_message.clone());
 
-                    save_session_if_needed(&session, &req.messages, &response_message, verbose);
-                    (false, None)
-                }
-                Some(FinishReason::ToolCalls) => {
-                    let tool_calls = stream_result
-                        .tool_calls
-                        .ok_or_else(|| anyhow::anyhow!("Tool calls expected but none received"))?;
-                    last_response_message =
-                        Some(base_response_message(None, Some(tool_calls.clone())));
-
-                    let assistant_msg = ChatCompletionRequestAssistantMessageArgs::default()
-                        .tool_calls(tool_calls.clone())
-                        .build()
-                        .map_err(|e| anyhow::anyhow!(e.to_string()))?;
-                    req.messages
-                        .push(ChatCompletionRequestMessage::Assistant(assistant_msg));
-
-                    for tool_call in tool_calls {
-                        let (id, result) = execute_tool_call(tool_call, &registry, verbose);
-                        let tool_msg = ChatCompletionRequestToolMessageArgs::default()
-                            .tool_call_id(id)
-                            .content(ChatCompletionRequestToolMessageContent::Text(result))
+                #[allow(deprecated)]
+                let base_response_message =
+                    |content: Option<String>,
+                     tool_calls: Option<Vec<ChatCompletionMessageToolCall>>| {
+                        ChatCompletionResponseMessage {
+                            content,
+                            refusal: None,
+                            tool_calls,
+                            role: Role::Assistant,
+                            function_call: None,
+                            audio: None,
+                        }
+                    };
+
+                match stream_result.finish_reason {
+                    None | Some(FinishReason::Stop) => {
+                        let response_message =
+                            base_response_message(Some(stream_result.content.clone()), None);
+                        last_response_message = Some(response_message.clone());
+
+                        save_session_if_needed(&save_target, &req, &response_message, verbose);
+
+                        (false, Some(stream_result.content))
+                    }
+                    Some(FinishReason::Length) => {
+                        let response_message =
+                            base_response_message(Some(stream_result.content.clone()), None);
+                        last_response_message = Some(response_message.clone());
+
+                        save_session_if_needed(&save_target, &req, &response_message, verbose);
+                        (false, None)
+                    }
+                    Some(FinishReason::ToolCalls) => {
+                        let tool_calls = stream_result
+                            .tool_calls
+                            .ok_or_else(|| anyhow::anyhow!("Tool calls expected but none received"))?;
+                        last_response_message =
+                            Some(base_response_message(None, Some(tool_calls.clone())));
+
+                        let assistant_msg = ChatCompletionRequestAssistantMessageArgs::default()
+                            .tool_calls(tool_calls.clone())
                             .build()
                             .map_err(|e| anyhow::anyhow!(e.to_string()))?;
                         req.messages
-                            .push(ChatCompletionRequestMessage::Tool(tool_msg));
+                            .push(ChatCompletionRequestMessage::Assistant(assistant_msg));
+
+                        for tool_call in tool_calls {
+                            run.tool_calls.push(tool_call.function.name.clone());
+                            let explainer = Explainer {
+                                client: &client,
+                                model: &selected_model,
+                                messages: &req.messages,
+                            };
+                            let (id, result) = execute_tool_call(
+                                tool_call,
+                                &mut tool_context,
+                                &registry,
+                                &explainer,
+                            )?;
+                            let tool_msg = ChatCompletionRequestToolMessageArgs::default()
+                                .tool_call_id(id)
+                                .content(ChatCompletionRequestToolMessageContent::Text(result))
+                                .build()
+                                .map_err(|e| anyhow::anyhow!(e.to_string()))?;
+                            req.messages
+                                .push(ChatCompletionRequestMessage::Tool(tool_msg));
+                        }
+
+                        (true, None)
                     }
+                    _ => {
+                        let response_message =
+                            base_response_message(Some(stream_result.content.clone()), None);
+                        last_response_message = Some(response_message.clone());
 
-                    (true, None)
+                        save_session_if_needed(&save_target, &req, &response_message, verbose);
+                        (false, None)
+                    }
                 }
-                _ => {
-                    let response_message =
-                        base_response_message(Some(stream_result.content.clone()), None);
-                    last_response_message = Some(response_message.clone());
+            } else {
+                let response = match client.chat().create(req.clone()).await {
+                    Ok(r) => r,
+                    Err(e) => {
+                        let error_str = e.to_string();
+                        if verbose {
+                            eprintln!("OpenAI API Error: {}", error_str);
+                        }
+
+                        if error_str.contains("400") || error_str.contains("invalid type: integer") {
+                            return Err(anyhow::anyhow!(
+                                "API request failed with 400 error. This might be due to:\n\
+                                 1. Invalid model name: '{}'\n\
+                                 2. Request format issues\n\
+                                 3. API rate limits or permissions\n\n\
+                                 Original error: {}",
+                                selected_model,
+                                error_str
+                            ));
+                        }
+
+                        return Err(anyhow::anyhow!("OpenAI API Error: {}", error_str));
+                    }
+                };
 
-                    save_session_if_needed(&session, &req.messages, &response_message, verbose);
-                    (false, None)
+                if let Some(usage) = &response.usage {
+                    run.prompt_tokens += usage.prompt_tokens;
+                    run.completion_tokens += usage.completion_tokens;
                 }
-            }
-        } else {
-            let response = match client.chat().create(req.clone()).await {
-                Ok(r) => r,
-                Err(e) => {
-                    let error_str = e.to_string();
-                    if verbose {
-                        eprintln!("OpenAI API Error: {}", error_str);
+                last_response_message = Some(response.choices[0].message.clone());
+
+                match response.choices[0].finish_reason {
+                    None => {
+                        save_session_if_needed(
+                            &save_target,
+                            &req,
+                            &response.choices[0].message,
+                            verbose,
+                        );
+
+                        (
+                            false,
+                            Some(response.choices[0].message.content.clone().unwrap()),
+                        )
                     }
-
-                    if error_str.contains("400") || error_str.contains("invalid type: integer") {
-                        return Err(anyhow::anyhow!(
-                            "API request failed with 400 error. This might be due to:\n\
-                             1. Invalid model name: '{}'\n\
-                             2. Request format issues\n\
-                             3. API rate limits or permissions\n\n\
-                             Original error: {}",
-                            selected_model,
-                            error_str
-                        ));
+                    Some(FinishReason::Stop) => {
+                        save_session_if_needed(
+                            &save_target,
+                            &req,
+                            &response.choices[0].message,
+                            verbose,
+                        );
+                        (
+                            false,
+                            Some(response.choices[0].message.content.clone().unwrap()),
+                        )
+                    }
+                    Some(FinishReason::Length) => {
+                        save_session_if_needed(
+                            &save_target,
+                            &req,
+                            &response.choices[0].message,
+                            verbose,
+                        );
+                        (false, None)
                     }
+                    Some(FinishReason::ToolCalls) => {
+                        let tool_calls = response.choices[0].message.tool_calls.clone().unwrap();
 
-                    return Err(anyhow::anyhow!("OpenAI API Error: {}", error_str));
+                        let assistant_msg = ChatCompletionRequestAssistantMessageArgs::default()
+                            .tool_calls(tool_calls.clone())
+                            .build()
+                            .map_err(|e| anyhow::anyhow!(e.to_string()))?;
+                        req.messages
+                            .push(ChatCompletionRequestMessage::Assistant(assistant_msg));
+
+                        for tool_call in tool_calls {
+                            run.tool_calls.push(tool_call.function.name.clone());
+                            let explainer = Explainer {
+                                client: &client,
+                                model: &selected_model,
+                                messages: &req.messages,
+                            };
+                            let (id, result) = execute_tool_call(
+                                tool_call,
+                                &mut tool_context,
+                                &registry,
+                                &explainer,
+                            )?;
+                            let tool_msg = ChatCompletionRequestToolMessageArgs::default()
+                                .tool_call_id(id)
+                                .content(ChatCompletionRequestToolMessageContent::Text(result))
+                                .build()
+                                .map_err(|e| anyhow::anyhow!(e.to_string()))?;
+                            req.messages
+                                .push(ChatCompletionRequestMessage::Tool(tool_msg));
+                        }
+
+                        (true, None)
+                    }
+                    _ => {
+                        save_session_if_needed(
+                            &save_target,
+                            &req,
+                            &response.choices[0].message,
+                            verbose,
+                        );
+                        (false, None)
+                    }
                 }
             };
 
-            last_response_message = Some(response.choices[0].message.clone());
+            if !should_continue {
+                return match result {
+                    Some(r) => Ok(r),
+                    None => Err(anyhow::anyhow!("Response too long")),
+                };
+            }
 
-            match response.choices[0].finish_reason {
-                None => {
-                    save_session_if_needed(
-                        &session,
-                        &req.messages,
-                        &response.choices[0].message,
-                        verbose,
-                    );
-
-                    (
-                        false,
-                        Some(response.choices[0].message.content.clone().unwrap()),
-                    )
-                }
-                Some(FinishReason::Stop) => {
-                    save_session_if_needed(
-                        &session,
-                        &req.messages,
-                        &response.choices[0].message,
-                        verbose,
-                    );
-                    (
-                        false,
-                        Some(response.choices[0].message.content.clone().unwrap()),
-                    )
-                }
-                Some(FinishReason::Length) => {
-                    save_session_if_needed(
-                        &session,
-                        &req.messages,
-                        &response.choices[0].message,
-                        verbose,
-                    );
-                    (false, None)
-                }
-                Some(FinishReason::ToolCalls) => {
-                    let tool_calls = response.choices[0].message.tool_calls.clone().unwrap();
-
-                    let assistant_msg = ChatCompletionRequestAssistantMessageArgs::default()
-                        .tool_calls(tool_calls.clone())
-                        .build()
-                        .map_err(|e| anyhow::anyhow!(e.to_string()))?;
-                    req.messages
-                        .push(ChatCompletionRequestMessage::Assistant(assistant_msg));
-
-                    for tool_call in tool_calls {
-                        let (id, result) = execute_tool_call(tool_call, &registry, verbose);
-                        let tool_msg = ChatCompletionRequestToolMessageArgs::default()
-                            .tool_call_id(id)
-                            .content(ChatCompletionRequestToolMessageContent::Text(result))
-                            .build()
-                            .map_err(|e| anyhow::anyhow!(e.to_string()))?;
-                        req.messages
-                            .push(ChatCompletionRequestMessage::Tool(tool_msg));
+            i += 1;
+
+            if i == max_iterations {
+                println!(
+                    "The LLM has been invoked {max_iterations} times. Do you want to continue (y/n)?"
+                );
+                let mut input = String::new();
+                std::io::stdin().read_line(&mut input).unwrap();
+
+                if input.trim().to_lowercase() == "y" {
+                    max_iterations *= 2;
+                } else {
+                    if let Some(response_message) = &last_response_message {
+                        save_session_if_needed(&save_target, &req, response_message, verbose);
                     }
+                    break;
+                }
+            }
+        }
 
-                    (true, None)
+        Err(anyhow::anyhow!(format!(
+            "No response after {max_iterations} attempts"
+        )))
+    }
+    .await;
+
+    // Stop every MCP server started during this run so no child processes linger.
+    registry.lock().await.shutdown().await;
+
+    if let Some(mut shell) = tool_context.shell.take()
+        && let Some(target) = &save_target
+        && sessions::session_exists(target)
+        && let Some(state) = shell.state()
+        && let Err(e) = sessions::save_shell_state(target, state)
+    {
+        eprintln!("Warning: Failed to save shell state: {e}");
+    }
+
+    if let Some(checkpoint) = tool_context.checkpoint.take()
+        && let Some(run_id) = checkpoint.finish()
+    {
+        eprintln!(
+            "Files changed by this run were checkpointed as {run_id}; restore them with `ask undo`."
+        );
+    }
+
+    // The plan follows the answer: printed after a streamed one, appended otherwise.
+    let result = match tool_context.dry_run.take() {
+        Some(plan) => {
+            if let Some(path) = &plan.options.script {
+                match plan.write_script(path, question) {
+                    Ok(()) => eprintln!("Plan script written to {}", path.display()),
+                    Err(e) => eprintln!("Warning: {e:#}"),
                 }
-                _ => {
-                    save_session_if_needed(
-                        &session,
-                        &req.messages,
-                        &response.choices[0].message,
-                        verbose,
-                    );
-                    (false, None)
+            }
+            match result {
+                Ok(answer) if stream => {
+                    println!("\n{}", plan.summary());
+                    Ok(answer)
+                }
+                Ok(answer) => Ok(format!("{}\n\n{}", answer.trim_end(), plan.summary())),
+                Err(e) => {
+                    eprintln!("{}", plan.summary());
+                    Err(e)
                 }
             }
-        };
+        }
+        None => result,
+    };
 
-        if !should_continue {
-            return match result {
-                Some(r) => Ok(r),
-                None => Err(anyhow::anyhow!("Response too long")),
-            };
+    if !incognito {
+        run.succeeded = result.is_ok();
+        if let Err(e) = history::record_run(&run) {
+            eprintln!("Warning: Failed to record run in history: {e}");
         }
 
-        i += 1;
+        if let Some(retention) = &config.session_retention {
+            apply_session_retention(retention, verbose);
+        }
+    }
 
-        if i == max_iterations {
-            println!(
-                "The LLM has been invoked {max_iterations} times. Do you want to continue (y/n)?"
-            );
-            let mut input = String::new();
-            std::io::stdin().read_line(&mut input).unwrap();
+    result
+}
 
-            if input.trim().to_lowercase() == "y" {
-                max_iterations *= 2;
-            } else {
-                if let Some(response_message) = &last_response_message {
-                    save_session_if_needed(&session, &req.messages, response_message, verbose);
-                }
-                break;
-            }
+/// A single completion without tools, sessions or history, for commands that only need
+/// generated text. `model` may be an alias; the configured model is used otherwise.
+pub async fn complete(
+    model: Option<String>,
+    system_prompt: &str,
+    prompt: &str,
+    verbose: bool,
+) -> Result<String, anyhow::Error> {
+    let config = config::load_config().unwrap_or_default();
+    let model = match model {
+        Some(model) => config.model_aliases.get(&model).cloned().unwrap_or(model),
+        None => config
+            .model
+            .clone()
+            .unwrap_or_else(|| "gpt-4.1-mini".to_string()),
+    };
+    if verbose {
+        println!("Using model: {model}");
+    }
+
+    let client = get_openai_client(&config.base_url, &verbose)?;
+    let request = CreateChatCompletionRequestArgs::default()
+        .model(model)
+        .messages(vec![
+            ChatCompletionRequestSystemMessageArgs::default()
+                .content(system_prompt)
+                .build()?
+                .into(),
+            ChatCompletionRequestUserMessageArgs::default()
+                .content(prompt)
+                .build()?
+                .into(),
+        ])
+        .build()?;
+
+    let response = client.chat().create(request).await?;
+    response
+        .choices
+        .into_iter()
+        .next()
+        .and_then(|choice| choice.message.content)
+        .ok_or_else(|| anyhow::anyhow!("The model returned no answer"))
+}
+
+fn apply_session_retention(retention: &SessionRetention, verbose: bool) {
+    let older_than = match retention.older_than.as_deref().map(sessions::parse_age) {
+        Some(Ok(age)) => Some(age),
+        Some(Err(e)) => {
+            eprintln!("Warning: Ignoring sessionRetention: {e}");
+            return;
+        }
+        None => None,
+    };
+
+    match sessions::prune_sessions_periodically(older_than, retention.keep) {
+        Ok(Some(pruned)) if verbose => {
+            println!("Pruned {} session(s) per sessionRetention", pruned.len());
         }
+        Ok(_) => {}
+        Err(e) => eprintln!("Warning: Failed to prune sessions: {e}"),
     }
+}
 
-    Err(anyhow::anyhow!(format!(
-        "No response after {max_iterations} attempts"
-    )))
+/// Tells the model the user refused a call, passing on their feedback.
+fn denied_result(message: &str, feedback: Option<String>) -> String {
+    match feedback {
+        Some(feedback) => format!("{message} The user said: {feedback}"),
+        None => message.to_string(),
+    }
 }
 
-fn execute_command_with_approval(arguments: &str, verbose: bool) -> String {
-    let args: ExecuteCommandRequest = match serde_json::from_str(arguments) {
+fn execute_command_with_approval(
+    arguments: &str,
+    shell: Option<&mut PersistentShell>,
+    mut checkpoint: Option<&mut Checkpoint>,
+    explain: &mut dyn FnMut(&str) -> Result<String, anyhow::Error>,
+    record: &mut CallRecord,
+    verbose: bool,
+) -> String {
+    let mut args: ExecuteCommandRequest = match serde_json::from_str(arguments) {
         Ok(args) => args,
         Err(e) => return format!("Error: Failed to parse command arguments: {}", e),
     };
 
-    let should_execute = approval::check_approval(&args.command, "", verbose);
+    // Relative paths in the command are resolved against where it will run.
+    let directory = match (&shell, args.working_directory.as_str()) {
+        (_, dir) if !dir.is_empty() => PathBuf::from(dir),
+        (Some(shell), _) => shell.cwd().to_path_buf(),
+        (None, _) => PathBuf::from("."),
+    };
+    let working_directory = args.working_directory.clone();
+    let render = |command: &str| {
+        let mut text = format!("$ {}", approval::highlight_command(command));
+        if !working_directory.is_empty() && working_directory != "." {
+            text.push_str(&format!("  \x1b[2m(in {working_directory})\x1b[0m"));
+        }
+        text
+    };
+    let decision = approval::check_approval(
+        approval::ApprovalRequest {
+            tool_name: None,
+            text: args.command.clone(),
+            file_name: "command.sh",
+            render: &render,
+            validate: &|command| {
+                if command.is_empty() {
+                    Err("The command is empty".to_string())
+                } else {
+                    Ok(())
+                }
+            },
+            dangers: &|command| command_risk::classify(command, &directory),
+        },
+        explain,
+        verbose,
+    );
+
+    let (command, outcome) = match &decision {
+        approval::Decision::Approve(command, _) => (command.as_str(), "ran"),
+        approval::Decision::Deny(_) => (args.command.as_str(), "denied"),
+    };
+    let dangers = command_risk::classify(command, &directory);
+    command_risk::log_classification(command, &directory, &dangers, outcome);
+    if verbose && !dangers.is_empty() {
+        eprintln!("Classified as dangerous: {}", dangers.join("; "));
+    }
+    record.dangers = dangers;
+
+    let mut note = String::new();
+    match decision {
+        approval::Decision::Approve(command, source) => {
+            record.decision = AuditDecision::Approved;
+            record.source = source;
+            if command != args.command {
+                note = format!(
+                    "The user edited the command before running it. It ran as: {command}\n"
+                );
+                args.command = command;
+                record.arguments = serde_json::to_string(&args).unwrap_or_default();
+            }
+        }
+        approval::Decision::Deny(feedback) => {
+            record.decision = AuditDecision::Denied;
+            record.source = ApprovalSource::Prompt;
+            return denied_result("Command execution canceled by user.", feedback);
+        }
+    }
 
-    if should_execute {
-        let cmd_result = crate::tools::execute_command(&args.command, &args.working_directory);
-        if cmd_result.is_empty() {
-            "Executed".to_string()
-        } else {
-            cmd_result
+    let scan = checkpoint
+        .as_deref_mut()
+        .and_then(|checkpoint| checkpoint.before_command());
+    let started = Instant::now();
+    let (cmd_result, exit_status) = match shell {
+        Some(shell) => {
+            let working_directory =
+                Some(args.working_directory.as_str()).filter(|dir| !dir.is_empty());
+            shell.run(&args.command, working_directory)
         }
+        None if args.working_directory.is_empty() => {
+            crate::tools::execute_command(&args.command, ".")
+        }
+        None => crate::tools::execute_command(&args.command, &args.working_directory),
+    };
+    record.duration = Some(started.elapsed());
+    record.exit_status = exit_status;
+    record.error = exit_status.is_none();
+    if let (Some(checkpoint), Some(scan)) = (checkpoint, scan)
+        && let Err(e) = checkpoint.after_command(scan, &args.command)
+    {
+        eprintln!("Warning: Failed to checkpoint changes: {e:#}");
+    }
+    if cmd_result.is_empty() {
+        format!("{note}Executed")
     } else {
-        "Command execution canceled by user.".to_string()
+        format!("{note}{cmd_result}")
     }
 }
 
@@ -452,10 +865,17 @@ async fn ensure_mcp_server_initialized(
     server_name: &str,
     verbose: bool,
 ) -> Result<(), String> {
-    if registry.get_service(server_name).is_some() {
+    if registry.is_service_alive(server_name) {
         return Ok(());
     }
 
+    if registry.get_service(server_name).is_some() {
+        return registry
+            .restart_service(server_name, verbose)
+            .await
+            .map_err(|e| format!("Failed to restart MCP server '{}': {}", server_name, e));
+    }
+
     if verbose {
         eprintln!("Initializing MCP server '{}'...", server_name);
     }
@@ -470,25 +890,82 @@ fn execute_mcp_tool(
     name: &str,
     arguments: &str,
     registry: &AsyncMutex<McpRegistry>,
+    checkpoint: Option<&mut Checkpoint>,
+    explain: &mut dyn FnMut(&str) -> Result<String, anyhow::Error>,
+    record: &mut CallRecord,
     verbose: bool,
 ) -> String {
-    let server_info = tokio::task::block_in_place(|| {
+    let route = tokio::task::block_in_place(|| {
         tokio::runtime::Handle::current().block_on(async {
             let reg = registry.lock().await;
-            reg.find_server_for_tool(name)
-                .map(|(name, config)| (name.to_string(), config.clone()))
+            reg.find_tool(name).cloned()
         })
     });
 
-    let Some((server_name, server_config)) = server_info else {
+    let Some(ToolRoute {
+        server: server_name,
+        tool: tool_name,
+        read_only,
+        input_schema,
+        ..
+    }) = route
+    else {
         return format!("Unknown tool: {}", name);
     };
 
-    let formatted_call = format_mcp_tool_call(name, arguments, verbose);
-    let should_execute = approval::check_approval(name, &formatted_call, verbose);
+    let decision = approval::check_approval(
+        approval::ApprovalRequest {
+            tool_name: Some(name),
+            text: arguments.to_string(),
+            file_name: "arguments.json",
+            render: &|arguments| format_mcp_tool_call(name, arguments, verbose),
+            validate: &|arguments| match validate_arguments(arguments, input_schema.as_ref()) {
+                Ok(validated) if validated.value.is_object() => Ok(()),
+                Ok(_) => Err("The arguments must be a JSON object".to_string()),
+                Err(e) => Err(e.problems.join("; ")),
+            },
+            dangers: &|_| Vec::new(),
+        },
+        explain,
+        verbose,
+    );
+    let edited_arguments = match decision {
+        approval::Decision::Approve(edited, source) => {
+            record.decision = AuditDecision::Approved;
+            record.source = source;
+            // Edits get the same repairs as the model's arguments.
+            let edited = if edited == arguments {
+                edited
+            } else {
+                match validate_arguments(&edited, input_schema.as_ref()) {
+                    Ok(validated) => validated.value.to_string(),
+                    Err(e) => {
+                        record.error = true;
+                        return format!("Error: {}", e.problems.join("; "));
+                    }
+                }
+            };
+            record.arguments = edited.clone();
+            edited
+        }
+        approval::Decision::Deny(feedback) => {
+            record.decision = AuditDecision::Denied;
+            record.source = ApprovalSource::Prompt;
+            return denied_result("MCP tool execution canceled by user.", feedback);
+        }
+    };
+    let note = if edited_arguments != arguments {
+        format!("The user edited the arguments before the call. It ran with: {edited_arguments}\n")
+    } else {
+        String::new()
+    };
+    let arguments = edited_arguments.as_str();
 
-    if !should_execute {
-        return "MCP tool execution canceled by user.".to_string();
+    if !read_only
+        && let Some(checkpoint) = checkpoint
+        && let Err(e) = checkpoint.before_file_tool(name, &tool_name, arguments)
+    {
+        eprintln!("Warning: Failed to checkpoint files: {e:#}");
     }
 
     // Initialize server lazily if not already initialized
@@ -500,56 +977,366 @@ fn execute_mcp_tool(
     });
 
     if let Err(e) = init_result {
+        record.error = true;
         return format!("Error: {}", e);
     }
 
-    let reg = tokio::task::block_in_place(|| {
-        tokio::runtime::Handle::current().block_on(async { registry.lock().await })
+    let started = Instant::now();
+    let mut result = call_mcp_service(registry, &server_name, &tool_name, arguments);
+
+    // A failed call on a dead transport means the server crashed; restart it. Only
+    // read-only calls are retried: the crashed call may already have made its changes.
+    let crashed = tokio::task::block_in_place(|| {
+        tokio::runtime::Handle::current()
+            .block_on(async { !registry.lock().await.is_service_alive(&server_name) })
     });
+    if result.is_err() && crashed {
+        let restart_result = tokio::task::block_in_place(|| {
+            tokio::runtime::Handle::current().block_on(async {
+                let mut reg = registry.lock().await;
+                ensure_mcp_server_initialized(&mut reg, &server_name, verbose).await
+            })
+        });
+
+        match restart_result {
+            Ok(()) if read_only => {
+                result = call_mcp_service(registry, &server_name, &tool_name, arguments)
+            }
+            Ok(()) => {
+                record.duration = Some(started.elapsed());
+                record.error = true;
+                return format!(
+                    "Error: MCP server '{server_name}' crashed during the call and was restarted. \
+                     The call was not retried because it may already have taken effect; check \
+                     its result before calling it again."
+                );
+            }
+            Err(e) => {
+                record.error = true;
+                return format!("Error: {}", e);
+            }
+        }
+    }
+    if result.is_ok() {
+        tokio::task::block_in_place(|| {
+            tokio::runtime::Handle::current()
+                .block_on(async { registry.lock().await.reset_restarts(&server_name) })
+        });
+    }
+    record.duration = Some(started.elapsed());
+    record.error = result.is_err();
 
-    if let Some(service) = reg.get_service(&server_name) {
-        match execute_mcp_tool_call(service, &server_config, name, arguments) {
-            Ok(response) => {
-                if verbose {
-                    eprintln!("\n[MCP Tool Response]");
-                    eprintln!("{}", response);
-                    eprintln!("[End MCP Tool Response]\n");
-                }
-                response
+    match result {
+        Ok(response) => {
+            if verbose {
+                eprintln!("\n[MCP Tool Response]");
+                eprintln!("{}", response);
+                eprintln!("[End MCP Tool Response]\n");
             }
-            Err(err) => format!("Error executing MCP tool {}: {}", name, err),
+            format!("{note}{response}")
         }
+        Err(err) => format!("Error executing MCP tool {}: {}", name, err),
+    }
+}
+
+fn execute_native_tool(
+    name: &str,
+    arguments: &str,
+    tools: &NativeTools,
+    checkpoint: Option<&mut Checkpoint>,
+    explain: &mut dyn FnMut(&str) -> Result<String, anyhow::Error>,
+    record: &mut CallRecord,
+    verbose: bool,
+) -> String {
+    let parse = |arguments: &str| match serde_json::from_str::<Value>(arguments) {
+        Ok(value @ Value::Object(_)) => Ok(value),
+        Ok(_) => Err("The arguments must be a JSON object".to_string()),
+        Err(e) => Err(format!("Invalid JSON: {e}")),
+    };
+    // Calls outside the roots, or patches that don't apply, are refused without asking.
+    let check = |arguments: &str| {
+        tools
+            .check(name, &parse(arguments)?)
+            .map_err(|e| format!("{e:#}"))
+    };
+    if let Err(e) = check(arguments) {
+        record.error = true;
+        return format!("Error: {e}");
+    }
+
+    let decision = approval::check_approval(
+        approval::ApprovalRequest {
+            tool_name: Some(name),
+            text: arguments.to_string(),
+            file_name: "arguments.json",
+            render: &|arguments| match parse(arguments) {
+                Ok(value) => tools.describe(name, &value),
+                Err(_) => format!("{name} {arguments}"),
+            },
+            validate: &check,
+            dangers: &|_| Vec::new(),
+        },
+        explain,
+        verbose,
+    );
+    let edited_arguments = match decision {
+        approval::Decision::Approve(edited, source) => {
+            record.decision = AuditDecision::Approved;
+            record.source = source;
+            record.arguments = edited.clone();
+            edited
+        }
+        approval::Decision::Deny(feedback) => {
+            record.decision = AuditDecision::Denied;
+            record.source = ApprovalSource::Prompt;
+            return denied_result("Tool call canceled by user.", feedback);
+        }
+    };
+    let note = if edited_arguments != arguments {
+        format!("The user edited the arguments before the call. It ran with: {edited_arguments}\n")
     } else {
-        format!("Error: MCP service '{}' not initialized", server_name)
+        String::new()
+    };
+    let arguments = match parse(&edited_arguments) {
+        Ok(arguments) => arguments,
+        Err(e) => {
+            record.error = true;
+            return format!("Error: {e}");
+        }
+    };
+
+    if let Some(checkpoint) = checkpoint {
+        let result = tools
+            .paths_to_change(name, &arguments)
+            .and_then(|paths| checkpoint.before_paths(name, &paths));
+        if let Err(e) = result {
+            eprintln!("Warning: Failed to checkpoint files: {e:#}");
+        }
+    }
+
+    let started = Instant::now();
+    let result = tools.execute(name, &arguments);
+    record.duration = Some(started.elapsed());
+    record.error = result.is_err();
+    match result {
+        Ok(output) => format!("{note}{output}"),
+        Err(e) => format!("{note}Error: {e:#}"),
+    }
+}
+
+fn call_mcp_service(
+    registry: &AsyncMutex<McpRegistry>,
+    server_name: &str,
+    tool_name: &str,
+    arguments: &str,
+) -> Result<String, String> {
+    let reg = tokio::task::block_in_place(|| {
+        tokio::runtime::Handle::current().block_on(async { registry.lock().await })
+    });
+
+    match reg.get_service(server_name) {
+        Some(service) => {
+            execute_mcp_tool_call(service, tool_name, arguments).map_err(|e| e.to_string())
+        }
+        None => Err(format!("MCP service '{}' not initialized", server_name)),
+    }
+}
+
+/// Asks the model why it made a tool call, for the approval prompt's explain option.
+struct Explainer<'a> {
+    client: &'a Client<OpenAIConfig>,
+    model: &'a str,
+    /// The conversation, ending with the assistant message that made the call.
+    messages: &'a [ChatCompletionRequestMessage],
+}
+
+impl Explainer<'_> {
+    fn explain(&self, tool: &str, call: &str) -> Result<String, anyhow::Error> {
+        // The pending call has no result yet, so the question replaces the message
+        // that made it.
+        let end = self
+            .messages
+            .iter()
+            .rposition(|message| matches!(message, ChatCompletionRequestMessage::Assistant(_)))
+            .unwrap_or(self.messages.len());
+        let mut messages = self.messages[..end].to_vec();
+        messages.push(
+            ChatCompletionRequestUserMessageArgs::default()
+                .content(format!(
+                    "Before it runs, explain in two or three sentences why you want to make \
+                     this tool call and what you expect it to do. Don't call any tools.\n\n\
+                     Tool: {tool}\nCall: {call}"
+                ))
+                .build()?
+                .into(),
+        );
+        let request = CreateChatCompletionRequestArgs::default()
+            .model(self.model)
+            .messages(messages)
+            .build()?;
+
+        let response = tokio::task::block_in_place(|| {
+            tokio::runtime::Handle::current().block_on(self.client.chat().create(request))
+        })?;
+        response
+            .choices
+            .into_iter()
+            .next()
+            .and_then(|choice| choice.message.content)
+            .ok_or_else(|| anyhow::anyhow!("The model returned no answer"))
     }
 }
 
+/// Runs a tool call and records it in the audit log.
 fn execute_tool_call(
     tool_call: ChatCompletionMessageToolCall,
+    context: &mut ToolContext,
     registry: &AsyncMutex<McpRegistry>,
-    verbose: bool,
-) -> (String, String) {
-    let name = tool_call.function.name.clone();
-    let arguments = tool_call.function.arguments.clone();
+    explainer: &Explainer,
+) -> Result<(String, String), anyhow::Error> {
     let id = tool_call.id.clone();
+    let mut record = CallRecord::new(&tool_call.function.name, &tool_call.function.arguments);
+    let result = run_tool_call(tool_call, context, registry, explainer, &mut record);
+    if let Some(audit) = &context.audit {
+        match &result {
+            Ok(output) => audit.record(&record, output),
+            Err(e) => audit.record(&record, &format!("{e:#}")),
+        }
+    }
+    Ok((id, result?))
+}
+
+fn run_tool_call(
+    tool_call: ChatCompletionMessageToolCall,
+    context: &mut ToolContext,
+    registry: &AsyncMutex<McpRegistry>,
+    explainer: &Explainer,
+    record: &mut CallRecord,
+) -> Result<String, anyhow::Error> {
+    let verbose = context.verbose;
+    let name = tool_call.function.name.clone();
+
+    if !context.offered_tools.contains(&name) {
+        if verbose {
+            eprintln!("Rejected call to '{}': tool is not available", name);
+        }
+        return Ok(serde_json::json!({
+            "error": "unknown_tool",
+            "tool": name,
+            "hint": "Only call the tools listed in this request."
+        })
+        .to_string());
+    }
 
+    let (schema, read_only) = if name == "execute_command" {
+        let schema = execute_command_tool(context.shell.is_some())
+            .function
+            .parameters;
+        (schema, false)
+    } else if context.native_tools.is_native(&name) {
+        let schema = native::definition(&name).and_then(|tool| tool.function.parameters);
+        (schema, native::is_read_only(&name))
+    } else {
+        tokio::task::block_in_place(|| {
+            tokio::runtime::Handle::current().block_on(async {
+                let reg = registry.lock().await;
+                match reg.find_tool(&name) {
+                    Some(route) => (route.input_schema.clone(), route.read_only),
+                    None => (None, false),
+                }
+            })
+        })
+    };
+
+    // Validate before asking for approval so the user never approves a call that cannot run.
+    let arguments = match validate_arguments(&tool_call.function.arguments, schema.as_ref()) {
+        Ok(validated) => {
+            context.invalid_tool_calls = 0;
+            if verbose && !validated.repairs.is_empty() {
+                eprintln!(
+                    "Repaired arguments for '{}': {}",
+                    name,
+                    validated.repairs.join(", ")
+                );
+            }
+            validated.value.to_string()
+        }
+        Err(e) => {
+            context.invalid_tool_calls += 1;
+            if context.invalid_tool_calls >= MAX_INVALID_TOOL_CALLS {
+                return Err(anyhow::anyhow!(
+                    "Aborting: the model made {} tool calls in a row with invalid arguments. Last problem(s) with '{}': {}",
+                    context.invalid_tool_calls,
+                    name,
+                    e.problems.join("; ")
+                ));
+            }
+            if verbose {
+                eprintln!("Rejected call to '{}': {}", name, e.problems.join("; "));
+            }
+            return Ok(e.to_tool_result(&name));
+        }
+    };
+    record.arguments = arguments.clone();
+
+    if let Some(plan) = context.dry_run.as_mut()
+        && let Some(result) = plan.intercept(&name, &arguments, read_only)
+    {
+        record.decision = AuditDecision::DryRun;
+        return Ok(result);
+    }
+
+    let mut explain = |call: &str| explainer.explain(&name, call);
     let result = if name == "execute_command" {
-        execute_command_with_approval(&arguments, verbose)
+        execute_command_with_approval(
+            &arguments,
+            context.shell.as_mut(),
+            context.checkpoint.as_mut(),
+            &mut explain,
+            record,
+            verbose,
+        )
+    } else if context.native_tools.is_native(&name) {
+        execute_native_tool(
+            &name,
+            &arguments,
+            &context.native_tools,
+            context.checkpoint.as_mut(),
+            &mut explain,
+            record,
+            verbose,
+        )
     } else {
-        execute_mcp_tool(&name, &arguments, registry, verbose)
+        execute_mcp_tool(
+            &name,
+            &arguments,
+            registry,
+            context.checkpoint.as_mut(),
+            &mut explain,
+            record,
+            verbose,
+        )
     };
 
-    (id, result)
+    Ok(result)
 }
 
+/// Saves the conversation to `session`; `None` means the run is not persisted.
 fn save_session_if_needed(
     session: &Option<String>,
-    messages: &[ChatCompletionRequestMessage],
+    req: &async_openai::types::CreateChatCompletionRequest,
     response_message: &async_openai::types::ChatCompletionResponseMessage,
     verbose: bool,
 ) {
-    let session_name = session.as_deref().unwrap_or("last");
-    match save_session(session_name, messages, Some(response_message)) {
+    let Some(session_name) = session else {
+        return;
+    };
+    match save_session(
+        session_name,
+        &req.messages,
+        Some(response_message),
+        Some(&req.model),
+    ) {
         Ok(_) => {
             if verbose {
                 println!("Session saved successfully");
@@ -613,9 +1400,23 @@ fn format_file_system_tools(tool_name: &str, json: &Value) -> String {
             format!("Searching({pattern}) in {path}")
         }
         "write_file" => {
-            let path = json["path"].to_string();
-            let content = json["content"].to_string();
-            format!("Writing {path}:\n{content}")
+            let path = json["path"].as_str().unwrap_or_default();
+            let content = json["content"].as_str().unwrap_or_default();
+            format!(
+                "Writing ({path})\n{}",
+                diff::preview_write(Path::new(path), content)
+            )
+        }
+        "edit_file" => {
+            let path = json["path"].as_str().unwrap_or_default();
+            let edits = json["edits"]
+                .as_array()
+                .map(Vec::as_slice)
+                .unwrap_or_default();
+            format!(
+                "Editing ({path})\n{}",
+                diff::preview_edits(Path::new(path), edits)
+            )
         }
         "create_directory" => {
             let path = json["path"].to_string();
@@ -650,37 +1451,24 @@ fn format_mcp_tool_call(tool_name: &str, arguments: &str, verbose: bool) -> Stri
     }
 }
 
-fn build_system_prompt(shell: &str) -> String {
-    let date = chrono::offset::Local::now().format("%Y-%m-%d").to_string();
-    let cwd = env::current_dir()
-        .map(|p| p.display().to_string())
-        .unwrap_or_else(|_| ".".to_string());
-    format!(
-        "Help the user with their tasks. \n\
-         IMPORTANT: This is a one-way conversation - the user cannot reply to your messages.\n\
-         Guidelines:\n\
-         • You don't need to ask for permission to use the tools available to you \n\
-         • The current working directory is: {cwd}\n\
-         • Use the current directory as working directory unless otherwise specified\n\
-         • Follow the conventions that the user uses.  \n\
-            • Example: If the user asks you to generate a commit message, look at other commits and generate a message that is similar to them. \n\
-            • If you don't know the answer, try to figure it out based on the information available to you.\n\
-         • Ensure shell commands are compatible with {shell}\n\
-         • Today's date is {date}.\n\
-         • Format all responses in markdown for readability\n\n"
-    )
-}
-
-fn get_base_messages(shell: &str) -> Vec<ChatCompletionRequestMessage> {
-    let system_msg = ChatCompletionRequestSystemMessageArgs::default()
+fn get_system_message(
+    shell: &ShellInfo,
+    instructions: Option<&str>,
+) -> ChatCompletionRequestMessage {
+    ChatCompletionRequestSystemMessageArgs::default()
         .content(ChatCompletionRequestSystemMessageContent::Text(
-            build_system_prompt(shell),
+            build_system_prompt(shell, instructions),
         ))
         .build()
         .map(ChatCompletionRequestMessage::System)
-        .unwrap();
+        .unwrap()
+}
 
-    vec![system_msg]
+fn get_base_messages(
+    shell: &ShellInfo,
+    instructions: Option<&str>,
+) -> Vec<ChatCompletionRequestMessage> {
+    vec![get_system_message(shell, instructions)]
 }
 
 #[derive(Default)]
@@ -695,6 +1483,7 @@ struct StreamResult {
     content: String,
     tool_calls: Option<Vec<ChatCompletionMessageToolCall>>,
     finish_reason: Option<FinishReason>,
+    usage: Option<CompletionUsage>,
 }
 
 async fn stream_chat_completion(
@@ -702,8 +1491,18 @@ async fn stream_chat_completion(
     req: &async_openai::types::CreateChatCompletionRequest,
     verbose: bool,
     selected_model: &str,
+    include_usage: bool,
 ) -> Result<StreamResult, anyhow::Error> {
-    let mut stream = match client.chat().create_stream(req.clone()).await {
+    let mut req = req.clone();
+    // Not every OpenAI-compatible server accepts stream_options, so the usage is
+    // only asked for when it is recorded.
+    if include_usage {
+        req.stream_options = Some(ChatCompletionStreamOptions {
+            include_usage: true,
+        });
+    }
+
+    let mut stream = match client.chat().create_stream(req).await {
         Ok(s) => s,
         Err(e) => {
             let error_str = e.to_string();
@@ -730,6 +1529,7 @@ async fn stream_chat_completion(
     let mut content = String::new();
     let mut tool_call_accumulators: Vec<ToolCallAccumulator> = Vec::new();
     let mut finish_reason: Option<FinishReason> = None;
+    let mut usage: Option<CompletionUsage> = None;
     let mut printed_any = false;
     let mut pending_star = false;
     let mut print_display = |text: &str| {
@@ -745,6 +1545,10 @@ async fn stream_chat_completion(
 
     while let Some(chunk) = stream.next().await {
         let chunk = chunk?;
+        // With include_usage the totals arrive in a final chunk without choices.
+        if chunk.usage.is_some() {
+            usage = chunk.usage.clone();
+        }
         if chunk.choices.is_empty() {
             continue;
         }
@@ -825,6 +1629,7 @@ async fn stream_chat_completion(
         content,
         tool_calls,
         finish_reason,
+        usage,
     })
 }
 
@@ -1,18 +1,33 @@
 use crate::commands::Commands;
+use crate::commands::approval_commands::handle_approval_commands;
+use crate::commands::audit_commands::handle_audit_commands;
 use crate::commands::base_url_commands::handle_base_url_commands;
+use crate::commands::checkpoint_commands::{handle_checkpoint_commands, handle_undo_command};
+use crate::commands::cmd_commands::{handle_cmd_command, handle_shell_init};
+use crate::commands::commit_commands::handle_commit_command;
+use crate::commands::history_commands::handle_history_command;
 use crate::commands::mcp_commands::handle_mcp_commands;
 use crate::commands::model_commands::handle_model_commands;
 use crate::commands::preset_commands::handle_preset_commands;
+use crate::commands::prompt_commands::handle_prompt_commands;
 use crate::commands::session_commands::handle_session_commands;
-use crate::config::get_prompt_preset;
 use crate::sessions::get_last_session_name;
 use clap::Parser;
 use crossterm::terminal;
 
 mod approval;
+mod audit;
+mod checkpoints;
 mod commands;
 mod config;
+mod diff;
+mod dry_run;
+mod editor;
+mod git;
+mod history;
 mod llms;
+mod presets;
+mod prompt;
 mod sessions;
 mod shell;
 mod tools;
@@ -36,6 +51,33 @@ struct Cli {
     #[arg(short, long)]
     reply: bool,
 
+    /// Rewrite the last question of the session (or of the last session) and regenerate the answer.
+    /// Without a new question the previous one is asked again. The original thread is kept as a
+    /// separate session.
+    #[arg(long)]
+    edit_last: bool,
+
+    /// Do not save this run as a session
+    #[arg(long, conflicts_with = "edit_last")]
+    incognito: bool,
+
+    /// Run commands in one shell for the whole run, so `cd` and exported variables carry over
+    #[arg(long)]
+    persistent_shell: bool,
+
+    /// Show what would be done: commands and MCP tool calls are recorded instead of run, and
+    /// the plan is listed at the end
+    #[arg(long)]
+    dry_run: bool,
+
+    /// With --dry-run, still run MCP tools that declare themselves read-only
+    #[arg(long, requires = "dry_run")]
+    run_read_only: bool,
+
+    /// With --dry-run, write the planned commands to this file as a shell script
+    #[arg(long, value_name = "PATH", requires = "dry_run")]
+    plan_script: Option<std::path::PathBuf>,
+
     /// The OPENAI model to use. Defaults to gpt-4.1-mini or whatever is configured in the config file.
     #[arg(short, long)]
     model: Option<String>,
@@ -43,6 +85,18 @@ struct Cli {
     #[arg(short, long)]
     preset: Option<String>,
 
+    /// Instructions to use instead of the built-in ones at the start of the system prompt
+    #[arg(long, conflicts_with = "system_file")]
+    system: Option<String>,
+
+    /// Read the system prompt instructions from a file
+    #[arg(long)]
+    system_file: Option<std::path::PathBuf>,
+
+    /// Attach git output to the question: diff, staged, log[:N] or branch. Can be repeated.
+    #[arg(long = "git", value_name = "CONTEXT", value_parser = git::parse_context)]
+    git_context: Vec<git::GitContext>,
+
     /// Maximum number of iterations with the AI (default 21)
     #[arg(short = 'i', long = "iterations")]
     iterations: Option<usize>,
@@ -72,6 +126,24 @@ async fn main() {
         Some(Commands::Model { command }) => handle_model_commands(command),
         Some(Commands::BaseUrl { command }) => handle_base_url_commands(command),
         Some(Commands::Preset { command }) => handle_preset_commands(command),
+        Some(Commands::Prompt { command }) => handle_prompt_commands(command),
+        Some(Commands::History { all, limit }) => handle_history_command(all, limit),
+        Some(Commands::Commit {
+            conventional,
+            amend,
+            model,
+            instructions,
+        }) => handle_commit_command(conventional, amend, model, instructions, cli.verbose).await,
+        Some(Commands::Cmd {
+            print_only,
+            model,
+            description,
+        }) => handle_cmd_command(description, print_only, model, cli.verbose).await,
+        Some(Commands::ShellInit { shell }) => handle_shell_init(shell),
+        Some(Commands::Checkpoint { command }) => handle_checkpoint_commands(command),
+        Some(Commands::Undo { run_id, yes }) => handle_undo_command(run_id, yes),
+        Some(Commands::Audit { command }) => handle_audit_commands(command),
+        Some(Commands::Approval { command }) => handle_approval_commands(command),
         Some(Commands::Init) => {
             handle_init();
         }
@@ -89,26 +161,33 @@ async fn main() {
             };
 
             let preset = match cli.preset {
-                Some(preset_name) => match get_prompt_preset(&preset_name) {
-                    Ok(preset_prompt) => {
+                Some(preset_name) => match presets::load_preset(&preset_name) {
+                    Ok(preset) => {
                         println!("Using preset '{}':", preset_name);
-                        preset_prompt
+                        Some(preset)
                     }
                     Err(e) => {
                         eprintln!("Error: Failed to load preset '{}': {}", preset_name, e);
                         std::process::exit(1);
                     }
                 },
-                None => String::new(),
+                None => None,
             };
 
-            if cli.question.is_empty() && stdin.is_empty() && preset.is_empty() {
+            if cli.question.is_empty()
+                && stdin.is_empty()
+                && preset.is_none()
+                && cli.git_context.is_empty()
+                && !cli.edit_last
+            {
                 eprintln!("Error: Please provide a question or use a subcommand (init, mcp)");
                 std::process::exit(1);
             }
 
             let loaded_config = config::load_config().unwrap_or_default();
-            let model = cli.model;
+            let model = cli
+                .model
+                .or_else(|| preset.as_ref().and_then(|p| p.settings.model.clone()));
             let selected_model: Option<String> = match model {
                 Some(model) => {
                     let model_aliases = &loaded_config.model_aliases;
@@ -120,33 +199,103 @@ async fn main() {
                 }
                 None => model,
             };
-            let mut question = cli.question.join(" ");
-            question = format!("{}\n{}\n\n{}", preset, question, stdin);
+            let has_new_question =
+                !(cli.question.is_empty() && stdin.is_empty() && preset.is_none());
+            let mut question = match &preset {
+                Some(preset) => match presets::render_prompt(preset, &cli.question, &stdin) {
+                    Ok(question) => question,
+                    Err(e) => {
+                        eprintln!("Error: Failed to render preset '{}': {}", preset.name, e);
+                        std::process::exit(1);
+                    }
+                },
+                None => format!("\n{}\n\n{}", cli.question.join(" "), stdin),
+            };
 
-            if cli.verbose {
-                println!("Asking: {question}");
+            let mut session = cli.session;
+            if session.is_none() && (cli.reply || cli.edit_last) {
+                session = history::last_session_in_dir().or_else(get_last_session_name);
             }
 
-            let mut session = cli.session;
-            if session.is_none() && cli.reply {
-                session = get_last_session_name();
+            if cli.edit_last {
+                let Some(session_name) = &session else {
+                    eprintln!("Error: No session to edit. Use --session <name>.");
+                    std::process::exit(1);
+                };
+                match sessions::rewind_last_turn(session_name) {
+                    Ok((backup, previous_question)) => {
+                        println!("Original thread saved as session '{}'", backup);
+                        if !has_new_question {
+                            question = previous_question;
+                        }
+                    }
+                    Err(e) => {
+                        eprintln!("Error: Failed to edit session '{}': {}", session_name, e);
+                        std::process::exit(1);
+                    }
+                }
+            }
+
+            for context in &cli.git_context {
+                match git::context_section(context) {
+                    Ok(section) => {
+                        question = format!("{}\n\n{}", question.trim_end(), section);
+                    }
+                    Err(e) => {
+                        eprintln!("Error: Failed to attach git context: {:#}", e);
+                        std::process::exit(1);
+                    }
+                }
+            }
+
+            if cli.verbose {
+                println!("Asking: {question}");
             }
 
             let stream = if cli.stream {
                 true
             } else if cli.no_stream {
                 false
+            } else if let Some(stream) = preset.as_ref().and_then(|p| p.settings.stream) {
+                stream
             } else {
                 config::resolve_stream_setting(&loaded_config)
             };
 
+            let (preset_system_prompt, allowed_tools) = match preset {
+                Some(preset) => (preset.settings.system_prompt, preset.settings.tools),
+                None => (None, None),
+            };
+            let system_prompt = match prompt::resolve_instructions(
+                cli.system,
+                cli.system_file.as_deref(),
+                preset_system_prompt,
+                loaded_config.system_prompt.clone(),
+            ) {
+                Ok(system_prompt) => system_prompt,
+                Err(e) => {
+                    eprintln!("Error: {:#}", e);
+                    std::process::exit(1);
+                }
+            };
+
             match llms::ask_question(
                 &question,
-                selected_model,
-                session,
-                max_iterations,
-                cli.verbose,
-                stream,
+                llms::AskOptions {
+                    model: selected_model,
+                    session,
+                    max_iterations,
+                    verbose: cli.verbose,
+                    stream,
+                    incognito: cli.incognito,
+                    system_prompt,
+                    allowed_tools,
+                    persistent_shell: cli.persistent_shell,
+                    dry_run: cli.dry_run.then_some(dry_run::DryRunOptions {
+                        run_read_only: cli.run_read_only,
+                        script: cli.plan_script,
+                    }),
+                },
             )
             .await
             {
@@ -214,31 +363,14 @@ fn get_stdin() -> Result<String, std::io::Error> {
 }
 
 fn handle_init() {
-    let npx_command = if cfg!(target_os = "windows") {
-        if check_command_exists("npx.cmd") {
-            "npx.cmd"
-        } else if check_command_exists("npx") {
-            "npx"
-        } else {
-            eprintln!("Error: 'npx' command not found in PATH.");
-            eprintln!("Please install Node.js/npm to use the filesystem MCP server.");
-            std::process::exit(1);
-        }
+    let npx_command = if cfg!(target_os = "windows") && check_command_exists("npx.cmd") {
+        Some("npx.cmd")
+    } else if check_command_exists("npx") {
+        Some("npx")
     } else {
-        if !check_command_exists("npx") {
-            eprintln!("Error: 'npx' command not found in PATH.");
-            eprintln!("Please install Node.js/npm to use the filesystem MCP server.");
-            std::process::exit(1);
-        }
-        "npx"
+        None
     };
-
     let uvx_exists = check_command_exists("uvx");
-    if !uvx_exists {
-        eprintln!("Error: 'uvx' command not found in PATH.");
-        eprintln!("Please install uv (https://docs.astral.sh/uv/) to use the git MCP server.");
-        std::process::exit(1);
-    }
 
     let config_path: std::path::PathBuf =
         match shellexpand::tilde("~/.ask/config").into_owned().parse() {
@@ -255,18 +387,37 @@ fn handle_init() {
         std::process::exit(1);
     }
 
-    println!("This will create ~/.ask/config with the following MCP servers:");
-    println!();
-    println!(
-        "  1. filesystem - File system operations (using @modelcontextprotocol/server-filesystem)"
-    );
-    println!("     Command: {npx_command} -y @modelcontextprotocol/server-filesystem .");
-    println!("     Provides tools for reading, writing, and managing files");
-    println!();
-    println!("  2. git - Git repository operations (using uvx mcp-server-git)");
-    println!("     Command: uvx mcp-server-git");
-    println!("     Provides tools for git commands and repository management");
+    println!("This will create ~/.ask/config. Reading, searching and editing files use the");
+    println!("built-in file tools, which need no MCP server.");
     println!();
+    if npx_command.is_some() || uvx_exists {
+        println!("It adds the following MCP servers:");
+        println!();
+    }
+    if uvx_exists {
+        println!("  git - Git repository operations (using uvx mcp-server-git)");
+        println!("     Command: uvx mcp-server-git");
+        println!("     Provides tools for git commands and repository management");
+        println!();
+    }
+    if let Some(npx_command) = npx_command {
+        println!("  sequential-thinking - Step-by-step problem solving");
+        println!("     Command: {npx_command} -y @modelcontextprotocol/server-sequential-thinking");
+        println!();
+    }
+    if !uvx_exists {
+        eprintln!("Warning: 'uvx' command not found in PATH; skipping the git MCP server.");
+        eprintln!("Install uv (https://docs.astral.sh/uv/) to add it later.");
+    }
+    if npx_command.is_none() {
+        eprintln!(
+            "Warning: 'npx' command not found in PATH; skipping the sequential-thinking MCP server."
+        );
+        eprintln!("Install Node.js/npm to add it later.");
+    }
+    if npx_command.is_none() || !uvx_exists {
+        println!();
+    }
     println!("Location: {}", config_path.display());
     println!();
 
@@ -289,58 +440,42 @@ fn handle_init() {
     }
 
     let config = config::AskConfig {
-        base_url: None,
-        model: None,
-        model_aliases: std::collections::HashMap::new(),
-        presets: std::collections::HashMap::new(),
         stream: Some(true),
         mcp_servers: {
             let mut servers = std::collections::HashMap::new();
-            servers.insert(
-                "filesystem".to_string(),
-                config::McpServerDefinition {
-                    command: npx_command.to_string(),
-                    args: vec![
-                        "-y".to_string(),
-                        "@modelcontextprotocol/server-filesystem".to_string(),
-                        ".".to_string(),
-                    ],
-                    env: {
-                        let mut env = std::collections::HashMap::new();
-                        env.insert("DEBUG".to_string(), "1".to_string());
-                        env
+            if uvx_exists {
+                servers.insert(
+                    "git".to_string(),
+                    config::McpServerDefinition {
+                        command: "uvx".to_string(),
+                        args: vec!["mcp-server-git".to_string()],
+                        env: std::collections::HashMap::new(),
                     },
-                },
-            );
-            servers.insert(
-                "git".to_string(),
-                config::McpServerDefinition {
-                    command: "uvx".to_string(),
-                    args: vec!["mcp-server-git".to_string()],
-                    env: std::collections::HashMap::new(),
-                },
-            );
-            servers.insert(
-                "sequential-thinking".to_string(),
-                config::McpServerDefinition {
-                    command: npx_command.to_string(),
-                    args: vec![
-                        "-y".to_string(),
-                        "@modelcontextprotocol/server-sequential-thinking".to_string(),
-                    ],
-                    env: std::collections::HashMap::new(),
-                },
-            );
+                );
+            }
+            if let Some(npx_command) = npx_command {
+                servers.insert(
+                    "sequential-thinking".to_string(),
+                    config::McpServerDefinition {
+                        command: npx_command.to_string(),
+                        args: vec![
+                            "-y".to_string(),
+                            "@modelcontextprotocol/server-sequential-thinking".to_string(),
+                        ],
+                        env: std::collections::HashMap::new(),
+                    },
+                );
+            }
             servers
         },
-        auto_approved_tools: Vec::new(),
+        ..Default::default()
     };
 
     match config::save_config(&config) {
         Ok(path) => {
             println!("✓ Created configuration file at {path:?}");
             println!();
-            println!("You can now use ask-rs with MCP tools!");
+            println!("You can now use ask-rs with the built-in file tools and MCP tools!");
             println!("Try: ask-rs \"list files in current directory\" or ask-rs mcp list");
         }
         Err(e) => {
@@ -0,0 +1,298 @@
+use crate::config::{self, PresetDefinition, PresetSettings};
+use anyhow::{Context, Result, bail};
+use once_cell::sync::Lazy;
+use std::fs;
+use std::path::PathBuf;
+
+pub enum PresetSource {
+    Config,
+    File(PathBuf),
+}
+
+pub struct Preset {
+    pub name: String,
+    pub source: PresetSource,
+    pub settings: PresetSettings,
+}
+
+fn get_presets_dir() -> Result<PathBuf> {
+    shellexpand::tilde("~/.ask/presets")
+        .into_owned()
+        .parse()
+        .context("Failed to parse presets directory path")
+}
+
+/// Preset names become file names in `~/.ask/presets`, so they follow the rules for
+/// session names.
+fn validate_preset_name(name: &str) -> Result<()> {
+    if name.trim().is_empty() {
+        bail!("Preset name cannot be empty");
+    }
+    if name.starts_with('.') {
+        bail!("Preset name '{}' cannot start with '.'", name);
+    }
+    if let Some(c) = name.chars().find(|c| {
+        c.is_control() || matches!(c, '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|')
+    }) {
+        bail!("Preset name '{}' cannot contain {:?}", name, c);
+    }
+    Ok(())
+}
+
+/// Loads a preset from `~/.ask/presets/<name>.md`, falling back to `promptPresets`
+/// in the config.
+pub fn load_preset(name: &str) -> Result<Preset> {
+    validate_preset_name(name)?;
+    let file_path = get_presets_dir()?.join(format!("{name}.md"));
+    if file_path.exists() {
+        return load_preset_file(name, file_path);
+    }
+
+    let config = config::load_config().context("Failed to load config")?;
+    match config.presets.get(name) {
+        Some(definition) => Ok(Preset {
+            name: name.to_string(),
+            source: PresetSource::Config,
+            settings: definition.clone().into_settings(),
+        }),
+        None => bail!("Prompt preset '{name}' not found"),
+    }
+}
+
+/// All presets, sorted by name. Preset files shadow config presets of the same name.
+pub fn list_presets() -> Result<Vec<Preset>> {
+    let mut presets = vec![];
+
+    let presets_dir = get_presets_dir()?;
+    if presets_dir.exists() {
+        let entries = fs::read_dir(&presets_dir).context(format!(
+            "Failed to read presets directory at {:?}",
+            presets_dir
+        ))?;
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if path.extension().is_some_and(|ext| ext == "md")
+                && let Some(name) = path.file_stem().and_then(|s| s.to_str())
+            {
+                match load_preset_file(name, path.clone()) {
+                    Ok(preset) => presets.push(preset),
+                    Err(e) => eprintln!("Warning: Skipping preset {:?}: {:#}", path, e),
+                }
+            }
+        }
+    }
+
+    if let Ok(config) = config::load_config() {
+        for (name, definition) in config.presets {
+            if !presets.iter().any(|preset| preset.name == name) {
+                presets.push(Preset {
+                    name,
+                    source: PresetSource::Config,
+                    settings: definition.into_settings(),
+                });
+            }
+        }
+    }
+
+    presets.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(presets)
+}
+
+fn load_preset_file(name: &str, path: PathBuf) -> Result<Preset> {
+    let contents =
+        fs::read_to_string(&path).context(format!("Failed to read preset file {:?}", path))?;
+    let settings =
+        parse_preset_file(&contents).context(format!("Failed to parse preset file {:?}", path))?;
+    Ok(Preset {
+        name: name.to_string(),
+        source: PresetSource::File(path),
+        settings,
+    })
+}
+
+/// Parses a markdown preset with optional front-matter:
+///
+/// ```text
+/// ---
+/// model: gpt-4.1
+/// stream: false
+/// tools: [git_*, execute_command]
+/// systemPrompt: You are a meticulous code reviewer.
+/// ---
+/// Review this diff: {{shell:git diff --staged}}
+/// ```
+fn parse_preset_file(contents: &str) -> Result<PresetSettings> {
+    let mut settings = PresetSettings::default();
+
+    let body = match contents
+        .strip_prefix("---\n")
+        .or_else(|| contents.strip_prefix("---\r\n"))
+    {
+        Some(rest) => {
+            let Some((front_matter, body)) = rest
+                .split_once("\n---\n")
+                .or_else(|| rest.split_once("\n---\r\n"))
+                .or_else(|| rest.strip_suffix("\n---").map(|fm| (fm, "")))
+            else {
+                bail!("Front-matter is not closed with '---'");
+            };
+            for line in front_matter.lines() {
+                let line = line.trim();
+                if line.is_empty() || line.starts_with('#') {
+                    continue;
+                }
+                let Some((key, value)) = line.split_once(':') else {
+                    bail!("Invalid front-matter line '{line}'. Expected 'key: value'");
+                };
+                let value = unquote(value.trim());
+                match key.trim() {
+                    "model" => settings.model = Some(value.to_string()),
+                    "systemPrompt" | "system_prompt" => {
+                        settings.system_prompt = Some(value.to_string())
+                    }
+                    "stream" => {
+                        settings.stream = Some(value.parse().context(format!(
+                            "Invalid value '{value}' for stream. Expected true or false"
+                        ))?)
+                    }
+                    "tools" => {
+                        let list = value.trim_start_matches('[').trim_end_matches(']');
+                        settings.tools = Some(
+                            list.split(',')
+                                .map(|tool| unquote(tool.trim()).to_string())
+                                .filter(|tool| !tool.is_empty())
+                                .collect(),
+                        );
+                    }
+                    other => bail!("Unknown front-matter key '{other}'"),
+                }
+            }
+            body
+        }
+        None => contents,
+    };
+
+    settings.prompt = body.trim().to_string();
+    Ok(settings)
+}
+
+fn unquote(value: &str) -> &str {
+    value
+        .strip_prefix('"')
+        .and_then(|v| v.strip_suffix('"'))
+        .or_else(|| value.strip_prefix('\'').and_then(|v| v.strip_suffix('\'')))
+        .unwrap_or(value)
+}
+
+/// Builds the question from a preset template, the question words (`args`) and piped
+/// input. `{{input}}` is the question words not consumed by `{{argN}}` placeholders,
+/// followed by the piped input. Templates that use neither `{{input}}` nor `{{argN}}`
+/// are followed by the question and piped input, as plain presets always were;
+/// templates that only use `{{argN}}` are followed by what `{{input}}` would be. Unknown
+/// placeholders are left as they are.
+pub fn render_prompt(preset: &Preset, args: &[String], stdin: &str) -> Result<String> {
+    static PLACEHOLDER: Lazy<regex::Regex> =
+        Lazy::new(|| regex::Regex::new(r"\{\{\s*(.+?)\s*\}\}").expect("Failed to compile regex"));
+
+    let template = &preset.settings.prompt;
+    let consumed_args = PLACEHOLDER
+        .captures_iter(template)
+        .filter_map(|captures| captures[1].strip_prefix("arg")?.parse::<usize>().ok())
+        .max()
+        .unwrap_or(0);
+
+    let question = args.get(consumed_args..).unwrap_or_default().join(" ");
+    let input = if stdin.is_empty() {
+        question.clone()
+    } else if question.is_empty() {
+        stdin.to_string()
+    } else {
+        format!("{question}\n\n{stdin}")
+    };
+
+    let mut rendered = String::new();
+    let mut last_end = 0;
+    let mut uses_input = false;
+    let mut uses_args = false;
+    for captures in PLACEHOLDER.captures_iter(template) {
+        let placeholder = captures.get(0).expect("capture 0 always exists");
+        let expression = &captures[1];
+        rendered.push_str(&template[last_end..placeholder.start()]);
+        last_end = placeholder.end();
+
+        if expression == "input" {
+            uses_input = true;
+            rendered.push_str(&input);
+        } else if let Some(index) = expression.strip_prefix("arg")
+            && let Ok(index) = index.parse::<usize>()
+        {
+            uses_args = true;
+            match index.checked_sub(1).and_then(|i| args.get(i)) {
+                Some(arg) => rendered.push_str(arg),
+                None => bail!(
+                    "Preset '{}' uses {{{{{expression}}}}} but only {} argument(s) were given",
+                    preset.name,
+                    args.len()
+                ),
+            }
+        } else if let Some(var) = expression.strip_prefix("env.") {
+            match std::env::var(var) {
+                Ok(value) => rendered.push_str(&value),
+                Err(_) => bail!("Environment variable '{var}' used by preset is not set"),
+            }
+        } else if let Some(path) = expression.strip_prefix("file:") {
+            let path = shellexpand::tilde(path.trim()).into_owned();
+            let contents =
+                fs::read_to_string(&path).context(format!("Failed to read file '{path}'"))?;
+            rendered.push_str(contents.trim_end());
+        } else if let Some(command) = expression.strip_prefix("shell:") {
+            rendered.push_str(run_template_command(command.trim())?.trim_end());
+        } else {
+            // Other `{{…}}`, e.g. a Handlebars or Jinja snippet, is text.
+            rendered.push_str(placeholder.as_str());
+        }
+    }
+    rendered.push_str(&template[last_end..]);
+
+    Ok(if uses_input {
+        rendered
+    } else if uses_args {
+        format!("{rendered}\n\n{input}")
+    } else {
+        format!("{rendered}\n{}\n\n{stdin}", args.join(" "))
+    })
+}
+
+fn run_template_command(command: &str) -> Result<String> {
+    let (shell, flag) = if cfg!(windows) {
+        ("cmd", "/C")
+    } else {
+        ("sh", "-c")
+    };
+
+    let output = std::process::Command::new(shell)
+        .arg(flag)
+        .arg(command)
+        .output()
+        .context(format!("Failed to run '{command}'"))?;
+    if !output.status.success() {
+        bail!(
+            "'{command}' failed with {}: {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+impl PresetDefinition {
+    pub fn into_settings(self) -> PresetSettings {
+        match self {
+            PresetDefinition::Template(prompt) => PresetSettings {
+                prompt,
+                ..Default::default()
+            },
+            PresetDefinition::Detailed(settings) => settings,
+        }
+    }
+}
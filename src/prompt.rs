@@ -0,0 +1,130 @@
+use crate::git::{self, find_git_root};
+use crate::shell::{CoreUtils, ShellInfo};
+use anyhow::{Context, Result};
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const DEFAULT_INSTRUCTIONS: &str = "Help the user with their tasks. \n\
+     IMPORTANT: This is a one-way conversation - the user cannot reply to your messages.\n\
+     Guidelines:\n\
+     • You don't need to ask for permission to use the tools available to you \n\
+     • Use the current directory as working directory unless otherwise specified\n\
+     • Follow the conventions that the user uses.  \n\
+        • Example: If the user asks you to generate a commit message, look at other commits and generate a message that is similar to them. \n\
+        • If you don't know the answer, try to figure it out based on the information available to you.\n\
+     • Format all responses in markdown for readability\n";
+
+/// Files with project instructions, looked for in every directory from the repository
+/// root down to the current directory.
+const PROJECT_INSTRUCTION_FILES: [&str; 2] = ["ASK.md", "AGENTS.md"];
+
+/// Project instruction files larger than this are truncated.
+const MAX_PROJECT_INSTRUCTIONS_BYTES: usize = 32 * 1024;
+
+/// Picks the instructions that replace the built-in ones, in order of precedence:
+/// `--system`, `--system-file`, the preset's `systemPrompt`, then the config's.
+pub fn resolve_instructions(
+    system: Option<String>,
+    system_file: Option<&Path>,
+    preset_prompt: Option<String>,
+    config_prompt: Option<String>,
+) -> Result<Option<String>> {
+    if system.is_some() {
+        return Ok(system);
+    }
+    if let Some(path) = system_file {
+        let contents = fs::read_to_string(path)
+            .context(format!("Failed to read system prompt file {:?}", path))?;
+        return Ok(Some(contents));
+    }
+    Ok(preset_prompt.or(config_prompt))
+}
+
+/// `instructions` replaces the built-in guidelines; project instructions and the
+/// environment details are always appended.
+pub fn build_system_prompt(shell: &ShellInfo, instructions: Option<&str>) -> String {
+    let date = chrono::offset::Local::now().format("%Y-%m-%d").to_string();
+    let cwd = env::current_dir()
+        .map(|p| p.display().to_string())
+        .unwrap_or_else(|_| ".".to_string());
+
+    let mut prompt = instructions
+        .unwrap_or(DEFAULT_INSTRUCTIONS)
+        .trim_end()
+        .to_string();
+    prompt.push_str("\n\n");
+
+    for (path, contents) in project_instructions() {
+        prompt.push_str(&format!(
+            "Project instructions from {}:\n{}\n\n",
+            path.display(),
+            contents.trim_end()
+        ));
+    }
+
+    prompt.push_str(&format!(
+        "Environment:\n\
+         • The current working directory is: {cwd}\n\
+         • Commands are run with `{invocation}`; ensure they are compatible with {syntax}\n\
+         • The user's shell is {user_shell}\n\
+         • The operating system is {os}\n",
+        invocation = shell.invocation().join(" "),
+        syntax = shell.command_syntax(),
+        user_shell = shell.describe_shell(),
+        os = shell.os,
+    ));
+    match shell.core_utils {
+        Some(CoreUtils::Gnu) => prompt.push_str("• GNU coreutils are installed\n"),
+        Some(CoreUtils::Bsd) => prompt.push_str(
+            "• The core utilities are the BSD variants: avoid GNU-only options such as `sed -i` without a suffix argument\n",
+        ),
+        Some(CoreUtils::BusyBox) => prompt.push_str(
+            "• The core utilities are provided by BusyBox and support fewer options than GNU coreutils\n",
+        ),
+        None => {}
+    }
+    prompt.push_str(&format!("• Today's date is {date}.\n"));
+    if let Some(repo) = git::repo_summary() {
+        for line in repo {
+            prompt.push_str(&format!("• {line}\n"));
+        }
+    }
+    prompt.push('\n');
+    prompt
+}
+
+/// Reads the `ASK.md`/`AGENTS.md` files that apply to the current directory, outermost
+/// first. Outside a git repository only the current directory is searched.
+fn project_instructions() -> Vec<(PathBuf, String)> {
+    let Ok(cwd) = env::current_dir() else {
+        return Vec::new();
+    };
+    let root = find_git_root(&cwd).unwrap_or_else(|| cwd.clone());
+
+    let mut dirs: Vec<&Path> = cwd
+        .ancestors()
+        .take_while(|dir| dir.starts_with(&root))
+        .collect();
+    dirs.reverse();
+
+    let mut found = vec![];
+    for dir in dirs {
+        for file_name in PROJECT_INSTRUCTION_FILES {
+            let path = dir.join(file_name);
+            let Ok(mut contents) = fs::read_to_string(&path) else {
+                continue;
+            };
+            if contents.len() > MAX_PROJECT_INSTRUCTIONS_BYTES {
+                let mut end = MAX_PROJECT_INSTRUCTIONS_BYTES;
+                while !contents.is_char_boundary(end) {
+                    end -= 1;
+                }
+                contents.truncate(end);
+                contents.push_str("\n[truncated]");
+            }
+            found.push((path, contents));
+        }
+    }
+    found
+}
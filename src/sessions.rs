@@ -1,15 +1,85 @@
-use anyhow::{Context, Result};
+use anyhow::{Context, Result, bail};
 use async_openai::types::{
-    ChatCompletionRequestAssistantMessage, ChatCompletionRequestMessage,
+    ChatCompletionRequestAssistantMessage, ChatCompletionRequestAssistantMessageContent,
+    ChatCompletionRequestAssistantMessageContentPart, ChatCompletionRequestMessage,
+    ChatCompletionRequestSystemMessageContent, ChatCompletionRequestSystemMessageContentPart,
+    ChatCompletionRequestToolMessageContent, ChatCompletionRequestToolMessageContentPart,
+    ChatCompletionRequestUserMessageContent, ChatCompletionRequestUserMessageContentPart,
     ChatCompletionResponseMessage,
 };
 use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::fs;
 use std::time::SystemTime;
 
 pub struct Session {
     pub name: String,
     pub created: String,
+    pub modified: SystemTime,
+    pub message_count: usize,
+    /// The first question asked in the session, if any.
+    pub preview: Option<String>,
+    /// Size of the session file in bytes.
+    pub size: u64,
+}
+
+/// A session removed (or, in a dry run, selected for removal) by `prune_sessions`.
+pub struct PrunedSession {
+    pub name: String,
+    pub size: u64,
+}
+
+/// When a turn was asked and which model answered it.
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct TurnInfo {
+    /// RFC 3339 timestamp of the question.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timestamp: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+}
+
+/// Directory and environment of the persistent shell at the end of the last run, so
+/// the next run of the session continues where it stopped.
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct ShellState {
+    /// Where `ask` was started. The shell's directory is only restored from there.
+    pub started_in: std::path::PathBuf,
+    pub cwd: std::path::PathBuf,
+    /// Variables set or changed by commands.
+    #[serde(default)]
+    pub env: BTreeMap<String, String>,
+    /// Variables unset by commands.
+    #[serde(default)]
+    pub unset: Vec<String>,
+}
+
+/// A session as stored on disk: the conversation plus metadata for each turn.
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct SessionData {
+    pub messages: Vec<ChatCompletionRequestMessage>,
+    /// One entry per turn (see `turn_starts`); may be shorter for older sessions.
+    #[serde(default)]
+    pub turns: Vec<TurnInfo>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub shell: Option<ShellState>,
+}
+
+/// Sessions written before turn metadata existed are a bare array of messages.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum SessionFile {
+    Current(SessionData),
+    Legacy(Vec<ChatCompletionRequestMessage>),
+}
+
+pub struct SearchHit {
+    pub session: String,
+    pub role: &'static str,
+    pub text: String,
+    /// Byte range of the match within `text`.
+    pub match_range: (usize, usize),
 }
 fn system_time_to_string(system_time: SystemTime) -> String {
     let datetime: DateTime<Local> = system_time.into();
@@ -46,17 +116,176 @@ fn get_session_dir() -> Result<std::path::PathBuf> {
     Ok(session_dir)
 }
 
+/// The name that always refers to the most recent default session of the current
+/// terminal (or directory, outside a terminal).
+pub const LAST_SESSION: &str = "last";
+
+/// Rejects names that could escape the session directory or clash with the hidden
+/// bookkeeping files stored next to sessions.
+pub fn validate_session_name(name: &str) -> Result<()> {
+    if name.trim().is_empty() {
+        bail!("Session name cannot be empty");
+    }
+    if name.len() > 100 {
+        bail!("Session name '{}' is too long (max 100 bytes)", name);
+    }
+    if name.starts_with('.') {
+        bail!("Session name '{}' cannot start with '.'", name);
+    }
+    if let Some(c) = name.chars().find(|c| {
+        c.is_control() || matches!(c, '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|')
+    }) {
+        bail!("Session name '{}' cannot contain {:?}", name, c);
+    }
+    Ok(())
+}
+
+/// FNV-1a; unlike `DefaultHasher` it is stable across Rust releases, so keys derived
+/// from it keep pointing at the same files.
+fn stable_hash(input: &str) -> u32 {
+    input.bytes().fold(0x811c9dc5u32, |hash, byte| {
+        (hash ^ byte as u32).wrapping_mul(0x01000193)
+    })
+}
+
+fn terminal_id() -> Option<String> {
+    const TERMINAL_VARS: [&str; 6] = [
+        "TMUX_PANE",
+        "WT_SESSION",
+        "TERM_SESSION_ID",
+        "ITERM_SESSION_ID",
+        "KITTY_WINDOW_ID",
+        "WEZTERM_PANE",
+    ];
+    for var in TERMINAL_VARS {
+        if let Ok(value) = std::env::var(var)
+            && !value.is_empty()
+        {
+            return Some(format!("{var}={value}"));
+        }
+    }
+
+    #[cfg(unix)]
+    for fd in 0..3 {
+        if let Ok(target) = fs::read_link(format!("/proc/self/fd/{fd}")) {
+            let target = target.display().to_string();
+            if target.starts_with("/dev/pts/") || target.starts_with("/dev/tty") {
+                return Some(target);
+            }
+        }
+    }
+
+    None
+}
+
+/// Identifies where the default session belongs: the terminal when it can be
+/// detected, otherwise the current directory. Concurrent `ask` processes in
+/// different terminals therefore never write to the same default session.
+fn default_session_key() -> String {
+    let id = terminal_id().unwrap_or_else(|| {
+        std::env::current_dir()
+            .map(|dir| format!("dir={}", dir.display()))
+            .unwrap_or_else(|_| "default".to_string())
+    });
+    format!("{:08x}", stable_hash(&id))
+}
+
 fn get_session_path(name: &str) -> Result<std::path::PathBuf> {
     let session_dir = get_session_dir()?;
-    let session_path = format!("{}/{name}", session_dir.display());
+    if name == LAST_SESSION {
+        let path = session_dir.join(format!(".last-{}", default_session_key()));
+        migrate_legacy_last_session(&session_dir, &path);
+        return Ok(path);
+    }
 
-    let session_path: std::path::PathBuf =
-        shellexpand::tilde(&session_path)
-            .into_owned()
-            .parse()
-            .context(format!("Failed to parse session path for '{}'", name))?;
+    validate_session_name(name)?;
+    Ok(session_dir.join(name))
+}
 
-    Ok(session_path)
+/// Before default sessions were kept per terminal, the default session was the file
+/// `last`. The first terminal to use `last` takes it over.
+fn migrate_legacy_last_session(session_dir: &std::path::Path, path: &std::path::Path) {
+    let legacy = session_dir.join(LAST_SESSION);
+    if !legacy.is_file() || path.exists() {
+        return;
+    }
+    if fs::rename(&legacy, path).is_ok() {
+        let _ = fs::remove_file(session_dir.join(format!(".{LAST_SESSION}.lock")));
+    }
+}
+
+fn get_last_session_pointer_path() -> Result<std::path::PathBuf> {
+    Ok(get_session_dir()?.join(format!(".last-session-{}", default_session_key())))
+}
+
+/// Takes an advisory lock on a session or another file in `~/.ask`, shared for readers
+/// and exclusive for writers. The lock is released when the returned file is dropped.
+pub(crate) fn lock_file(path: &std::path::Path, exclusive: bool) -> Result<fs::File> {
+    let lock_path = lock_path(path, "lock");
+    let lock_file = open_lock_file(&lock_path)?;
+
+    let locked = if exclusive {
+        lock_file.lock()
+    } else {
+        lock_file.lock_shared()
+    };
+    locked.context(format!("Failed to lock {:?}", lock_path))?;
+    Ok(lock_file)
+}
+
+/// Reserves a session for the whole of a run, so that two `ask` processes never
+/// continue the same session at once: each would save its own copy of the
+/// conversation and one run's turns would be lost. Fails at once instead of
+/// waiting when another run holds the session. The reservation ends when the
+/// returned file is dropped.
+pub fn reserve_session(name: &str) -> Result<fs::File> {
+    let lock_path = lock_path(&get_session_path(name)?, "run");
+    let lock_file = open_lock_file(&lock_path)?;
+    match lock_file.try_lock() {
+        Ok(()) => Ok(lock_file),
+        Err(fs::TryLockError::WouldBlock) => bail!(
+            "Session '{}' is in use by another ask process; wait for it to finish or use another session",
+            name
+        ),
+        Err(fs::TryLockError::Error(e)) => {
+            Err(e).context(format!("Failed to lock {:?}", lock_path))
+        }
+    }
+}
+
+/// The hidden file next to `path` that is locked on its behalf, e.g. `.name.lock`.
+fn lock_path(path: &std::path::Path, suffix: &str) -> std::path::PathBuf {
+    let file_name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    path.with_file_name(format!(".{file_name}.{suffix}"))
+}
+
+fn open_lock_file(lock_path: &std::path::Path) -> Result<fs::File> {
+    fs::OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(lock_path)
+        .context(format!("Failed to open lock file {:?}", lock_path))
+}
+
+/// Writes to a temporary file in the same directory and renames it over `path`, so
+/// readers never observe a partially written file.
+pub(crate) fn write_atomic(path: &std::path::Path, contents: &str) -> Result<()> {
+    let file_name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let temp_path = path.with_file_name(format!(".{file_name}.{}.tmp", std::process::id()));
+
+    fs::write(&temp_path, contents).context(format!("Failed to write {:?}", temp_path))?;
+    if let Err(e) = fs::rename(&temp_path, path) {
+        let _ = fs::remove_file(&temp_path);
+        return Err(e).context(format!("Failed to replace {:?}", path));
+    }
+    Ok(())
 }
 
 pub fn get_all_sessions() -> Result<Vec<Session>> {
@@ -71,8 +300,8 @@ pub fn get_all_sessions() -> Result<Vec<Session>> {
         let entry = entry.context("Failed to read session directory entry")?;
         let name = entry.file_name();
 
-        // Skip hidden session files
-        if name == ".last-session" || name == "last" {
+        // Skip default sessions and hidden bookkeeping files (pointers, locks, temp files)
+        if name == LAST_SESSION || name.to_string_lossy().starts_with('.') {
             continue;
         }
 
@@ -85,17 +314,299 @@ pub fn get_all_sessions() -> Result<Vec<Session>> {
         ))?;
 
         if let Some(name_str) = name.to_str() {
+            let messages = read_session_file(&entry.path())
+                .map(|data| data.messages)
+                .unwrap_or_default();
+            let preview = messages.iter().find_map(|message| match message {
+                ChatCompletionRequestMessage::User(_) => message_text(message).map(|(_, t)| t),
+                _ => None,
+            });
+
             result.push(Session {
                 name: name_str.to_string(),
                 created: system_time_to_string(modified_date),
+                modified: modified_date,
+                message_count: messages.len(),
+                preview,
+                size: metadata.len(),
             });
         }
     }
 
+    // Most recently used first.
+    result.sort_by_key(|session| std::cmp::Reverse(session.modified));
+
     Ok(result)
 }
 
-pub fn get_session(name: &str) -> Option<Vec<ChatCompletionRequestMessage>> {
+fn read_session_file(path: &std::path::Path) -> Result<SessionData> {
+    let _lock = lock_file(path, false)?;
+    parse_session_file(path)
+}
+
+fn parse_session_file(path: &std::path::Path) -> Result<SessionData> {
+    let contents =
+        fs::read_to_string(path).context(format!("Failed to read session file {:?}", path))?;
+    let file: SessionFile = serde_json::from_str(&contents)
+        .context(format!("Failed to parse session file {:?}", path))?;
+    Ok(match file {
+        SessionFile::Current(data) => data,
+        SessionFile::Legacy(messages) => SessionData {
+            messages,
+            ..Default::default()
+        },
+    })
+}
+
+/// Returns the role label and plain text of a message, ignoring non-text content.
+pub fn message_text(message: &ChatCompletionRequestMessage) -> Option<(&'static str, String)> {
+    match message {
+        ChatCompletionRequestMessage::System(m) => Some((
+            "System",
+            match &m.content {
+                ChatCompletionRequestSystemMessageContent::Text(text) => text.clone(),
+                ChatCompletionRequestSystemMessageContent::Array(parts) => parts
+                    .iter()
+                    .map(|ChatCompletionRequestSystemMessageContentPart::Text(t)| t.text.as_str())
+                    .collect::<Vec<_>>()
+                    .join("\n"),
+            },
+        )),
+        ChatCompletionRequestMessage::User(m) => Some((
+            "User",
+            match &m.content {
+                ChatCompletionRequestUserMessageContent::Text(text) => text.clone(),
+                ChatCompletionRequestUserMessageContent::Array(parts) => parts
+                    .iter()
+                    .filter_map(|part| match part {
+                        ChatCompletionRequestUserMessageContentPart::Text(t) => {
+                            Some(t.text.as_str())
+                        }
+                        _ => None,
+                    })
+                    .collect::<Vec<_>>()
+                    .join("\n"),
+            },
+        )),
+        ChatCompletionRequestMessage::Assistant(m) => match &m.content {
+            Some(ChatCompletionRequestAssistantMessageContent::Text(text)) => {
+                Some(("Assistant", text.clone()))
+            }
+            Some(ChatCompletionRequestAssistantMessageContent::Array(parts)) => Some((
+                "Assistant",
+                parts
+                    .iter()
+                    .filter_map(|part| match part {
+                        ChatCompletionRequestAssistantMessageContentPart::Text(t) => {
+                            Some(t.text.as_str())
+                        }
+                        _ => None,
+                    })
+                    .collect::<Vec<_>>()
+                    .join("\n"),
+            )),
+            None => None,
+        },
+        ChatCompletionRequestMessage::Tool(m) => Some((
+            "Tool",
+            match &m.content {
+                ChatCompletionRequestToolMessageContent::Text(text) => text.clone(),
+                ChatCompletionRequestToolMessageContent::Array(parts) => parts
+                    .iter()
+                    .map(|ChatCompletionRequestToolMessageContentPart::Text(t)| t.text.as_str())
+                    .collect::<Vec<_>>()
+                    .join("\n"),
+            },
+        )),
+        _ => None,
+    }
+}
+
+/// Case-insensitive full-text search over the user, assistant and tool messages of
+/// every session, including `last`.
+pub fn search_sessions(query: &str) -> Result<Vec<SearchHit>> {
+    let pattern = regex::RegexBuilder::new(&regex::escape(query))
+        .case_insensitive(true)
+        .build()
+        .context("Failed to build search pattern")?;
+    let mut hits = vec![];
+
+    let mut sessions = get_all_sessions()?;
+    if get_session_path(LAST_SESSION)?.exists() {
+        sessions.insert(
+            0,
+            Session {
+                name: LAST_SESSION.to_string(),
+                created: String::new(),
+                modified: SystemTime::now(),
+                message_count: 0,
+                preview: None,
+                size: 0,
+            },
+        );
+    }
+
+    for session in sessions {
+        let messages = match read_session_file(&get_session_path(&session.name)?) {
+            Ok(data) => data.messages,
+            Err(_) => continue,
+        };
+
+        for message in &messages {
+            let Some((role, text)) = message_text(message) else {
+                continue;
+            };
+            if role == "System" {
+                continue;
+            }
+            // Matched on the original text, so the range is its own even where
+            // lowercasing would change byte lengths.
+            if let Some(found) = pattern.find(&text) {
+                hits.push(SearchHit {
+                    session: session.name.clone(),
+                    role,
+                    match_range: (found.start(), found.end()),
+                    text,
+                });
+            }
+        }
+    }
+
+    Ok(hits)
+}
+
+pub fn session_exists(name: &str) -> bool {
+    get_session_path(name).map(|p| p.exists()).unwrap_or(false)
+}
+
+pub fn rename_session(name: &str, new_name: &str) -> Result<()> {
+    let from = get_session_path(name)?;
+    let to = get_session_path(new_name)?;
+    if !from.exists() {
+        bail!("Session '{}' not found", name);
+    }
+    if to.exists() {
+        bail!("Session '{}' already exists", new_name);
+    }
+    let _reservation = reserve_session(name)?;
+    {
+        // Lock both names in a fixed order and check again under the locks, so a
+        // concurrent save or rename to `new_name` is never overwritten.
+        let (first, second) = if from < to {
+            (&from, &to)
+        } else {
+            (&to, &from)
+        };
+        let _first = lock_file(first, true)?;
+        let _second = lock_file(second, true)?;
+        if !from.exists() {
+            bail!("Session '{}' not found", name);
+        }
+        if to.exists() {
+            bail!("Session '{}' already exists", new_name);
+        }
+        fs::rename(&from, &to)
+            .context(format!("Failed to rename session {:?} to {:?}", from, to))?;
+    }
+    let _ = fs::remove_file(lock_path(&from, "lock"));
+    let _ = fs::remove_file(lock_path(&from, "run"));
+
+    if get_last_session_name().as_deref() == Some(name) {
+        set_last_session_name(new_name)?;
+    }
+    Ok(())
+}
+
+pub fn copy_session(name: &str, new_name: &str) -> Result<()> {
+    fork_session(name, None, new_name)
+}
+
+/// Indices of the user messages that start each turn. Turn `n` (1-based) starts at
+/// `turn_starts(messages)[n - 1]` and runs until the next user message.
+pub fn turn_starts(messages: &[ChatCompletionRequestMessage]) -> Vec<usize> {
+    messages
+        .iter()
+        .enumerate()
+        .filter(|(_, message)| matches!(message, ChatCompletionRequestMessage::User(_)))
+        .map(|(index, _)| index)
+        .collect()
+}
+
+/// Keeps everything up to and including turn `turn` (1-based).
+pub fn truncate_to_turn(
+    messages: &[ChatCompletionRequestMessage],
+    turn: usize,
+) -> Result<Vec<ChatCompletionRequestMessage>> {
+    let starts = turn_starts(messages);
+    if turn == 0 || turn > starts.len() {
+        bail!(
+            "Turn {} does not exist; the session has {} turn(s)",
+            turn,
+            starts.len()
+        );
+    }
+    let end = starts.get(turn).copied().unwrap_or(messages.len());
+    Ok(messages[..end].to_vec())
+}
+
+/// Copies a session under a new name, optionally only up to the given turn.
+pub fn fork_session(name: &str, turn: Option<usize>, new_name: &str) -> Result<()> {
+    if session_exists(new_name) {
+        bail!("Session '{}' already exists", new_name);
+    }
+    let mut data = get_session_data(name).context(format!("Session '{}' not found", name))?;
+    if let Some(turn) = turn {
+        data.messages = truncate_to_turn(&data.messages, turn)?;
+        data.turns.truncate(turn);
+    }
+    save_session_data(new_name, &data)
+}
+
+/// Returns `base` if no session by that name exists, otherwise `base-2`, `base-3`, ...
+pub fn unused_session_name(base: &str) -> String {
+    if !session_exists(base) {
+        return base.to_string();
+    }
+    (2..)
+        .map(|n| format!("{base}-{n}"))
+        .find(|name| !session_exists(name))
+        .unwrap_or_else(|| base.to_string())
+}
+
+/// Removes the last turn of a session so its question can be asked again. The
+/// original thread is preserved as a separate session whose name is returned,
+/// together with the removed question.
+pub fn rewind_last_turn(name: &str) -> Result<(String, String)> {
+    let data = get_session_data(name).context(format!("Session '{}' not found", name))?;
+    let starts = turn_starts(&data.messages);
+    let Some(&last_start) = starts.last() else {
+        bail!("Session '{}' has no question to edit", name);
+    };
+    let question = message_text(&data.messages[last_start])
+        .map(|(_, text)| text)
+        .unwrap_or_default();
+
+    let backup = unused_session_name(&format!("{name}-orig"));
+    save_session_data(&backup, &data)?;
+
+    let mut rewound = data;
+    rewound.messages.truncate(last_start);
+    rewound.turns.truncate(starts.len() - 1);
+    save_session_data(name, &rewound)?;
+
+    Ok((backup, question))
+}
+
+/// Imports a session previously exported as JSON.
+pub fn import_session(path: &std::path::Path, name: &str) -> Result<()> {
+    if session_exists(name) {
+        bail!("Session '{}' already exists", name);
+    }
+    let data = parse_session_file(path)?;
+    save_session_data(name, &data)
+}
+
+pub fn get_session_data(name: &str) -> Option<SessionData> {
     let session_path = match get_session_path(name) {
         Ok(path) => path,
         Err(e) => {
@@ -109,33 +620,29 @@ pub fn get_session(name: &str) -> Option<Vec<ChatCompletionRequestMessage>> {
         return None;
     }
 
-    let session_file = match fs::File::open(&session_path) {
-        Ok(file) => file,
-        Err(e) => {
-            eprintln!("Failed to open session file {:?}: {}", session_path, e);
-            return None;
-        }
-    };
-
-    match serde_json::from_reader(session_file) {
+    match read_session_file(&session_path) {
         Ok(data) => Some(data),
         Err(e) => {
-            eprintln!("Failed to parse session '{}': {}", name, e);
+            eprintln!("Failed to load session '{}': {:#}", name, e);
             None
         }
     }
 }
 
+/// Saves a conversation, appending the model's response if given. Turns already in
+/// the stored session keep their metadata; new turns are stamped with the current
+/// time and `model`.
 pub fn save_session(
     name: &str,
     request: &[ChatCompletionRequestMessage],
     res: Option<&ChatCompletionResponseMessage>,
+    model: Option<&str>,
 ) -> Result<()> {
     let session_path = get_session_path(name)?;
 
-    let mut session = request.to_owned();
+    let mut messages = request.to_owned();
     if let Some(res) = res {
-        session.push(ChatCompletionRequestMessage::Assistant(
+        messages.push(ChatCompletionRequestMessage::Assistant(
             ChatCompletionRequestAssistantMessage {
                 content: res.clone().content.map(|c| c.into()),
                 ..Default::default()
@@ -143,40 +650,234 @@ pub fn save_session(
         ));
     }
 
-    let session_json =
-        serde_json::to_string_pretty(&session).context("Failed to serialize session to JSON")?;
+    {
+        let _lock = lock_file(&session_path, true)?;
+        let existing = if session_path.exists() {
+            parse_session_file(&session_path).ok()
+        } else {
+            None
+        };
+        let mut turns = existing
+            .as_ref()
+            .map(|existing| carry_over_turns(existing, &messages))
+            .unwrap_or_default();
+        let turn_count = turn_starts(&messages).len();
+        turns.truncate(turn_count);
+        turns.resize(
+            turn_count,
+            TurnInfo {
+                timestamp: Some(Local::now().to_rfc3339()),
+                model: model.map(str::to_string),
+            },
+        );
 
-    fs::write(&session_path, session_json)
-        .context(format!("Failed to write session to {:?}", session_path))?;
+        let shell = existing.and_then(|existing| existing.shell);
+        write_session_file(
+            &session_path,
+            &SessionData {
+                messages,
+                turns,
+                shell,
+            },
+        )?;
+    }
 
     set_last_session_name(name)?;
     Ok(())
 }
 
+/// Metadata of the leading turns of `existing` that `messages` still starts with.
+fn carry_over_turns(
+    existing: &SessionData,
+    messages: &[ChatCompletionRequestMessage],
+) -> Vec<TurnInfo> {
+    let old_starts = turn_starts(&existing.messages);
+    let new_starts = turn_starts(messages);
+    old_starts
+        .iter()
+        .zip(&new_starts)
+        .take_while(|(old, new)| {
+            message_text(&existing.messages[**old]) == message_text(&messages[**new])
+        })
+        .enumerate()
+        .map(|(turn, _)| existing.turns.get(turn).cloned().unwrap_or_default())
+        .collect()
+}
+
+/// Writes a session together with its turn metadata, replacing any existing one.
+pub fn save_session_data(name: &str, data: &SessionData) -> Result<()> {
+    let session_path = get_session_path(name)?;
+    {
+        let _lock = lock_file(&session_path, true)?;
+        write_session_file(&session_path, data)?;
+    }
+
+    set_last_session_name(name)?;
+    Ok(())
+}
+
+/// Stores the persistent shell's state with an existing session.
+pub fn save_shell_state(name: &str, state: ShellState) -> Result<()> {
+    let session_path = get_session_path(name)?;
+    let _lock = lock_file(&session_path, true)?;
+    let mut data = parse_session_file(&session_path)?;
+    data.shell = Some(state);
+    write_session_file(&session_path, &data)
+}
+
+fn write_session_file(session_path: &std::path::Path, data: &SessionData) -> Result<()> {
+    let session_json =
+        serde_json::to_string_pretty(data).context("Failed to serialize session to JSON")?;
+    write_atomic(session_path, &session_json)
+        .context(format!("Failed to write session to {:?}", session_path))
+}
+
 pub fn delete_session(name: &str) -> Result<()> {
     let session_path = get_session_path(name)?;
-    match fs::remove_file(&session_path) {
-        Ok(_) => Ok(()),
-        Err(e) => {
-            if e.kind() == std::io::ErrorKind::NotFound {
-                eprintln!("Session not found: {:?}", session_path);
+    if let Err(e) = fs::metadata(&session_path) {
+        if e.kind() == std::io::ErrorKind::NotFound {
+            eprintln!("Session not found: {:?}", session_path);
+        }
+        return Err(e.into());
+    }
+    remove_session_file(&session_path)
+}
+
+/// Parses an age such as `90m`, `12h`, `30d` or `2w`.
+pub fn parse_age(text: &str) -> Result<chrono::Duration> {
+    let text = text.trim();
+    let split = text
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(text.len());
+    let (amount, unit) = text.split_at(split);
+    let amount: i64 = amount.parse().context(format!(
+        "Invalid age '{}'. Expected e.g. 30d, 12h or 2w",
+        text
+    ))?;
+
+    match unit {
+        "s" => Ok(chrono::Duration::seconds(amount)),
+        "m" => Ok(chrono::Duration::minutes(amount)),
+        "h" => Ok(chrono::Duration::hours(amount)),
+        "d" | "" => Ok(chrono::Duration::days(amount)),
+        "w" => Ok(chrono::Duration::weeks(amount)),
+        _ => bail!(
+            "Invalid age unit '{}' in '{}'. Use s, m, h, d or w",
+            unit,
+            text
+        ),
+    }
+}
+
+fn is_older_than(modified: SystemTime, age: chrono::Duration) -> bool {
+    let modified: DateTime<Local> = modified.into();
+    Local::now() - modified > age
+}
+
+fn remove_session_file(path: &std::path::Path) -> Result<()> {
+    {
+        let _lock = lock_file(path, true)?;
+        fs::remove_file(path).context(format!("Failed to delete {:?}", path))?;
+    }
+    let _ = fs::remove_file(lock_path(path, "lock"));
+    let _ = fs::remove_file(lock_path(path, "run"));
+    Ok(())
+}
+
+/// Deletes named sessions that are both beyond the `keep` most recent ones and not
+/// used for longer than `older_than`. Either limit may be omitted. The default
+/// sessions of other terminals only expire by age, together with their pointers.
+pub fn prune_sessions(
+    older_than: Option<chrono::Duration>,
+    keep: Option<usize>,
+    dry_run: bool,
+) -> Result<Vec<PrunedSession>> {
+    if older_than.is_none() && keep.is_none() {
+        bail!("Nothing to prune: specify an age, a number of sessions to keep, or both");
+    }
+
+    let mut pruned = vec![];
+    for session in get_all_sessions()?.into_iter().skip(keep.unwrap_or(0)) {
+        if older_than.is_some_and(|age| !is_older_than(session.modified, age)) {
+            continue;
+        }
+        if !dry_run {
+            remove_session_file(&get_session_path(&session.name)?)?;
+        }
+        pruned.push(PrunedSession {
+            name: session.name,
+            size: session.size,
+        });
+    }
+
+    let Some(age) = older_than else {
+        return Ok(pruned);
+    };
+    let session_dir = get_session_dir()?;
+    for entry in fs::read_dir(&session_dir)?.flatten() {
+        let file_name = entry.file_name().to_string_lossy().to_string();
+        let Some(key) = file_name.strip_prefix(".last-") else {
+            continue;
+        };
+        if key.contains('.') {
+            // Lock and temporary files
+            continue;
+        }
+        let Ok(metadata) = entry.metadata() else {
+            continue;
+        };
+        if !metadata.modified().is_ok_and(|m| is_older_than(m, age)) {
+            continue;
+        }
+
+        if key.starts_with("session-") {
+            if !dry_run {
+                let _ = fs::remove_file(entry.path());
             }
+            continue;
+        }
 
-            Err(e.into())
+        if !dry_run {
+            remove_session_file(&entry.path())?;
         }
+        pruned.push(PrunedSession {
+            name: format!("{LAST_SESSION} ({key})"),
+            size: metadata.len(),
+        });
     }
+
+    Ok(pruned)
+}
+
+/// Runs `prune_sessions` at most once a day, for policies applied automatically.
+/// Returns `None` when pruning was skipped because it ran recently.
+pub fn prune_sessions_periodically(
+    older_than: Option<chrono::Duration>,
+    keep: Option<usize>,
+) -> Result<Option<Vec<PrunedSession>>> {
+    let stamp_path = get_session_dir()?.join(".prune-stamp");
+    if let Ok(modified) = fs::metadata(&stamp_path).and_then(|m| m.modified())
+        && !is_older_than(modified, chrono::Duration::days(1))
+    {
+        return Ok(None);
+    }
+
+    let pruned = prune_sessions(older_than, keep, false)?;
+    fs::write(&stamp_path, "").context(format!("Failed to write {:?}", stamp_path))?;
+    Ok(Some(pruned))
 }
 
+/// The session most recently used from this terminal (or directory).
 pub fn get_last_session_name() -> Option<String> {
-    let session_path = get_session_path(".last-session").ok()?;
-    fs::read_to_string(session_path).ok()
+    let pointer_path = get_last_session_pointer_path().ok()?;
+    fs::read_to_string(pointer_path).ok()
 }
 
 fn set_last_session_name(name: &str) -> Result<()> {
-    let session_path = get_session_path(".last-session")?;
-    fs::write(&session_path, name).context(format!(
+    let pointer_path = get_last_session_pointer_path()?;
+    write_atomic(&pointer_path, name).context(format!(
         "Failed to write last session name to {:?}",
-        session_path
+        pointer_path
     ))?;
     Ok(())
 }
@@ -1,42 +1,288 @@
-use std::{collections::HashMap, path::Path};
+use once_cell::sync::Lazy;
+use std::path::{Path, PathBuf};
+use std::process::Command;
 
-pub fn detect_shell_kind() -> String {
-    let env: HashMap<_, _> = std::env::vars().collect();
+/// Shells that can run commands through `-l -c` when `loginShell` is enabled.
+const LOGIN_SHELLS: [&str; 3] = ["bash", "zsh", "fish"];
 
-    if env.contains_key("POWERSHELL_DISTRIBUTION_CHANNEL")
-        || env.contains_key("PSModulePath")
-        || env.contains_key("PSExecutionPolicyPreference")
-    {
-        return "Powershell".to_string();
+const KNOWN_SHELLS: [&str; 9] = [
+    "bash",
+    "zsh",
+    "fish",
+    "sh",
+    "dash",
+    "ksh",
+    "pwsh",
+    "powershell",
+    "cmd",
+];
+
+/// Which flavour of the standard command line tools is installed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CoreUtils {
+    Gnu,
+    Bsd,
+    BusyBox,
+}
+
+/// The user's shell and operating system, detected once per run.
+#[derive(Debug)]
+pub struct ShellInfo {
+    /// `bash`, `zsh`, `fish`, `pwsh`, `powershell`, `cmd`, ...
+    pub name: String,
+    pub path: Option<PathBuf>,
+    pub version: Option<String>,
+    /// `POSIX`, `Powershell` or `cmd`.
+    pub kind: String,
+    /// e.g. `Ubuntu 24.04.1 LTS (x86_64)`
+    pub os: String,
+    pub core_utils: Option<CoreUtils>,
+    /// Commands run with the user's shell as a login shell instead of `sh`.
+    pub login_shell: bool,
+}
+
+static SHELL_INFO: Lazy<ShellInfo> = Lazy::new(ShellInfo::detect);
+
+pub fn shell_info() -> &'static ShellInfo {
+    &SHELL_INFO
+}
+
+impl ShellInfo {
+    fn detect() -> Self {
+        let name = detect_shell_name();
+        let path = shell_path(&name);
+        let version = path.as_deref().and_then(|path| shell_version(&name, path));
+        let kind = match name.as_str() {
+            "pwsh" | "powershell" => "Powershell",
+            "cmd" => "cmd",
+            _ => "POSIX",
+        }
+        .to_string();
+
+        let login_shell = path.is_some()
+            && LOGIN_SHELLS.contains(&name.as_str())
+            && crate::config::load_config()
+                .ok()
+                .and_then(|config| config.login_shell)
+                .unwrap_or(false);
+
+        ShellInfo {
+            name,
+            path,
+            version,
+            kind,
+            os: detect_os(),
+            core_utils: detect_core_utils(),
+            login_shell,
+        }
+    }
+
+    /// The program and arguments commands are run with, e.g. `["sh", "-c"]`.
+    pub fn invocation(&self) -> Vec<String> {
+        if self.login_shell
+            && let Some(path) = &self.path
+        {
+            return vec![path.display().to_string(), "-l".into(), "-c".into()];
+        }
+
+        if self.kind == "Powershell" && cfg!(windows) {
+            vec!["powershell".into(), "-Command".into()]
+        } else if cfg!(windows) {
+            vec!["cmd".into(), "/C".into()]
+        } else {
+            vec!["sh".into(), "-c".into()]
+        }
+    }
+
+    /// A command that runs `command` with the shell commands are executed with.
+    pub fn command(&self, command: &str) -> Command {
+        let invocation = self.invocation();
+        let mut process = Command::new(&invocation[0]);
+        process
+            .args(&invocation[1..])
+            .arg(format!("{}{command}", self.rc_prelude()));
+        process
+    }
+
+    /// Script that makes a login bash or zsh read the user's interactive rc file and
+    /// expand its aliases, which non-interactive shells skip. Fish reads `config.fish`
+    /// by itself. The rc file's output is discarded; rc files that return early when
+    /// not interactive, as Debian's default `~/.bashrc` does, define nothing.
+    pub fn rc_prelude(&self) -> &'static str {
+        if !self.login_shell {
+            return "";
+        }
+        // Aliases only apply to lines read after they are defined, hence the newline.
+        match self.name.as_str() {
+            "bash" => {
+                "shopt -s expand_aliases\n\
+                 if [ -f ~/.bashrc ]; then . ~/.bashrc >/dev/null 2>&1; fi\n"
+            }
+            "zsh" => {
+                "if [ -f \"${ZDOTDIR:-$HOME}/.zshrc\" ]; then \
+                 . \"${ZDOTDIR:-$HOME}/.zshrc\" >/dev/null 2>&1; fi\n"
+            }
+            _ => "",
+        }
+    }
+
+    /// The syntax commands must use: the login shell's, or the kind of `sh`/`cmd`/PowerShell.
+    pub fn command_syntax(&self) -> String {
+        if self.login_shell {
+            match &self.version {
+                Some(version) => format!("{} {version}", self.name),
+                None => self.name.clone(),
+            }
+        } else if cfg!(windows) {
+            self.kind.clone()
+        } else {
+            "POSIX sh".to_string()
+        }
     }
 
-    if env.contains_key("SHELL")
-        || env.contains_key("BASH_VERSION")
-        || env.contains_key("ZSH_VERSION")
-        || env.contains_key("FISH_VERSION")
+    /// e.g. `zsh 5.9 (/bin/zsh)`
+    pub fn describe_shell(&self) -> String {
+        let mut text = self.name.clone();
+        if let Some(version) = &self.version {
+            text.push_str(&format!(" {version}"));
+        }
+        if let Some(path) = &self.path {
+            text.push_str(&format!(" ({})", path.display()));
+        }
+        text
+    }
+}
+
+/// Lowercased name without `.exe` or the `-` login shells are started with.
+fn normalize_shell_name(name: &str) -> String {
+    let name = name.trim().trim_start_matches('-').to_lowercase();
+    name.strip_suffix(".exe").unwrap_or(&name).to_string()
+}
+
+fn detect_shell_name() -> String {
+    // The process that started `ask` is the most reliable signal: $SHELL is the login
+    // shell even when the user switched to another one.
+    if let Some(parent) = parent_process_name().map(|name| normalize_shell_name(&name))
+        && KNOWN_SHELLS.contains(&parent.as_str())
     {
-        return "POSIX".to_string();
+        return parent;
     }
 
-    if cfg!(windows)
-        && let Ok(comspec) = std::env::var("ComSpec")
+    if std::env::var_os("POWERSHELL_DISTRIBUTION_CHANNEL").is_some() {
+        return "pwsh".to_string();
+    }
+
+    if let Ok(shell) = std::env::var("SHELL")
+        && let Some(name) = Path::new(&shell).file_name().and_then(|n| n.to_str())
     {
-        let name = Path::new(&comspec)
+        return normalize_shell_name(name);
+    }
+
+    if cfg!(windows) {
+        // PSModulePath is set system-wide on Windows, so it doesn't mean PowerShell is
+        // the current shell; without better information assume the default, cmd.exe.
+        return "cmd".to_string();
+    }
+
+    "sh".to_string()
+}
+
+fn shell_path(name: &str) -> Option<PathBuf> {
+    if let Ok(shell) = std::env::var("SHELL") {
+        let path = PathBuf::from(shell);
+        if path
             .file_name()
             .and_then(|n| n.to_str())
-            .unwrap_or("");
-        if name.eq_ignore_ascii_case("cmd.exe") {
-            return "Powershell".to_string();
+            .is_some_and(|file_name| normalize_shell_name(file_name) == name)
+        {
+            return Some(path);
         }
     }
 
-    match parent_process_name().as_deref() {
-        Some("pwsh") | Some("powershell") | Some("powershell.exe") | Some("pwsh.exe") => {
-            "Powershell".to_string()
+    let file_name = if cfg!(windows) {
+        format!("{name}.exe")
+    } else {
+        name.to_string()
+    };
+    std::env::split_paths(&std::env::var_os("PATH")?)
+        .map(|dir| dir.join(&file_name))
+        .find(|candidate| candidate.is_file())
+}
+
+fn shell_version(name: &str, path: &Path) -> Option<String> {
+    static VERSION: Lazy<regex::Regex> =
+        Lazy::new(|| regex::Regex::new(r"\d+(\.\d+)+").expect("Failed to compile regex"));
+
+    let output = match name {
+        "bash" | "zsh" | "fish" | "ksh" | "pwsh" => {
+            Command::new(path).arg("--version").output().ok()?
+        }
+        "powershell" => Command::new(path)
+            .args([
+                "-NoProfile",
+                "-Command",
+                "$PSVersionTable.PSVersion.ToString()",
+            ])
+            .output()
+            .ok()?,
+        _ => return None,
+    };
+    let text = String::from_utf8_lossy(&output.stdout);
+    VERSION
+        .find(text.lines().next()?)
+        .map(|version| version.as_str().to_string())
+}
+
+fn detect_os() -> String {
+    let arch = std::env::consts::ARCH;
+    let name = if cfg!(target_os = "linux") {
+        std::fs::read_to_string("/etc/os-release")
+            .ok()
+            .and_then(|release| {
+                release.lines().find_map(|line| {
+                    line.strip_prefix("PRETTY_NAME=")
+                        .map(|name| name.trim_matches('"').to_string())
+                })
+            })
+            .unwrap_or_else(|| "Linux".to_string())
+    } else if cfg!(target_os = "macos") {
+        Command::new("sw_vers")
+            .arg("-productVersion")
+            .output()
+            .ok()
+            .map(|output| format!("macOS {}", String::from_utf8_lossy(&output.stdout).trim()))
+            .unwrap_or_else(|| "macOS".to_string())
+    } else if cfg!(windows) {
+        "Windows".to_string()
+    } else {
+        std::env::consts::OS.to_string()
+    };
+    format!("{name} ({arch})")
+}
+
+fn detect_core_utils() -> Option<CoreUtils> {
+    if cfg!(windows) {
+        return None;
+    }
+
+    // GNU and uutils accept --version; BusyBox reports itself in the error; BSD `ls`
+    // just rejects the option.
+    match Command::new("ls").arg("--version").output() {
+        Ok(output) => {
+            let text = format!(
+                "{}{}",
+                String::from_utf8_lossy(&output.stdout),
+                String::from_utf8_lossy(&output.stderr)
+            );
+            if text.contains("BusyBox") {
+                Some(CoreUtils::BusyBox)
+            } else if output.status.success() && text.contains("coreutils") {
+                Some(CoreUtils::Gnu)
+            } else {
+                Some(CoreUtils::Bsd)
+            }
         }
-        Some("bash") | Some("zsh") | Some("fish") | Some("sh") | Some("bash.exe")
-        | Some("zsh.exe") | Some("fish.exe") | Some("sh.exe") => "POSIX".to_string(),
-        _ => "POSIX".to_string(),
+        Err(_) => None,
     }
 }
 
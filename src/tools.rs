@@ -1,30 +1,45 @@
+pub(crate) mod command_risk;
 pub(crate) mod mcp;
+pub(crate) mod mcp_client;
+pub(crate) mod native;
+pub(crate) mod persistent_shell;
+pub(crate) mod progress;
+pub(crate) mod schema;
 pub(crate) mod tool_cache;
 
 use async_openai::types::{ChatCompletionTool, ChatCompletionToolType, FunctionObject};
 use serde::{Deserialize, Serialize};
 use serde_json::json;
+use std::path::{Component, Path, PathBuf};
 
 #[derive(Deserialize, Serialize)]
 pub struct ExecuteCommandRequest {
     pub command: String,
+    #[serde(default)]
     pub working_directory: String,
 }
 
-pub fn execute_command(command: &str, working_directory: &str) -> String {
-    let shell_kind = crate::shell::detect_shell_kind();
-
-    let (shell, flag) = if shell_kind == "Powershell" && cfg!(windows) {
-        ("powershell", "-Command")
-    } else if cfg!(windows) {
-        ("cmd", "/C")
-    } else {
-        ("sh", "-c")
-    };
+/// Resolves `.` and `..` in `path` without touching the filesystem, so symlinks are
+/// not followed.
+pub fn normalize_path(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            component => normalized.push(component),
+        }
+    }
+    normalized
+}
 
-    let output = std::process::Command::new(shell)
-        .arg(flag)
-        .arg(command)
+/// Runs `command` and returns its output and exit code. The exit code is `None` when
+/// the command couldn't be started or was killed by a signal.
+pub fn execute_command(command: &str, working_directory: &str) -> (String, Option<i32>) {
+    let output = crate::shell::shell_info()
+        .command(command)
         .current_dir(working_directory)
         .output();
 
@@ -33,31 +48,72 @@ pub fn execute_command(command: &str, working_directory: &str) -> String {
             let stdout = String::from_utf8_lossy(&output.stdout).to_string();
             let stderr = String::from_utf8_lossy(&output.stderr).to_string();
 
-            if stderr.is_empty() {
+            let result = if stderr.is_empty() {
                 stdout
             } else {
                 format!("stdout:\n{stdout}\n---\nstderr:\n{stderr}")
-            }
+            };
+            (result, output.status.code())
         }
-        Err(e) => format!("Failed to execute command '{command}': {e}"),
+        Err(e) => (format!("Failed to execute command '{command}': {e}"), None),
     }
 }
 
-pub fn execute_command_tool() -> ChatCompletionTool {
+/// The `execute_command` tool. With a persistent shell the working directory is
+/// optional, since the shell keeps its directory between calls.
+pub fn execute_command_tool(persistent_shell: bool) -> ChatCompletionTool {
+    let (description, working_directory, required) = if persistent_shell {
+        (
+            "Execute a command in a persistent shell on the Operating System. The directory, \
+             exported variables and activated environments carry over to later commands. \
+             Results end with the exit status and the shell's working directory.",
+            "Directory to change to before running the command. Omit it to stay in the \
+             shell's current directory.",
+            json!(["command"]),
+        )
+    } else {
+        (
+            "Execute a command on the Operating System",
+            "The working directory for the command execution (optional)",
+            json!(["command", "working_directory"]),
+        )
+    };
+
     ChatCompletionTool {
         r#type: ChatCompletionToolType::Function,
         function: FunctionObject {
             name: "execute_command".to_string(),
-            description: Some("Execute a command on the Operating System".to_string()),
+            description: Some(description.to_string()),
             parameters: Some(json!({
                 "type": "object",
                 "properties": {
                     "command": {"type": "string", "description": "The command to be executed"},
-                    "working_directory": {"type": "string", "description": "The working directory for the command execution (optional)"}
+                    "working_directory": {"type": "string", "description": working_directory}
                 },
-                "required": ["command", "working_directory"]
+                "required": required
             })),
             strict: None,
         },
     }
 }
+
+/// Matches a tool name against a pattern in which `*` stands for any characters.
+pub fn matches_tool_pattern(pattern: &str, name: &str) -> bool {
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or_default();
+    let Some(mut rest) = name.strip_prefix(first) else {
+        return false;
+    };
+
+    let parts: Vec<&str> = parts.collect();
+    let Some((last, middle)) = parts.split_last() else {
+        return rest.is_empty();
+    };
+    for part in middle {
+        match rest.find(part) {
+            Some(index) => rest = &rest[index + part.len()..],
+            None => return false,
+        }
+    }
+    rest.len() >= last.len() && rest.ends_with(last)
+}
@@ -1,35 +1,144 @@
+use crate::tools::mcp_client::{AskClientHandler, server_log_path};
 use crate::tools::tool_cache::{McpRegistry, McpService, update_cache_for_server};
+use anyhow::bail;
 use async_openai::types::{ChatCompletionTool, ChatCompletionToolType, FunctionObject};
-use rmcp::model::CallToolRequestParam;
-use rmcp::service::ServiceExt;
+use rmcp::model::{CallToolRequest, CallToolRequestParam, ClientRequest, ServerResult};
+use rmcp::service::{PeerRequestOptions, ServiceExt};
 use rmcp::transport::TokioChildProcess;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
-use std::collections::HashMap;
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet};
 use std::process::Stdio;
+use std::time::Duration;
 use tokio::process::Command;
 
+/// OpenAI only accepts function names matching `^[a-zA-Z0-9_-]{1,64}$`.
+const MAX_TOOL_NAME_LEN: usize = 64;
+
+/// How many times a crashed MCP server is restarted before giving up for the run.
+const MAX_RESTARTS: u32 = 3;
+/// Initial delay before restarting a crashed server; doubled on every attempt.
+const RESTART_BACKOFF: Duration = Duration::from_millis(500);
+
+/// Where an exposed (mangled) tool name is routed to.
+#[derive(Clone, Debug)]
+pub struct ToolRoute {
+    pub server: String,
+    pub tool: String,
+    pub input_schema: Option<Value>,
+    /// The server declared that the tool doesn't modify its environment.
+    pub read_only: bool,
+}
+
+/// An MCP tool as advertised by its server, together with the OpenAI definition
+/// exposed to the model under a mangled name.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct McpToolDefinition {
+    pub tool_name: String,
+    pub definition: ChatCompletionTool,
+    /// The tool's `readOnlyHint` annotation. Tools cached before it was recorded count
+    /// as mutating.
+    #[serde(default)]
+    pub read_only: bool,
+}
+
 impl McpRegistry {
     pub fn new() -> Self {
         Self {
             servers: HashMap::new(),
             services: HashMap::new(),
+            tools: HashMap::new(),
+            restarts: HashMap::new(),
+            handler: AskClientHandler::default(),
+            builtin_tools: HashSet::new(),
         }
     }
 
-    pub fn from_servers(servers: Vec<(String, McpServerConfig)>) -> Self {
-        for (name, _) in &servers {
-            if name.contains(".") {
-                eprintln!(
-                    "Warning: MCP server name '{name}' contains a '.' which is not allowed. Please rename it."
+    pub fn from_servers(servers: Vec<(String, McpServerConfig)>) -> anyhow::Result<Self> {
+        for (name, config) in &servers {
+            if sanitize_tool_name(&config.tool_prefix)
+                .trim_matches('_')
+                .is_empty()
+            {
+                bail!(
+                    "MCP server name '{name}' cannot be used as a tool prefix. \
+                     Use letters, digits, '_' or '-'."
                 );
-                std::process::exit(1);
             }
         }
-        Self {
+        Ok(Self {
             servers: servers.into_iter().collect(),
             services: HashMap::new(),
+            tools: HashMap::new(),
+            restarts: HashMap::new(),
+            handler: AskClientHandler::default(),
+            builtin_tools: HashSet::new(),
+        })
+    }
+
+    pub fn with_handler(mut self, handler: AskClientHandler) -> Self {
+        self.handler = handler;
+        self
+    }
+
+    /// Reserves the names of the tools offered next to the MCP tools, e.g.
+    /// `execute_command`, so no server's tool is exposed under one of them.
+    pub fn with_builtin_tools<'a>(mut self, names: impl IntoIterator<Item = &'a str>) -> Self {
+        self.builtin_tools = names.into_iter().map(str::to_string).collect();
+        self
+    }
+
+    /// Records the exposed names of a server's tools so calls can be routed back to the
+    /// original server and tool. Fails if a name is invalid for the API or already taken
+    /// by a built-in tool or another server's tool.
+    pub fn register_tools(
+        &mut self,
+        server_name: &str,
+        tools: &[McpToolDefinition],
+    ) -> anyhow::Result<()> {
+        for tool in tools {
+            let exposed = &tool.definition.function.name;
+            if !is_valid_tool_name(exposed) {
+                bail!(
+                    "Tool '{}' from MCP server '{server_name}' is exposed as '{exposed}', \
+                     which is not a valid function name",
+                    tool.tool_name
+                );
+            }
+
+            if self.builtin_tools.contains(exposed) {
+                bail!(
+                    "Tool name collision: '{exposed}' from MCP server '{server_name}' ({}) is \
+                     also the name of a built-in tool. Rename the MCP server.",
+                    tool.tool_name
+                );
+            }
+
+            match self.tools.entry(exposed.clone()) {
+                Entry::Occupied(existing) => {
+                    let existing = existing.get();
+                    if existing.server != server_name || existing.tool != tool.tool_name {
+                        bail!(
+                            "Tool name collision: '{exposed}' is provided by both \
+                             '{}' ({}) and '{server_name}' ({}). Rename one of the MCP servers.",
+                            existing.server,
+                            existing.tool,
+                            tool.tool_name
+                        );
+                    }
+                }
+                Entry::Vacant(slot) => {
+                    slot.insert(ToolRoute {
+                        server: server_name.to_string(),
+                        tool: tool.tool_name.clone(),
+                        input_schema: tool.definition.function.parameters.clone(),
+                        read_only: tool.read_only,
+                    });
+                }
+            }
         }
+        Ok(())
     }
 
     #[allow(dead_code)]
@@ -44,8 +153,9 @@ impl McpRegistry {
         for (name, config) in &self.servers {
             let config_clone = config.clone();
             let name_clone = name.clone();
+            let handler = self.handler.for_server(name);
             tasks.push(async move {
-                let result = create_mcp_service(&config_clone, verbose).await;
+                let result = create_mcp_service(&config_clone, handler, verbose).await;
                 (name_clone, result)
             });
         }
@@ -66,21 +176,67 @@ impl McpRegistry {
         Ok(())
     }
 
-    pub fn find_server_for_tool(&self, tool_name: &str) -> Option<(&str, &McpServerConfig)> {
-        // Tool names are formatted as "{prefix}_{actual_tool_name}"
-        for (name, config) in &self.servers {
-            let prefix_with_underscore = format!("{}_", config.tool_prefix);
-            if tool_name.starts_with(&prefix_with_underscore) {
-                return Some((name, config));
-            }
-        }
-        None
+    pub fn find_tool(&self, exposed_name: &str) -> Option<&ToolRoute> {
+        self.tools.get(exposed_name)
     }
 
     pub fn get_service(&self, server_name: &str) -> Option<&McpService> {
         self.services.get(server_name)
     }
 
+    /// Returns false if the server was started but its transport has since closed,
+    /// which happens when the child process exits or crashes.
+    pub fn is_service_alive(&self, server_name: &str) -> bool {
+        self.services
+            .get(server_name)
+            .is_some_and(|service| !service.is_transport_closed())
+    }
+
+    /// Replaces a dead service with a fresh one, waiting with exponential back-off
+    /// between attempts. Gives up after `MAX_RESTARTS` restarts of the same server.
+    pub async fn restart_service(
+        &mut self,
+        server_name: &str,
+        verbose: bool,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        if let Some(service) = self.services.remove(server_name) {
+            let _ = service.cancel().await;
+        }
+
+        let attempts = self.restarts.entry(server_name.to_string()).or_insert(0);
+        if *attempts >= MAX_RESTARTS {
+            return Err(format!(
+                "MCP server '{server_name}' stopped {MAX_RESTARTS} times; not restarting it again"
+            )
+            .into());
+        }
+
+        let delay = RESTART_BACKOFF * 2u32.pow(*attempts);
+        *attempts += 1;
+        eprintln!(
+            "MCP server '{server_name}' stopped unexpectedly. Restarting (attempt {}/{MAX_RESTARTS})...",
+            attempts
+        );
+        tokio::time::sleep(delay).await;
+
+        self.initialize_service(server_name, verbose).await
+    }
+
+    /// Forgets the restarts of a server after it handled a call, so only crashes in a row
+    /// count towards `MAX_RESTARTS`.
+    pub fn reset_restarts(&mut self, server_name: &str) {
+        self.restarts.remove(server_name);
+    }
+
+    /// Cancels every running service and waits for it to stop.
+    pub async fn shutdown(&mut self) {
+        for (name, service) in self.services.drain() {
+            if let Err(e) = service.cancel().await {
+                eprintln!("Warning: Failed to stop MCP server '{name}': {e}");
+            }
+        }
+    }
+
     pub fn servers(&self) -> &HashMap<String, McpServerConfig> {
         &self.servers
     }
@@ -100,7 +256,8 @@ impl McpRegistry {
         }
 
         if let Some(config) = self.servers.get(server_name) {
-            match create_mcp_service(config, verbose).await {
+            let handler = self.handler.for_server(server_name);
+            match create_mcp_service(config, handler, verbose).await {
                 Ok(service) => {
                     self.services.insert(server_name.to_string(), service);
 
@@ -158,6 +315,7 @@ impl McpServerConfig {
 
 pub async fn create_mcp_service(
     config: &McpServerConfig,
+    handler: AskClientHandler,
     verbose: bool,
 ) -> Result<McpService, Box<dyn std::error::Error>> {
     let command = config.command.clone();
@@ -168,22 +326,74 @@ pub async fn create_mcp_service(
     let mut cmd = Command::new(&command);
     cmd.args(&args);
     cmd.envs(&env);
+    // Make sure the server never outlives us, even if we exit without a clean shutdown.
+    cmd.kill_on_drop(true);
 
     let mut proc = TokioChildProcess::builder(cmd);
     if verbose {
         proc = proc.stderr(Stdio::inherit());
     } else {
-        proc = proc.stderr(Stdio::null());
+        // Keep the server's stderr out of the terminal but available for debugging.
+        let log_file = server_log_path(&config.tool_prefix).and_then(|path| {
+            std::fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .ok()
+        });
+        proc = match log_file {
+            Some(file) => proc.stderr(Stdio::from(file)),
+            None => proc.stderr(Stdio::null()),
+        };
     }
 
     let (child_process, _stderr) = proc.spawn()?;
-    let service = ().serve(child_process).await?;
+    let service = handler.serve(child_process).await?;
 
     Ok(service)
 }
 
-fn convert_mcp_tool_to_openai(mcp_tool: &rmcp::model::Tool, prefix: &str) -> ChatCompletionTool {
-    let name = format!("{}_{}", prefix, mcp_tool.name);
+fn sanitize_tool_name(name: &str) -> String {
+    name.chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '_' || c == '-' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+fn is_valid_tool_name(name: &str) -> bool {
+    !name.is_empty() && name.len() <= MAX_TOOL_NAME_LEN && sanitize_tool_name(name) == name
+}
+
+/// Builds the name a tool is exposed to the model under: `{prefix}_{tool}`.
+/// Names that contain illegal characters or exceed the length limit are sanitized,
+/// truncated and suffixed with a hash of the original so they stay unique. The
+/// registry's lookup table maps them back to the original server and tool.
+pub fn mangle_tool_name(prefix: &str, tool_name: &str) -> String {
+    use std::collections::hash_map::DefaultHasher;
+    use std::hash::{Hash, Hasher};
+
+    let raw = format!("{prefix}_{tool_name}");
+    let sanitized = sanitize_tool_name(&raw);
+    if sanitized == raw && raw.len() <= MAX_TOOL_NAME_LEN {
+        return raw;
+    }
+
+    let mut hasher = DefaultHasher::new();
+    raw.hash(&mut hasher);
+    let suffix = format!("_{:08x}", hasher.finish() as u32);
+
+    // Sanitized names are ASCII, so byte truncation is safe.
+    let keep = sanitized.len().min(MAX_TOOL_NAME_LEN - suffix.len());
+    format!("{}{suffix}", &sanitized[..keep])
+}
+
+fn convert_mcp_tool_to_openai(mcp_tool: &rmcp::model::Tool, prefix: &str) -> McpToolDefinition {
+    let name = mangle_tool_name(prefix, &mcp_tool.name);
 
     // Ensure the schema has required fields for OpenAI
     let mut schema = mcp_tool.input_schema.as_ref().clone();
@@ -200,13 +410,21 @@ fn convert_mcp_tool_to_openai(mcp_tool: &rmcp::model::Tool, prefix: &str) -> Cha
         );
     }
 
-    ChatCompletionTool {
-        r#type: ChatCompletionToolType::Function,
-        function: FunctionObject {
-            name,
-            description: mcp_tool.description.as_ref().map(|c| c.to_string()),
-            parameters: Some(serde_json::Value::Object(schema)),
-            strict: None,
+    McpToolDefinition {
+        read_only: mcp_tool
+            .annotations
+            .as_ref()
+            .and_then(|annotations| annotations.read_only_hint)
+            .unwrap_or(false),
+        tool_name: mcp_tool.name.to_string(),
+        definition: ChatCompletionTool {
+            r#type: ChatCompletionToolType::Function,
+            function: FunctionObject {
+                name,
+                description: mcp_tool.description.as_ref().map(|c| c.to_string()),
+                parameters: Some(serde_json::Value::Object(schema)),
+                strict: None,
+            },
         },
     }
 }
@@ -214,7 +432,7 @@ fn convert_mcp_tool_to_openai(mcp_tool: &rmcp::model::Tool, prefix: &str) -> Cha
 pub fn get_mcp_tools(
     service: &McpService,
     config: &McpServerConfig,
-) -> Result<Vec<ChatCompletionTool>, String> {
+) -> Result<Vec<McpToolDefinition>, String> {
     tokio::task::block_in_place(|| {
         tokio::runtime::Handle::current().block_on(async {
             match service.list_tools(Default::default()).await {
@@ -231,26 +449,32 @@ pub fn get_mcp_tools(
 
 pub fn execute_mcp_tool_call(
     service: &McpService,
-    config: &McpServerConfig,
-    name: &str,
+    tool_name: &str,
     arguments: &str,
 ) -> Result<String, Box<dyn std::error::Error>> {
-    let prefix_with_underscore = format!("{}_", config.tool_prefix);
-    let tool_name = name.strip_prefix(&prefix_with_underscore).unwrap_or(name);
-
     tokio::task::block_in_place(|| {
         tokio::runtime::Handle::current().block_on(async {
             let args: Value = serde_json::from_str(arguments)?;
             let args_object = args.as_object().cloned();
 
-            let result = service
-                .call_tool(CallToolRequestParam {
+            let request =
+                ClientRequest::CallToolRequest(CallToolRequest::new(CallToolRequestParam {
                     name: tool_name.to_string().into(),
                     arguments: args_object,
-                })
+                }));
+            let handle = service
+                .send_cancellable_request(request, PeerRequestOptions::no_options())
                 .await?;
 
-            Ok(format_tool_result(&result))
+            let progress = service.service().progress();
+            progress.begin(handle.progress_token.clone(), tool_name);
+            let response = handle.await_response().await;
+            progress.finish();
+
+            match response? {
+                ServerResult::CallToolResult(result) => Ok(format_tool_result(&result)),
+                _ => Err("Unexpected response to tool call".into()),
+            }
         })
     })
 }
@@ -273,7 +497,7 @@ pub fn load_all_mcp_tools(registry: &McpRegistry, verbose: bool) -> Vec<ChatComp
                         eprintln!("  Loaded {} tools from '{}'", tools.len(), name);
                     }
                     loaded_servers.push((name.clone(), tools.len()));
-                    all_tools.extend(tools);
+                    all_tools.extend(tools.into_iter().map(|t| t.definition));
                 }
                 Err(e) => {
                     eprintln!("Failed to load MCP server '{name}': {e}");
@@ -0,0 +1,450 @@
+use crate::approval;
+use crate::tools::progress::ProgressDisplay;
+use async_openai::types::{
+    ChatCompletionRequestAssistantMessageArgs, ChatCompletionRequestMessage,
+    ChatCompletionRequestSystemMessageArgs, ChatCompletionRequestUserMessageArgs,
+    CreateChatCompletionRequestArgs, FinishReason, Stop,
+};
+use rmcp::ErrorData as McpError;
+use rmcp::RoleClient;
+use rmcp::handler::client::ClientHandler;
+use rmcp::model::{
+    ClientCapabilities, ClientInfo, Content, CreateElicitationRequestParam,
+    CreateElicitationResult, CreateMessageRequestParam, CreateMessageResult, ElicitationAction,
+    ElicitationCapability, Implementation, ListRootsResult, LoggingLevel,
+    LoggingMessageNotificationParam, ProgressNotificationParam, RawContent, Role, Root,
+    RootsCapabilities, SamplingMessage,
+};
+use rmcp::service::{NotificationContext, RequestContext};
+use serde_json::{Map, Value};
+use std::io::Write;
+use std::path::PathBuf;
+
+/// Used when `samplingMaxTokens` is not configured.
+pub const DEFAULT_SAMPLING_MAX_TOKENS: u32 = 1024;
+
+/// How sampling requests from MCP servers are fulfilled.
+#[derive(Clone)]
+pub struct SamplingSettings {
+    pub base_url: Option<String>,
+    pub model: String,
+    /// Upper bound on tokens per sampling request, regardless of what the server asks for.
+    pub max_tokens: u32,
+}
+
+/// Client side of every MCP connection. Answers sampling requests through the
+/// configured LLM provider, turns elicitation requests into terminal prompts and
+/// advertises the current working directory as the only root.
+#[derive(Clone, Default)]
+pub struct AskClientHandler {
+    server_name: String,
+    sampling: Option<SamplingSettings>,
+    /// Minimum level of server log messages shown in the terminal (warning if unset).
+    log_level: Option<LoggingLevel>,
+    progress: ProgressDisplay,
+    verbose: bool,
+}
+
+impl AskClientHandler {
+    pub fn new(
+        sampling: Option<SamplingSettings>,
+        log_level: Option<LoggingLevel>,
+        verbose: bool,
+    ) -> Self {
+        Self {
+            server_name: String::new(),
+            sampling,
+            log_level,
+            progress: ProgressDisplay::default(),
+            verbose,
+        }
+    }
+
+    pub fn progress(&self) -> &ProgressDisplay {
+        &self.progress
+    }
+
+    /// Returns a copy of this handler labelled with the server it is attached to.
+    pub fn for_server(&self, server_name: &str) -> Self {
+        Self {
+            server_name: server_name.to_string(),
+            ..self.clone()
+        }
+    }
+
+    async fn sample(
+        &self,
+        settings: &SamplingSettings,
+        params: CreateMessageRequestParam,
+    ) -> Result<CreateMessageResult, anyhow::Error> {
+        let client = crate::llms::get_openai_client(&settings.base_url, &self.verbose)?;
+
+        let mut messages = Vec::new();
+        if let Some(system_prompt) = &params.system_prompt {
+            messages.push(
+                ChatCompletionRequestSystemMessageArgs::default()
+                    .content(system_prompt.as_str())
+                    .build()
+                    .map(ChatCompletionRequestMessage::System)?,
+            );
+        }
+        for message in &params.messages {
+            let text = sampling_message_text(message);
+            let message = match message.role {
+                Role::User => ChatCompletionRequestUserMessageArgs::default()
+                    .content(text)
+                    .build()
+                    .map(ChatCompletionRequestMessage::User)?,
+                Role::Assistant => ChatCompletionRequestAssistantMessageArgs::default()
+                    .content(text)
+                    .build()
+                    .map(ChatCompletionRequestMessage::Assistant)?,
+            };
+            messages.push(message);
+        }
+
+        let mut request = CreateChatCompletionRequestArgs::default();
+        request
+            .model(settings.model.clone())
+            .messages(messages)
+            .max_completion_tokens(params.max_tokens.min(settings.max_tokens));
+        if let Some(temperature) = params.temperature {
+            request.temperature(temperature);
+        }
+        if let Some(stop) = params.stop_sequences.filter(|s| !s.is_empty()) {
+            request.stop(Stop::StringArray(stop));
+        }
+
+        let response = client.chat().create(request.build()?).await?;
+        let choice = response
+            .choices
+            .into_iter()
+            .next()
+            .ok_or_else(|| anyhow::anyhow!("The model returned no choices"))?;
+
+        let stop_reason = match choice.finish_reason {
+            Some(FinishReason::Length) => CreateMessageResult::STOP_REASON_END_MAX_TOKEN,
+            _ => CreateMessageResult::STOP_REASON_END_TURN,
+        };
+
+        Ok(CreateMessageResult {
+            model: response.model,
+            stop_reason: Some(stop_reason.to_string()),
+            message: SamplingMessage {
+                role: Role::Assistant,
+                content: Content::text(choice.message.content.unwrap_or_default()),
+            },
+        })
+    }
+}
+
+impl ClientHandler for AskClientHandler {
+    async fn create_message(
+        &self,
+        params: CreateMessageRequestParam,
+        _context: RequestContext<RoleClient>,
+    ) -> Result<CreateMessageResult, McpError> {
+        let Some(settings) = self.sampling.clone() else {
+            return Err(McpError::invalid_request(
+                "Sampling is not enabled for this client",
+                None,
+            ));
+        };
+
+        let max_tokens = params.max_tokens.min(settings.max_tokens);
+        let mut summary = format!(
+            "MCP server '{}' requests an LLM completion ({}, up to {} tokens):",
+            self.server_name, settings.model, max_tokens
+        );
+        if let Some(system_prompt) = &params.system_prompt {
+            summary.push_str(&format!("\n  [system] {system_prompt}"));
+        }
+        for message in &params.messages {
+            let role = match message.role {
+                Role::User => "user",
+                Role::Assistant => "assistant",
+            };
+            summary.push_str(&format!("\n  [{role}] {}", sampling_message_text(message)));
+        }
+
+        let approved = tokio::task::spawn_blocking(move || {
+            approval::confirm(&format!("{summary}\nAllow this request?"))
+        })
+        .await
+        .unwrap_or(false);
+
+        if !approved {
+            return Err(McpError::invalid_request(
+                "User declined the sampling request",
+                None,
+            ));
+        }
+
+        self.sample(&settings, params)
+            .await
+            .map_err(|e| McpError::internal_error(format!("Sampling failed: {e}"), None))
+    }
+
+    async fn create_elicitation(
+        &self,
+        request: CreateElicitationRequestParam,
+        _context: RequestContext<RoleClient>,
+    ) -> Result<CreateElicitationResult, McpError> {
+        let server_name = self.server_name.clone();
+        let result =
+            tokio::task::spawn_blocking(move || prompt_for_elicitation(&server_name, &request))
+                .await
+                .unwrap_or(CreateElicitationResult {
+                    action: ElicitationAction::Cancel,
+                    content: None,
+                });
+        Ok(result)
+    }
+
+    async fn list_roots(
+        &self,
+        _context: RequestContext<RoleClient>,
+    ) -> Result<ListRootsResult, McpError> {
+        let roots = std::env::current_dir()
+            .map(|cwd| {
+                vec![Root {
+                    uri: path_to_file_uri(&cwd),
+                    name: cwd.file_name().map(|n| n.to_string_lossy().to_string()),
+                }]
+            })
+            .unwrap_or_default();
+        Ok(ListRootsResult { roots })
+    }
+
+    async fn on_progress(
+        &self,
+        params: ProgressNotificationParam,
+        _context: NotificationContext<RoleClient>,
+    ) {
+        self.progress.update(&params);
+    }
+
+    async fn on_logging_message(
+        &self,
+        params: LoggingMessageNotificationParam,
+        _context: NotificationContext<RoleClient>,
+    ) {
+        let message = match &params.data {
+            Value::String(text) => text.clone(),
+            other => other.to_string(),
+        };
+        let logger = params
+            .logger
+            .as_ref()
+            .map(|l| format!(" {l}"))
+            .unwrap_or_default();
+        let level = level_name(params.level);
+
+        append_server_log(
+            &self.server_name,
+            &format!(
+                "{} [{level}]{logger} {message}",
+                chrono::Local::now().format("%Y-%m-%d %H:%M:%S")
+            ),
+        );
+
+        let threshold = if self.verbose {
+            LoggingLevel::Debug
+        } else {
+            self.log_level.unwrap_or(LoggingLevel::Warning)
+        };
+        if severity(params.level) >= severity(threshold) {
+            eprintln!("[{}] {level}:{logger} {message}", self.server_name);
+        }
+    }
+
+    fn get_info(&self) -> ClientInfo {
+        ClientInfo {
+            protocol_version: Default::default(),
+            capabilities: ClientCapabilities {
+                roots: Some(RootsCapabilities { list_changed: None }),
+                sampling: self.sampling.as_ref().map(|_| Map::new()),
+                elicitation: Some(ElicitationCapability {
+                    schema_validation: Some(false),
+                }),
+                ..Default::default()
+            },
+            client_info: Implementation {
+                name: env!("CARGO_PKG_NAME").to_string(),
+                version: env!("CARGO_PKG_VERSION").to_string(),
+                ..Default::default()
+            },
+        }
+    }
+}
+
+fn severity(level: LoggingLevel) -> u8 {
+    match level {
+        LoggingLevel::Debug => 0,
+        LoggingLevel::Info => 1,
+        LoggingLevel::Notice => 2,
+        LoggingLevel::Warning => 3,
+        LoggingLevel::Error => 4,
+        LoggingLevel::Critical => 5,
+        LoggingLevel::Alert => 6,
+        LoggingLevel::Emergency => 7,
+    }
+}
+
+fn level_name(level: LoggingLevel) -> String {
+    serde_json::to_value(level)
+        .ok()
+        .and_then(|v| v.as_str().map(str::to_string))
+        .unwrap_or_else(|| format!("{level:?}"))
+}
+
+/// Path of the log file for an MCP server, `~/.ask/logs/<server>.log`. Creates the
+/// directory if needed.
+pub fn server_log_path(server_name: &str) -> Option<PathBuf> {
+    let log_dir: PathBuf = shellexpand::tilde("~/.ask/logs")
+        .into_owned()
+        .parse()
+        .ok()?;
+    std::fs::create_dir_all(&log_dir).ok()?;
+    Some(log_dir.join(format!("{server_name}.log")))
+}
+
+fn append_server_log(server_name: &str, line: &str) {
+    let Some(path) = server_log_path(server_name) else {
+        return;
+    };
+    if let Ok(mut file) = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+    {
+        let _ = writeln!(file, "{line}");
+    }
+}
+
+fn sampling_message_text(message: &SamplingMessage) -> String {
+    match &message.content.raw {
+        RawContent::Text(text) => text.text.clone(),
+        RawContent::Image(image) => format!("[Image: {}]", image.mime_type),
+        RawContent::Audio(audio) => format!("[Audio: {}]", audio.mime_type),
+        RawContent::Resource(resource) => format!("[Resource: {:?}]", resource.resource),
+        RawContent::ResourceLink(link) => format!("[Resource: {}]", link.uri),
+    }
+}
+
+fn path_to_file_uri(path: &std::path::Path) -> String {
+    let path = path.display().to_string().replace('\\', "/");
+    if path.starts_with('/') {
+        format!("file://{path}")
+    } else {
+        format!("file:///{path}")
+    }
+}
+
+/// Asks the user for every field in the elicitation schema. Blank answers skip
+/// optional fields; answering a required field with nothing declines the request.
+fn prompt_for_elicitation(
+    server_name: &str,
+    request: &CreateElicitationRequestParam,
+) -> CreateElicitationResult {
+    println!("MCP server '{server_name}' is asking for input:");
+    println!("{}", request.message);
+
+    let action = approval::prompt_line("Respond? [y]es / [n]o / [c]ancel: ").to_lowercase();
+    match action.as_str() {
+        "y" | "yes" => {}
+        "c" | "cancel" => {
+            return CreateElicitationResult {
+                action: ElicitationAction::Cancel,
+                content: None,
+            };
+        }
+        _ => {
+            return CreateElicitationResult {
+                action: ElicitationAction::Decline,
+                content: None,
+            };
+        }
+    }
+
+    let required: Vec<&str> = request
+        .requested_schema
+        .get("required")
+        .and_then(|r| r.as_array())
+        .map(|r| r.iter().filter_map(|f| f.as_str()).collect())
+        .unwrap_or_default();
+
+    let mut content = Map::new();
+    if let Some(Value::Object(properties)) = request.requested_schema.get("properties") {
+        for (name, schema) in properties {
+            let is_required = required.contains(&name.as_str());
+            loop {
+                let answer =
+                    approval::prompt_line(&elicitation_field_prompt(name, schema, is_required));
+                if answer.is_empty() {
+                    // Also the answer on EOF, so closed stdin never loops here.
+                    if is_required {
+                        println!("'{name}' is required; declining the request.");
+                        return CreateElicitationResult {
+                            action: ElicitationAction::Decline,
+                            content: None,
+                        };
+                    }
+                    break;
+                }
+                match parse_elicitation_answer(&answer, schema) {
+                    Some(value) => {
+                        content.insert(name.clone(), value);
+                        break;
+                    }
+                    None => println!("Invalid value for '{name}'."),
+                }
+            }
+        }
+    }
+
+    CreateElicitationResult {
+        action: ElicitationAction::Accept,
+        content: Some(Value::Object(content)),
+    }
+}
+
+fn elicitation_field_prompt(name: &str, schema: &Value, required: bool) -> String {
+    let label = schema.get("title").and_then(|t| t.as_str()).unwrap_or(name);
+    let mut prompt = label.to_string();
+    if let Some(description) = schema.get("description").and_then(|d| d.as_str()) {
+        prompt.push_str(&format!(" ({description})"));
+    }
+    if let Some(Value::Array(options)) = schema.get("enum") {
+        let options: Vec<String> = options
+            .iter()
+            .map(|o| o.as_str().map(str::to_string).unwrap_or(o.to_string()))
+            .collect();
+        prompt.push_str(&format!(" [{}]", options.join("/")));
+    } else if schema.get("type").and_then(|t| t.as_str()) == Some("boolean") {
+        prompt.push_str(" [y/n]");
+    }
+    if !required {
+        prompt.push_str(" (optional)");
+    }
+    prompt.push_str(": ");
+    prompt
+}
+
+fn parse_elicitation_answer(answer: &str, schema: &Value) -> Option<Value> {
+    let value = match schema.get("type").and_then(|t| t.as_str()) {
+        Some("boolean") => match answer.to_lowercase().as_str() {
+            "y" | "yes" | "true" => Value::Bool(true),
+            "n" | "no" | "false" => Value::Bool(false),
+            _ => return None,
+        },
+        Some("integer") => Value::from(answer.parse::<i64>().ok()?),
+        Some("number") => Value::Number(serde_json::Number::from_f64(answer.parse().ok()?)?),
+        _ => Value::String(answer.to_string()),
+    };
+
+    match schema.get("enum") {
+        Some(Value::Array(options)) if !options.contains(&value) => None,
+        _ => Some(value),
+    }
+}
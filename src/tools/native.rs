@@ -0,0 +1,1012 @@
+use crate::checkpoints::SKIPPED_DIRS;
+use crate::config::NativeToolSettings;
+use crate::diff;
+use crate::tools::normalize_path;
+use anyhow::{Context, Result, bail};
+use async_openai::types::{ChatCompletionTool, ChatCompletionToolType, FunctionObject};
+use regex::{Regex, RegexBuilder};
+use serde_json::{Value, json};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// The built-in file tools, in the order they are offered.
+pub const NATIVE_TOOLS: [&str; 6] = [
+    "read_file",
+    "list_dir",
+    "glob",
+    "grep",
+    "write_file",
+    "apply_patch",
+];
+
+const READ_ONLY_TOOLS: [&str; 4] = ["read_file", "list_dir", "glob", "grep"];
+
+/// Lines `read_file` returns when no end line is given.
+const DEFAULT_READ_LINES: usize = 2000;
+
+/// Files larger than this are neither read nor searched.
+const MAX_FILE_BYTES: u64 = 10 * 1024 * 1024;
+
+const MAX_LIST_ENTRIES: usize = 1000;
+
+const MAX_GLOB_RESULTS: usize = 500;
+
+const MAX_GREP_MATCHES: usize = 200;
+
+/// Longer matching lines are cut off in `grep` results.
+const MAX_GREP_LINE_CHARS: usize = 300;
+
+/// Directory walks stop after this many entries.
+const MAX_WALK_ENTRIES: usize = 50_000;
+
+/// The built-in file tools, restricted to the configured root directories.
+pub struct NativeTools {
+    enabled: Vec<&'static str>,
+    /// Canonical directories the tools may access.
+    roots: Vec<PathBuf>,
+}
+
+impl NativeTools {
+    pub fn new(settings: Option<&NativeToolSettings>) -> Self {
+        let enabled = match settings {
+            Some(settings) if settings.enabled == Some(false) => Vec::new(),
+            Some(settings) => NATIVE_TOOLS
+                .into_iter()
+                .filter(|tool| settings.tools.get(*tool).copied().unwrap_or(true))
+                .collect(),
+            None => NATIVE_TOOLS.to_vec(),
+        };
+
+        let configured = settings
+            .map(|settings| settings.roots.clone())
+            .filter(|roots| !roots.is_empty())
+            .unwrap_or_else(|| vec![".".to_string()]);
+        let cwd = std::env::current_dir().unwrap_or_default();
+        let roots = configured
+            .iter()
+            .filter_map(|root| {
+                let path = cwd.join(shellexpand::tilde(root).as_ref());
+                path.canonicalize()
+                    .inspect_err(|e| {
+                        eprintln!("Warning: Ignoring native tool root {root}: {e}");
+                    })
+                    .ok()
+            })
+            .collect();
+
+        NativeTools { enabled, roots }
+    }
+
+    pub fn definitions(&self) -> Vec<ChatCompletionTool> {
+        self.enabled
+            .iter()
+            .filter_map(|name| definition(name))
+            .collect()
+    }
+
+    /// Names of the enabled tools.
+    pub fn names(&self) -> &[&'static str] {
+        &self.enabled
+    }
+
+    pub fn is_native(&self, name: &str) -> bool {
+        self.enabled.contains(&name)
+    }
+
+    /// Checks that a call only touches paths inside the roots and, for patches, that the
+    /// patch applies, so calls that can't run are refused before approval.
+    pub fn check(&self, name: &str, arguments: &Value) -> Result<()> {
+        if name == "apply_patch" {
+            self.plan_patch(string_argument(arguments, "patch")?)?;
+        } else {
+            let path = arguments
+                .get("path")
+                .and_then(|path| path.as_str())
+                .unwrap_or(".");
+            self.resolve(path)?;
+        }
+        Ok(())
+    }
+
+    /// What the call does, for the approval prompt. Writes and patches show a diff.
+    pub fn describe(&self, name: &str, arguments: &Value) -> String {
+        let path = arguments
+            .get("path")
+            .and_then(|path| path.as_str())
+            .unwrap_or(".");
+        match name {
+            "read_file" => {
+                let range = match (
+                    arguments.get("start_line").and_then(|line| line.as_u64()),
+                    arguments.get("end_line").and_then(|line| line.as_u64()),
+                ) {
+                    (None, None) => String::new(),
+                    (start, end) => format!(
+                        ", lines {}-{}",
+                        start.unwrap_or(1),
+                        end.map(|end| end.to_string()).unwrap_or_default()
+                    ),
+                };
+                format!("Reading ({path}{range})")
+            }
+            "list_dir" => format!("Listing Files ({path})"),
+            "glob" => format!(
+                "Finding Files ({} in {path})",
+                arguments["pattern"].as_str().unwrap_or_default()
+            ),
+            "grep" => format!(
+                "Searching ({} in {path})",
+                arguments["pattern"].as_str().unwrap_or_default()
+            ),
+            "write_file" => {
+                let content = arguments["content"].as_str().unwrap_or_default();
+                match self.resolve(path) {
+                    Ok(resolved) => format!(
+                        "Writing ({path})\n{}",
+                        diff::preview_write(&resolved, content)
+                    ),
+                    Err(e) => format!("Writing ({path})\n\x1b[33m({e})\x1b[0m"),
+                }
+            }
+            "apply_patch" => {
+                let patch = arguments["patch"].as_str().unwrap_or_default();
+                match self.plan_patch(patch) {
+                    Ok(files) => {
+                        let mut text = "Applying patch".to_string();
+                        for file in files {
+                            text.push_str(&format!(
+                                "\n\x1b[1m{} {}\x1b[0m\n{}",
+                                file.action(),
+                                display_path(&file.path),
+                                diff::unified_diff(
+                                    file.old.as_deref().unwrap_or_default(),
+                                    file.new.as_deref().unwrap_or_default()
+                                )
+                            ));
+                        }
+                        text
+                    }
+                    Err(e) => format!("Applying patch\n{patch}\n\x1b[33m({e:#})\x1b[0m"),
+                }
+            }
+            _ => format!("Executing {name}"),
+        }
+    }
+
+    /// The paths a call changes, for checkpoints. Empty for read-only tools.
+    pub fn paths_to_change(&self, name: &str, arguments: &Value) -> Result<Vec<PathBuf>> {
+        match name {
+            "write_file" => Ok(vec![self.resolve(string_argument(arguments, "path")?)?]),
+            "apply_patch" => {
+                let files = self.plan_patch(string_argument(arguments, "patch")?)?;
+                let mut paths = Vec::new();
+                for file in files {
+                    paths.extend(file.renamed_from);
+                    paths.push(file.path);
+                }
+                Ok(paths)
+            }
+            _ => Ok(Vec::new()),
+        }
+    }
+
+    pub fn execute(&self, name: &str, arguments: &Value) -> Result<String> {
+        match name {
+            "read_file" => self.read_file(arguments),
+            "list_dir" => self.list_dir(arguments),
+            "glob" => self.glob(arguments),
+            "grep" => self.grep(arguments),
+            "write_file" => self.write_file(arguments),
+            "apply_patch" => self.apply_patch(arguments),
+            _ => bail!("Unknown tool: {name}"),
+        }
+    }
+
+    /// `path` made absolute against the current directory. Fails when it, or the file a
+    /// symlink in it points to, is outside every root.
+    fn resolve(&self, path: &str) -> Result<PathBuf> {
+        let cwd = std::env::current_dir().context("Failed to get the current directory")?;
+        let path = normalize_path(&cwd.join(shellexpand::tilde(path).as_ref()));
+        let real = canonicalize_existing(&path);
+        if !self.roots.iter().any(|root| real.starts_with(root)) {
+            bail!(
+                "{} is outside the allowed directories: {}",
+                path.display(),
+                self.roots
+                    .iter()
+                    .map(|root| root.display().to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            );
+        }
+        Ok(path)
+    }
+
+    fn read_file(&self, arguments: &Value) -> Result<String> {
+        let path = self.resolve(string_argument(arguments, "path")?)?;
+        let bytes = read_bytes(&path)?;
+        if is_binary(&bytes) {
+            bail!(
+                "{} is a binary file ({} bytes)",
+                display_path(&path),
+                bytes.len()
+            );
+        }
+        let text = String::from_utf8_lossy(&bytes);
+        let lines: Vec<&str> = text.lines().collect();
+
+        if lines.is_empty() {
+            return Ok(format!("{} is empty", display_path(&path)));
+        }
+        let start = arguments["start_line"].as_u64().unwrap_or(1).max(1);
+        if start > lines.len() as u64 {
+            bail!(
+                "start_line {start} is past the end of {} ({} lines)",
+                display_path(&path),
+                lines.len()
+            );
+        }
+        let start = start as usize;
+        let end = arguments["end_line"]
+            .as_u64()
+            .map(|end| end.min(lines.len() as u64) as usize)
+            .unwrap_or(start.saturating_add(DEFAULT_READ_LINES - 1))
+            .min(lines.len());
+        if end < start {
+            bail!("end_line {end} is before start_line {start}");
+        }
+
+        let mut output = String::new();
+        for (index, line) in lines[start - 1..end].iter().enumerate() {
+            output.push_str(&format!("{:>6}\t{line}\n", start + index));
+        }
+        if start > 1 || end < lines.len() {
+            output.push_str(&format!(
+                "(lines {start}-{end} of {}; use start_line and end_line to read more)\n",
+                lines.len()
+            ));
+        }
+        Ok(output)
+    }
+
+    fn list_dir(&self, arguments: &Value) -> Result<String> {
+        let path = self.resolve(arguments["path"].as_str().unwrap_or("."))?;
+        let mut entries: Vec<(String, Option<u64>)> = fs::read_dir(&path)
+            .context(format!("Failed to read directory {}", display_path(&path)))?
+            .flatten()
+            .map(|entry| {
+                let name = entry.file_name().to_string_lossy().to_string();
+                match entry.metadata() {
+                    Ok(metadata) if metadata.is_dir() => (format!("{name}/"), None),
+                    Ok(metadata) => (name, Some(metadata.len())),
+                    Err(_) => (name, None),
+                }
+            })
+            .collect();
+        entries.sort();
+
+        if entries.is_empty() {
+            return Ok(format!("{} is empty", display_path(&path)));
+        }
+        let mut output = String::new();
+        for (name, size) in entries.iter().take(MAX_LIST_ENTRIES) {
+            match size {
+                Some(size) => output.push_str(&format!("{name}  ({size} bytes)\n")),
+                None => output.push_str(&format!("{name}\n")),
+            }
+        }
+        if entries.len() > MAX_LIST_ENTRIES {
+            output.push_str(&format!(
+                "… {} more entries\n",
+                entries.len() - MAX_LIST_ENTRIES
+            ));
+        }
+        Ok(output)
+    }
+
+    fn glob(&self, arguments: &Value) -> Result<String> {
+        let pattern = string_argument(arguments, "pattern")?;
+        let base = self.resolve(arguments["path"].as_str().unwrap_or("."))?;
+        let regex = glob_regex(pattern)?;
+
+        let mut matches = Vec::new();
+        let complete = self.walk(&base, &mut |path, is_dir| {
+            if !is_dir
+                && let Ok(relative) = path.strip_prefix(&base)
+                && regex.is_match(&slash_path(relative))
+            {
+                matches.push(display_path(path));
+            }
+            matches.len() < MAX_GLOB_RESULTS
+        });
+
+        if matches.is_empty() {
+            return Ok(format!(
+                "No files match {pattern} in {}",
+                display_path(&base)
+            ));
+        }
+        let mut output = matches.join("\n");
+        output.push('\n');
+        if !complete {
+            output.push_str("(results truncated; use a narrower pattern or path)\n");
+        }
+        Ok(output)
+    }
+
+    fn grep(&self, arguments: &Value) -> Result<String> {
+        let pattern = string_argument(arguments, "pattern")?;
+        let base = self.resolve(arguments["path"].as_str().unwrap_or("."))?;
+        let regex = RegexBuilder::new(pattern)
+            .case_insensitive(arguments["case_insensitive"].as_bool().unwrap_or(false))
+            .build()
+            .context("Invalid pattern")?;
+        let file_filter = match arguments["glob"].as_str() {
+            Some(glob) => Some(glob_regex(glob)?),
+            None => None,
+        };
+
+        let mut matches = Vec::new();
+        let search = |path: &Path, relative: &Path, matches: &mut Vec<String>| {
+            if let Some(filter) = &file_filter {
+                let name = path
+                    .file_name()
+                    .map(|name| name.to_string_lossy().to_string())
+                    .unwrap_or_default();
+                if !filter.is_match(&slash_path(relative)) && !filter.is_match(&name) {
+                    return;
+                }
+            }
+            let Ok(bytes) = read_bytes(path) else {
+                return;
+            };
+            if is_binary(&bytes) {
+                return;
+            }
+            for (index, line) in String::from_utf8_lossy(&bytes).lines().enumerate() {
+                if matches.len() >= MAX_GREP_MATCHES {
+                    return;
+                }
+                if regex.is_match(line) {
+                    let line = if line.chars().count() > MAX_GREP_LINE_CHARS {
+                        format!(
+                            "{}…",
+                            line.chars().take(MAX_GREP_LINE_CHARS).collect::<String>()
+                        )
+                    } else {
+                        line.to_string()
+                    };
+                    matches.push(format!("{}:{}: {line}", display_path(path), index + 1));
+                }
+            }
+        };
+
+        if base.is_file() {
+            search(&base, Path::new(""), &mut matches);
+        } else {
+            self.walk(&base, &mut |path, is_dir| {
+                if !is_dir {
+                    search(path, path.strip_prefix(&base).unwrap_or(path), &mut matches);
+                }
+                matches.len() < MAX_GREP_MATCHES
+            });
+        }
+
+        if matches.is_empty() {
+            return Ok(format!(
+                "No matches for {pattern} in {}",
+                display_path(&base)
+            ));
+        }
+        let mut output = matches.join("\n");
+        output.push('\n');
+        if matches.len() >= MAX_GREP_MATCHES {
+            output.push_str(&format!(
+                "(showing the first {MAX_GREP_MATCHES} matches; narrow the search for more)\n"
+            ));
+        }
+        Ok(output)
+    }
+
+    fn write_file(&self, arguments: &Value) -> Result<String> {
+        let path = self.resolve(string_argument(arguments, "path")?)?;
+        let content = string_argument(arguments, "content")?;
+        let existed = path.exists();
+        write(&path, content)?;
+        Ok(format!(
+            "{} {} ({} bytes)",
+            if existed { "Updated" } else { "Created" },
+            display_path(&path),
+            content.len()
+        ))
+    }
+
+    fn apply_patch(&self, arguments: &Value) -> Result<String> {
+        let files = self.plan_patch(string_argument(arguments, "patch")?)?;
+        let mut summary = Vec::new();
+        for file in &files {
+            match &file.new {
+                Some(content) => write(&file.path, content)?,
+                None => fs::remove_file(&file.path)
+                    .context(format!("Failed to delete {}", display_path(&file.path)))?,
+            }
+            if let Some(old_path) = &file.renamed_from {
+                fs::remove_file(old_path)
+                    .context(format!("Failed to delete {}", display_path(old_path)))?;
+            }
+            summary.push(format!("{} {}", file.action(), display_path(&file.path)));
+        }
+        Ok(summary.join("\n"))
+    }
+
+    /// The contents of every file the patch touches, before and after. Fails without
+    /// changing anything if any hunk doesn't apply.
+    fn plan_patch(&self, patch: &str) -> Result<Vec<PatchedFile>> {
+        let file_patches = parse_patch(patch)?;
+        let mut files = Vec::new();
+        for file_patch in file_patches {
+            let old_path = match &file_patch.old_path {
+                Some(path) => Some(self.resolve(path)?),
+                None => None,
+            };
+            let new_path = match &file_patch.new_path {
+                Some(path) => Some(self.resolve(path)?),
+                None => None,
+            };
+
+            let old = match &old_path {
+                Some(path) => Some(
+                    fs::read_to_string(path)
+                        .context(format!("Failed to read {}", display_path(path)))?,
+                ),
+                None => None,
+            };
+            if old.is_none()
+                && let Some(path) = &new_path
+                && path.exists()
+            {
+                bail!(
+                    "{} already exists; patch it instead of creating it",
+                    display_path(path)
+                );
+            }
+
+            let patched = apply_hunks(
+                old.as_deref().unwrap_or_default(),
+                &file_patch.hunks,
+                &file_patch.display_name(),
+            )?;
+            files.push(match (old_path, new_path) {
+                (Some(old_path), None) => PatchedFile {
+                    path: old_path,
+                    renamed_from: None,
+                    old,
+                    new: None,
+                },
+                (old_path, Some(new_path)) => PatchedFile {
+                    renamed_from: old_path.filter(|old_path| *old_path != new_path),
+                    path: new_path,
+                    old,
+                    new: Some(patched),
+                },
+                (None, None) => bail!("The patch has a file without a path"),
+            });
+        }
+        Ok(files)
+    }
+
+    /// Calls `visit` with every file and directory under `root`, in sorted order, until
+    /// it returns false. Symlinks are only followed to files inside the roots, and the
+    /// directories checkpoints skip are not entered. Returns false if the walk stopped
+    /// early.
+    fn walk(&self, root: &Path, visit: &mut dyn FnMut(&Path, bool) -> bool) -> bool {
+        let mut stack = vec![root.to_path_buf()];
+        let mut seen = 0;
+        while let Some(dir) = stack.pop() {
+            let Ok(read_dir) = fs::read_dir(&dir) else {
+                continue;
+            };
+            let mut entries: Vec<_> = read_dir.flatten().collect();
+            entries.sort_by_key(|entry| entry.file_name());
+
+            let mut subdirectories = Vec::new();
+            for entry in entries {
+                seen += 1;
+                if seen > MAX_WALK_ENTRIES {
+                    return false;
+                }
+                let path = entry.path();
+                let Ok(file_type) = entry.file_type() else {
+                    continue;
+                };
+                if file_type.is_symlink() && !self.is_linked_file_inside(&path) {
+                    continue;
+                }
+                let is_dir = file_type.is_dir();
+                if is_dir && SKIPPED_DIRS.contains(&entry.file_name().to_string_lossy().as_ref()) {
+                    continue;
+                }
+                if !visit(&path, is_dir) {
+                    return false;
+                }
+                if is_dir {
+                    subdirectories.push(path);
+                }
+            }
+            stack.extend(subdirectories.into_iter().rev());
+        }
+        true
+    }
+
+    /// Whether the symlink at `path` points to a file inside the roots.
+    fn is_linked_file_inside(&self, path: &Path) -> bool {
+        path.canonicalize().is_ok_and(|real| {
+            real.is_file() && self.roots.iter().any(|root| real.starts_with(root))
+        })
+    }
+}
+
+/// Whether the tool only reads, so `--dry-run --run-read-only` may run it.
+pub fn is_read_only(name: &str) -> bool {
+    READ_ONLY_TOOLS.contains(&name)
+}
+
+pub fn definition(name: &str) -> Option<ChatCompletionTool> {
+    let (description, parameters) = match name {
+        "read_file" => (
+            "Read a text file. Lines are numbered; large files are returned 2000 lines at a \
+             time, so use start_line and end_line to read the rest.",
+            json!({
+                "type": "object",
+                "properties": {
+                    "path": {"type": "string", "description": "The file to read"},
+                    "start_line": {"type": "integer", "description": "First line to return, starting at 1"},
+                    "end_line": {"type": "integer", "description": "Last line to return, inclusive"}
+                },
+                "required": ["path"]
+            }),
+        ),
+        "list_dir" => (
+            "List a directory's entries. Directories end with /; files show their size.",
+            json!({
+                "type": "object",
+                "properties": {
+                    "path": {"type": "string", "description": "The directory to list (default: the current directory)"}
+                }
+            }),
+        ),
+        "glob" => (
+            "Find files whose path relative to `path` matches a glob pattern. `*` and `?` \
+             match within a directory, `**` matches across directories and `{a,b}` matches \
+             alternatives, e.g. `src/**/*.rs` or `**/*.{ts,tsx}`.",
+            json!({
+                "type": "object",
+                "properties": {
+                    "pattern": {"type": "string", "description": "The glob pattern"},
+                    "path": {"type": "string", "description": "The directory to search (default: the current directory)"}
+                },
+                "required": ["pattern"]
+            }),
+        ),
+        "grep" => (
+            "Search the text files under a directory, or a single file, for lines matching a \
+             regular expression. Results are `path:line: text`.",
+            json!({
+                "type": "object",
+                "properties": {
+                    "pattern": {"type": "string", "description": "The regular expression"},
+                    "path": {"type": "string", "description": "The directory or file to search (default: the current directory)"},
+                    "glob": {"type": "string", "description": "Only search files matching this glob, e.g. `*.rs`"},
+                    "case_insensitive": {"type": "boolean", "description": "Ignore case"}
+                },
+                "required": ["pattern"]
+            }),
+        ),
+        "write_file" => (
+            "Create a file or replace its whole content. Parent directories are created. \
+             Prefer apply_patch for changes to existing files.",
+            json!({
+                "type": "object",
+                "properties": {
+                    "path": {"type": "string", "description": "The file to write"},
+                    "content": {"type": "string", "description": "The complete new content"}
+                },
+                "required": ["path", "content"]
+            }),
+        ),
+        "apply_patch" => (
+            "Change files with a unified diff, as produced by `diff -u` or `git diff`: \
+             `--- a/path` and `+++ b/path` headers followed by `@@` hunks with a few lines of \
+             context. Use /dev/null as the old path to create a file, or as the new path to \
+             delete one. Nothing is changed unless every hunk applies.",
+            json!({
+                "type": "object",
+                "properties": {
+                    "patch": {"type": "string", "description": "The unified diff"}
+                },
+                "required": ["patch"]
+            }),
+        ),
+        _ => return None,
+    };
+
+    Some(ChatCompletionTool {
+        r#type: ChatCompletionToolType::Function,
+        function: FunctionObject {
+            name: name.to_string(),
+            description: Some(description.to_string()),
+            parameters: Some(parameters),
+            strict: None,
+        },
+    })
+}
+
+fn string_argument<'a>(arguments: &'a Value, name: &str) -> Result<&'a str> {
+    arguments
+        .get(name)
+        .and_then(|value| value.as_str())
+        .context(format!("Missing string argument '{name}'"))
+}
+
+fn read_bytes(path: &Path) -> Result<Vec<u8>> {
+    let metadata = fs::metadata(path).context(format!("Failed to read {}", display_path(path)))?;
+    if metadata.is_dir() {
+        bail!("{} is a directory", display_path(path));
+    }
+    if metadata.len() > MAX_FILE_BYTES {
+        bail!(
+            "{} is too large ({} bytes)",
+            display_path(path),
+            metadata.len()
+        );
+    }
+    fs::read(path).context(format!("Failed to read {}", display_path(path)))
+}
+
+fn write(path: &Path, content: &str) -> Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).context(format!("Failed to create {}", display_path(dir)))?;
+    }
+    fs::write(path, content).context(format!("Failed to write {}", display_path(path)))
+}
+
+/// Text files don't contain NUL bytes.
+fn is_binary(bytes: &[u8]) -> bool {
+    bytes.iter().take(8192).any(|byte| *byte == 0)
+}
+
+/// A regular expression matching the relative paths the glob pattern matches.
+fn glob_regex(pattern: &str) -> Result<Regex> {
+    let chars: Vec<char> = pattern.chars().collect();
+    let mut regex = String::from("^");
+    let mut in_braces = false;
+    let mut i = 0;
+    while i < chars.len() {
+        match chars[i] {
+            '*' if chars.get(i + 1) == Some(&'*') => {
+                i += 1;
+                if chars.get(i + 1) == Some(&'/') {
+                    i += 1;
+                    regex.push_str("(?:.*/)?");
+                } else {
+                    regex.push_str(".*");
+                }
+            }
+            '*' => regex.push_str("[^/]*"),
+            '?' => regex.push_str("[^/]"),
+            '{' => {
+                in_braces = true;
+                regex.push_str("(?:");
+            }
+            ',' if in_braces => regex.push('|'),
+            '}' if in_braces => {
+                in_braces = false;
+                regex.push(')');
+            }
+            '[' => {
+                let end = (i + 1..chars.len()).find(|&j| chars[j] == ']' && j > i + 1);
+                match end {
+                    Some(end) => {
+                        let class: String = chars[i + 1..end].iter().collect();
+                        let class = class
+                            .strip_prefix('!')
+                            .map_or(class.clone(), |rest| format!("^{rest}"));
+                        regex.push_str(&format!("[{}]", class.replace('\\', "\\\\")));
+                        i = end;
+                    }
+                    None => regex.push_str("\\["),
+                }
+            }
+            c => regex.push_str(&regex::escape(&c.to_string())),
+        }
+        i += 1;
+    }
+    regex.push('$');
+    Regex::new(&regex).context(format!("Invalid glob pattern {pattern}"))
+}
+
+/// The path with `/` separators, as glob patterns are written.
+fn slash_path(path: &Path) -> String {
+    path.components()
+        .map(|component| component.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// The path relative to the current directory if it's inside it.
+fn display_path(path: &Path) -> String {
+    let relative = std::env::current_dir()
+        .ok()
+        .and_then(|cwd| path.strip_prefix(cwd).ok().map(Path::to_path_buf));
+    match relative {
+        Some(relative) if relative.as_os_str().is_empty() => ".".to_string(),
+        Some(relative) => relative.display().to_string(),
+        None => path.display().to_string(),
+    }
+}
+
+/// The path with symlinks resolved as far as it exists.
+fn canonicalize_existing(path: &Path) -> PathBuf {
+    let mut existing = path;
+    let mut rest = Vec::new();
+    loop {
+        if let Ok(real) = existing.canonicalize() {
+            return rest.iter().rev().fold(real, |path, name| path.join(name));
+        }
+        match (existing.parent(), existing.file_name()) {
+            (Some(parent), Some(name)) => {
+                rest.push(name.to_os_string());
+                existing = parent;
+            }
+            _ => return path.to_path_buf(),
+        }
+    }
+}
+
+/// One file of a patch, before and after.
+struct PatchedFile {
+    path: PathBuf,
+    /// The old path of a renamed file, which is deleted.
+    renamed_from: Option<PathBuf>,
+    /// `None` for created files.
+    old: Option<String>,
+    /// `None` for deleted files.
+    new: Option<String>,
+}
+
+impl PatchedFile {
+    fn action(&self) -> &'static str {
+        match (&self.old, &self.new, &self.renamed_from) {
+            (None, _, _) => "Created",
+            (_, None, _) => "Deleted",
+            (_, _, Some(_)) => "Moved and patched",
+            _ => "Patched",
+        }
+    }
+}
+
+struct FilePatch {
+    /// `None` for `/dev/null`.
+    old_path: Option<String>,
+    new_path: Option<String>,
+    hunks: Vec<Hunk>,
+}
+
+impl FilePatch {
+    fn display_name(&self) -> String {
+        self.new_path
+            .clone()
+            .or_else(|| self.old_path.clone())
+            .unwrap_or_default()
+    }
+}
+
+struct Hunk {
+    /// The line the hunk starts at in the old file, starting at 1.
+    old_start: usize,
+    /// Each line with its marker: ' ', '-' or '+'.
+    lines: Vec<(char, String)>,
+}
+
+fn parse_patch(patch: &str) -> Result<Vec<FilePatch>> {
+    let lines: Vec<&str> = patch.trim_end_matches('\n').lines().collect();
+    let header_regex = Regex::new(r"^@@ -(\d+)(?:,\d+)? \+\d+(?:,\d+)? @@").unwrap();
+    let mut files: Vec<FilePatch> = Vec::new();
+    let mut i = 0;
+    while i < lines.len() {
+        let line = lines[i];
+        if let Some(old) = line.strip_prefix("--- ")
+            && let Some(new) = lines.get(i + 1).and_then(|next| next.strip_prefix("+++ "))
+        {
+            files.push(FilePatch {
+                old_path: patch_path(old, "a/"),
+                new_path: patch_path(new, "b/"),
+                hunks: Vec::new(),
+            });
+            i += 2;
+            continue;
+        }
+
+        if line.starts_with("@@") {
+            let Some(file) = files.last_mut() else {
+                bail!("The patch has a hunk before any --- and +++ file header");
+            };
+            let old_start = header_regex
+                .captures(line)
+                .and_then(|captures| captures[1].parse().ok())
+                .context(format!("Invalid hunk header: {line}"))?;
+            let mut hunk = Hunk {
+                old_start,
+                lines: Vec::new(),
+            };
+            i += 1;
+            while let Some(line) = lines.get(i) {
+                if line.starts_with("@@")
+                    || (line.starts_with("--- ")
+                        && lines
+                            .get(i + 1)
+                            .is_some_and(|next| next.starts_with("+++ ")))
+                    || line.starts_with("diff ")
+                {
+                    break;
+                }
+                match line.chars().next() {
+                    Some(marker @ (' ' | '-' | '+')) => {
+                        hunk.lines.push((marker, line[1..].to_string()))
+                    }
+                    // Some tools drop the space of empty context lines.
+                    None => hunk.lines.push((' ', String::new())),
+                    // "\ No newline at end of file"
+                    Some('\\') => {}
+                    Some(_) => bail!("Invalid line in hunk: {line}"),
+                }
+                i += 1;
+            }
+            file.hunks.push(hunk);
+            continue;
+        }
+
+        // `diff --git`, `index` and mode lines carry nothing needed here.
+        i += 1;
+    }
+
+    if files.is_empty() {
+        bail!("The patch has no --- and +++ file headers; it must be a unified diff");
+    }
+    Ok(files)
+}
+
+/// The path in a `---` or `+++` header without git's prefix and any timestamp.
+fn patch_path(header: &str, prefix: &str) -> Option<String> {
+    let path = header.split('\t').next().unwrap_or(header).trim();
+    if path == "/dev/null" {
+        return None;
+    }
+    Some(path.strip_prefix(prefix).unwrap_or(path).to_string())
+}
+
+fn apply_hunks(old: &str, hunks: &[Hunk], name: &str) -> Result<String> {
+    let line_ending = if old.contains("\r\n") { "\r\n" } else { "\n" };
+    let mut lines: Vec<String> = old
+        .lines()
+        .map(|line| line.trim_end_matches('\r').to_string())
+        .collect();
+
+    // Lines added or removed by earlier hunks move the later ones.
+    let mut offset: isize = 0;
+    // Hunks apply in order, so each is searched for after the previous one.
+    let mut earliest = 0;
+    for (index, hunk) in hunks.iter().enumerate() {
+        let before: Vec<&str> = hunk
+            .lines
+            .iter()
+            .filter(|(marker, _)| *marker != '+')
+            .map(|(_, line)| line.as_str())
+            .collect();
+        let after: Vec<String> = hunk
+            .lines
+            .iter()
+            .filter(|(marker, _)| *marker != '-')
+            .map(|(_, line)| line.clone())
+            .collect();
+
+        let hint = (hunk.old_start.saturating_sub(1) as isize + offset).max(0) as usize;
+        let position = if before.is_empty() {
+            // A hunk that only adds lines goes after its start line.
+            Some((hunk.old_start as isize + offset).clamp(0, lines.len() as isize) as usize)
+        } else {
+            find_block(&lines, &before, hint, earliest)
+        };
+        let Some(position) = position else {
+            bail!(
+                "Hunk {} of {name} doesn't match the file near line {}",
+                index + 1,
+                hunk.old_start
+            );
+        };
+
+        let added = after.len();
+        lines.splice(position..position + before.len(), after);
+        offset += added as isize - before.len() as isize;
+        earliest = position + added;
+    }
+
+    let mut new = lines.join(line_ending);
+    if !lines.is_empty() && (old.is_empty() || old.ends_with('\n')) {
+        new.push_str(line_ending);
+    }
+    Ok(new)
+}
+
+/// Where `block` occurs in `lines` at or after `earliest`, closest to `hint`. Trailing
+/// whitespace is ignored if there's no exact match.
+fn find_block(lines: &[String], block: &[&str], hint: usize, earliest: usize) -> Option<usize> {
+    if block.len() > lines.len() {
+        return None;
+    }
+    let last = lines.len() - block.len();
+    let closest = |matches: &dyn Fn(usize) -> bool| {
+        (earliest..=last)
+            .filter(|&start| matches(start))
+            .min_by_key(|&start| start.abs_diff(hint))
+    };
+    closest(&|start| {
+        block
+            .iter()
+            .enumerate()
+            .all(|(i, line)| lines[start + i] == *line)
+    })
+    .or_else(|| {
+        closest(&|start| {
+            block
+                .iter()
+                .enumerate()
+                .all(|(i, line)| lines[start + i].trim_end() == line.trim_end())
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(unix)]
+    #[test]
+    fn walks_do_not_follow_symlinks_outside_the_roots() {
+        use std::os::unix::fs::symlink;
+
+        let scratch = std::env::temp_dir().join(format!("ask-native-{}", std::process::id()));
+        let root = scratch.join("root");
+        let outside = scratch.join("outside");
+        fs::create_dir_all(&root).unwrap();
+        fs::create_dir_all(&outside).unwrap();
+        fs::write(outside.join("id_rsa"), "secret key\n").unwrap();
+        fs::write(root.join("notes.txt"), "secret plans\n").unwrap();
+        symlink(outside.join("id_rsa"), root.join("key")).unwrap();
+        symlink(&outside, root.join("linked")).unwrap();
+        symlink(root.join("notes.txt"), root.join("plans")).unwrap();
+
+        let tools = NativeTools {
+            enabled: NATIVE_TOOLS.to_vec(),
+            roots: vec![root.canonicalize().unwrap()],
+        };
+        let path = root.display().to_string();
+        let grep = tools.execute("grep", &json!({"pattern": "secret", "path": path}));
+        let glob = tools.execute("glob", &json!({"pattern": "**", "path": path}));
+        let read = tools.execute("read_file", &json!({"path": root.join("key")}));
+        fs::remove_dir_all(&scratch).unwrap();
+
+        let grep = grep.unwrap();
+        assert!(grep.contains("notes.txt:1: secret plans"), "{grep}");
+        assert!(grep.contains("plans:1: secret plans"), "{grep}");
+        assert!(!grep.contains("secret key"), "{grep}");
+
+        let glob = glob.unwrap();
+        let found: Vec<&str> = glob.lines().collect();
+        assert!(found.iter().any(|path| path.ends_with("/plans")), "{glob}");
+        assert!(!found.iter().any(|path| path.ends_with("/key")), "{glob}");
+        assert!(!found.iter().any(|path| path.contains("/linked")), "{glob}");
+
+        assert!(read.is_err());
+    }
+}
@@ -0,0 +1,461 @@
+use crate::sessions::ShellState;
+use crate::shell::shell_info;
+use anyhow::{Context, Result, bail};
+use std::collections::BTreeMap;
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::time::{Duration, Instant};
+
+pub const DEFAULT_COMMAND_TIMEOUT_SECS: u64 = 120;
+
+/// Variables the shell maintains itself; they are never saved with the session.
+const SHELL_MANAGED_VARIABLES: [&str; 4] = ["PWD", "OLDPWD", "SHLVL", "_"];
+
+/// Variables whose names contain one of these are never saved with the session.
+const SECRET_NAME_PARTS: [&str; 8] = [
+    "KEY",
+    "TOKEN",
+    "SECRET",
+    "PASSWORD",
+    "PASSWD",
+    "CREDENTIAL",
+    "AUTH",
+    "COOKIE",
+];
+
+enum Line {
+    Stdout(String),
+    Stderr(String),
+}
+
+struct ShellProcess {
+    child: Child,
+    stdin: ChildStdin,
+    lines: Receiver<Line>,
+}
+
+/// A shell that stays alive for the whole run, so `cd`, exported variables and
+/// activated virtualenvs carry over from one `execute_command` call to the next.
+///
+/// Each command is followed by a unique sentinel line on stdout and stderr that
+/// carries its exit status and the shell's directory. A command that doesn't finish
+/// in time is killed together with the shell; the next command starts a new shell in
+/// the last known directory.
+pub struct PersistentShell {
+    process: Option<ShellProcess>,
+    cwd: PathBuf,
+    /// Variables set in every new shell: restored from the session, then kept up to
+    /// date when the state is captured.
+    env: BTreeMap<String, String>,
+    unset: Vec<String>,
+    /// The environment of the running shell when it started or was last captured;
+    /// only the changes since are saved.
+    baseline: BTreeMap<String, String>,
+    timeout: Duration,
+    commands_run: u64,
+}
+
+impl PersistentShell {
+    /// Prepares a shell that continues from `state`. The shell itself is started with
+    /// the first command.
+    pub fn new(state: Option<ShellState>, timeout: Duration) -> Result<Self> {
+        if cfg!(windows) {
+            bail!("Persistent shells are not supported on Windows");
+        }
+
+        let current_dir = std::env::current_dir().context("Failed to get current directory")?;
+        let (cwd, env, unset) = match state {
+            Some(state) => {
+                let cwd = if state.started_in == current_dir && state.cwd.is_dir() {
+                    state.cwd
+                } else {
+                    current_dir
+                };
+                (cwd, state.env, state.unset)
+            }
+            None => (current_dir, BTreeMap::new(), Vec::new()),
+        };
+
+        Ok(PersistentShell {
+            process: None,
+            cwd,
+            env,
+            unset,
+            baseline: BTreeMap::new(),
+            timeout,
+            commands_run: 0,
+        })
+    }
+
+    /// The program and arguments of the shell: the user's bash or zsh as a login shell
+    /// when `loginShell` is enabled, `sh` otherwise.
+    fn program() -> (String, Vec<&'static str>) {
+        let info = shell_info();
+        if info.login_shell
+            && matches!(info.name.as_str(), "bash" | "zsh")
+            && let Some(path) = &info.path
+        {
+            return (path.display().to_string(), vec!["-l"]);
+        }
+        ("sh".to_string(), vec![])
+    }
+
+    fn spawn(&self) -> Result<ShellProcess> {
+        let (program, args) = Self::program();
+        let mut command = Command::new(&program);
+        command
+            .args(args)
+            .current_dir(&self.cwd)
+            .envs(&self.env)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped());
+        for name in &self.unset {
+            command.env_remove(name);
+        }
+
+        let mut child = command
+            .spawn()
+            .context(format!("Failed to start persistent shell '{program}'"))?;
+        let stdin = child.stdin.take().context("Shell stdin is not available")?;
+        let stdout = child
+            .stdout
+            .take()
+            .context("Shell stdout is not available")?;
+        let stderr = child
+            .stderr
+            .take()
+            .context("Shell stderr is not available")?;
+
+        let (sender, lines) = mpsc::channel();
+        forward_lines(stdout, sender.clone(), Line::Stdout);
+        forward_lines(stderr, sender, Line::Stderr);
+
+        Ok(ShellProcess {
+            child,
+            stdin,
+            lines,
+        })
+    }
+
+    /// Parses `command` with `-n` in a separate shell of the same kind. An incomplete
+    /// command, such as one with an unclosed quote, would otherwise leave the persistent
+    /// shell waiting for more input until the command timeout.
+    fn check_syntax(command: &str) -> Result<()> {
+        let (program, _) = Self::program();
+        let Ok(mut child) = Command::new(&program)
+            .arg("-n")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped())
+            .spawn()
+        else {
+            // Without a checker the command still runs; the timeout covers the rest.
+            return Ok(());
+        };
+        if let Some(mut stdin) = child.stdin.take() {
+            let _ = stdin.write_all(format!("{command}\n").as_bytes());
+        }
+        let output = child
+            .wait_with_output()
+            .context("Failed to check the command's syntax")?;
+        if !output.status.success() {
+            bail!(
+                "The command was not run because it has a syntax error:\n{}",
+                String::from_utf8_lossy(&output.stderr).trim_end()
+            );
+        }
+        Ok(())
+    }
+
+    /// The shell's current directory.
+    pub fn cwd(&self) -> &Path {
+        &self.cwd
+    }
+
+    /// Runs `command`, first changing to `working_directory` if given. The result has
+    /// the same format as `execute_command`'s, followed by the exit status and the
+    /// shell's directory, and the exit code.
+    pub fn run(&mut self, command: &str, working_directory: Option<&str>) -> (String, Option<i32>) {
+        if let Err(e) = Self::check_syntax(command) {
+            return (format!("Error: {e:#}"), None);
+        }
+        match self.execute(command, working_directory, self.timeout) {
+            Ok(output) => {
+                let mut result = if output.stderr.is_empty() {
+                    output.stdout
+                } else {
+                    format!(
+                        "stdout:\n{}\n---\nstderr:\n{}",
+                        output.stdout, output.stderr
+                    )
+                };
+                if !result.is_empty() && !result.ends_with('\n') {
+                    result.push('\n');
+                }
+                result.push_str(&format!(
+                    "[exit status: {}; working directory: {}]",
+                    output.status,
+                    self.cwd.display()
+                ));
+                (result, output.status.parse().ok())
+            }
+            Err(e) => (format!("Error: {e:#}"), None),
+        }
+    }
+
+    fn execute(
+        &mut self,
+        command: &str,
+        working_directory: Option<&str>,
+        timeout: Duration,
+    ) -> Result<CommandOutput> {
+        if self.process.is_none() {
+            self.process = Some(self.spawn()?);
+            // Includes what a login shell's profile and rc file export, which isn't saved.
+            let setup = format!("{}env", shell_info().rc_prelude());
+            self.baseline = parse_env(&self.execute(&setup, None, Duration::from_secs(10))?.stdout);
+        }
+
+        self.commands_run += 1;
+        let sentinel = format!(
+            "__ASK_DONE_{}_{}_{}__",
+            std::process::id(),
+            self.commands_run,
+            chrono::Local::now()
+                .timestamp_nanos_opt()
+                .unwrap_or_default()
+        );
+
+        let cd = match working_directory {
+            Some(dir) => format!("cd -- {} && ", shell_quote(dir)),
+            None => String::new(),
+        };
+        // Commands read from /dev/null so they can't consume the script that follows.
+        let script = format!(
+            "{cd}{{ {command}\n}} </dev/null\n\
+             __ask_status=$?\n\
+             printf '\\n%s %s %s\\n' '{sentinel}' \"$__ask_status\" \"$PWD\"\n\
+             printf '\\n%s\\n' '{sentinel}' >&2\n"
+        );
+
+        let process = self.process.as_mut().expect("shell was started above");
+        if let Err(e) = process
+            .stdin
+            .write_all(script.as_bytes())
+            .and_then(|_| process.stdin.flush())
+        {
+            self.kill();
+            bail!("The shell exited ({e}). A new shell will be started for the next command.");
+        }
+
+        let deadline = Instant::now() + timeout;
+        let mut stdout: Vec<String> = vec![];
+        let mut stderr: Vec<String> = vec![];
+        let mut status = None;
+        let mut stderr_done = false;
+        while status.is_none() || !stderr_done {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            match process.lines.recv_timeout(remaining) {
+                Ok(Line::Stdout(line)) => match line.strip_prefix(&sentinel) {
+                    Some(rest) => {
+                        let (code, cwd) = rest.trim_start().split_once(' ').unwrap_or((rest, ""));
+                        status = Some(code.trim().to_string());
+                        if !cwd.is_empty() {
+                            self.cwd = PathBuf::from(cwd);
+                        }
+                    }
+                    None => stdout.push(line),
+                },
+                Ok(Line::Stderr(line)) => {
+                    if line == sentinel {
+                        stderr_done = true;
+                    } else {
+                        stderr.push(line);
+                    }
+                }
+                Err(RecvTimeoutError::Timeout) => {
+                    self.kill();
+                    bail!(
+                        "The command did not finish within {} seconds and was killed. The shell \
+                         was reset: environment changes since the start of the run are lost and \
+                         the next command starts in {}.\nOutput so far:\n{}",
+                        timeout.as_secs(),
+                        self.cwd.display(),
+                        join_output(stdout, stderr)
+                    );
+                }
+                Err(RecvTimeoutError::Disconnected) => {
+                    let exit = process
+                        .child
+                        .wait()
+                        .map(|status| status.to_string())
+                        .unwrap_or_else(|_| "unknown status".to_string());
+                    self.process = None;
+                    bail!(
+                        "The shell exited with {exit}. A new shell will be started in {} for the \
+                         next command.\nOutput:\n{}",
+                        self.cwd.display(),
+                        join_output(stdout, stderr)
+                    );
+                }
+            }
+        }
+
+        // Every output is followed by the newline printed before the sentinel, so joining
+        // the lines restores it exactly.
+        Ok(CommandOutput {
+            stdout: stdout.join("\n"),
+            stderr: stderr.join("\n"),
+            status: status.unwrap_or_default(),
+        })
+    }
+
+    /// The shell's directory and the variables its commands set or unset, for saving
+    /// with the session. Variables with secret-looking names are left out.
+    pub fn state(&mut self) -> Option<ShellState> {
+        let started_in = std::env::current_dir().ok()?;
+        if self.process.is_some() {
+            let output = self.execute("env", None, Duration::from_secs(10)).ok()?;
+            let shell_env = parse_env(&output.stdout);
+            let saved = |name: &str| !SHELL_MANAGED_VARIABLES.contains(&name);
+
+            for (name, value) in &shell_env {
+                if saved(name) && self.baseline.get(name) != Some(value) {
+                    self.env.insert(name.clone(), value.clone());
+                    self.unset.retain(|unset| unset != name);
+                }
+            }
+            for name in self.baseline.keys() {
+                if saved(name) && !shell_env.contains_key(name) {
+                    self.env.remove(name);
+                    if !self.unset.contains(name) {
+                        self.unset.push(name.clone());
+                    }
+                }
+            }
+            self.unset.sort();
+            self.baseline = shell_env;
+        }
+
+        Some(ShellState {
+            started_in,
+            cwd: self.cwd.clone(),
+            env: self
+                .env
+                .iter()
+                .filter(|(name, _)| !is_secret_name(name))
+                .map(|(name, value)| (name.clone(), value.clone()))
+                .collect(),
+            unset: self.unset.clone(),
+        })
+    }
+
+    /// Kills the shell and everything started from it.
+    fn kill(&mut self) {
+        let Some(mut process) = self.process.take() else {
+            return;
+        };
+        kill_descendants(process.child.id());
+        let _ = process.child.kill();
+        let _ = process.child.wait();
+    }
+}
+
+impl Drop for PersistentShell {
+    fn drop(&mut self) {
+        self.kill();
+    }
+}
+
+struct CommandOutput {
+    stdout: String,
+    stderr: String,
+    status: String,
+}
+
+fn forward_lines<R: std::io::Read + Send + 'static>(
+    reader: R,
+    sender: Sender<Line>,
+    wrap: fn(String) -> Line,
+) {
+    std::thread::spawn(move || {
+        let mut reader = BufReader::new(reader);
+        let mut buffer = Vec::new();
+        loop {
+            buffer.clear();
+            match reader.read_until(b'\n', &mut buffer) {
+                Ok(0) | Err(_) => break,
+                Ok(_) => {
+                    let line = String::from_utf8_lossy(&buffer);
+                    let line = line.strip_suffix('\n').unwrap_or(&line);
+                    if sender.send(wrap(line.to_string())).is_err() {
+                        break;
+                    }
+                }
+            }
+        }
+    });
+}
+
+fn join_output(stdout: Vec<String>, stderr: Vec<String>) -> String {
+    let stdout = stdout.join("\n");
+    let stderr = stderr.join("\n");
+    if stderr.is_empty() {
+        stdout
+    } else {
+        format!("stdout:\n{stdout}\n---\nstderr:\n{stderr}")
+    }
+}
+
+fn shell_quote(text: &str) -> String {
+    format!("'{}'", text.replace('\'', "'\\''"))
+}
+
+/// Whether a variable name contains one of `SECRET_NAME_PARTS`, ignoring case.
+fn is_secret_name(name: &str) -> bool {
+    let name = name.to_ascii_uppercase();
+    SECRET_NAME_PARTS.iter().any(|part| name.contains(part))
+}
+
+/// Parses `env` output. Lines that don't start a new `NAME=value` pair continue the
+/// previous value.
+fn parse_env(output: &str) -> BTreeMap<String, String> {
+    let mut env = BTreeMap::new();
+    let mut current: Option<String> = None;
+    for line in output.lines() {
+        if let Some((name, value)) = line.split_once('=')
+            && !name.is_empty()
+            && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+            && !name.starts_with(|c: char| c.is_ascii_digit())
+        {
+            env.insert(name.to_string(), value.to_string());
+            current = Some(name.to_string());
+        } else if let Some(name) = &current
+            && let Some(value) = env.get_mut(name)
+        {
+            value.push('\n');
+            value.push_str(line);
+        }
+    }
+    env
+}
+
+fn kill_descendants(pid: u32) {
+    use sysinfo::{Pid, ProcessesToUpdate, System};
+
+    let mut system = System::new();
+    system.refresh_processes(ProcessesToUpdate::All, true);
+
+    let mut parents = vec![Pid::from_u32(pid)];
+    while let Some(parent) = parents.pop() {
+        for (child_pid, process) in system.processes() {
+            if process.parent() == Some(parent) {
+                parents.push(*child_pid);
+                process.kill();
+            }
+        }
+    }
+}
@@ -0,0 +1,77 @@
+use rmcp::model::{ProgressNotificationParam, ProgressToken};
+use std::io::Write;
+use std::sync::{Arc, Mutex};
+
+const SPINNER_FRAMES: [char; 10] = ['⠋', '⠙', '⠹', '⠸', '⠼', '⠴', '⠦', '⠧', '⠇', '⠏'];
+const BAR_WIDTH: usize = 20;
+
+struct ActiveProgress {
+    token: ProgressToken,
+    label: String,
+    frame: usize,
+    drawn: bool,
+}
+
+/// Single-line progress indicator on stderr for the MCP tool call in flight.
+/// Only notifications carrying the token of that call are rendered.
+#[derive(Clone, Default)]
+pub struct ProgressDisplay {
+    active: Arc<Mutex<Option<ActiveProgress>>>,
+}
+
+impl ProgressDisplay {
+    pub fn begin(&self, token: ProgressToken, label: &str) {
+        *self.active.lock().unwrap() = Some(ActiveProgress {
+            token,
+            label: label.to_string(),
+            frame: 0,
+            drawn: false,
+        });
+    }
+
+    pub fn update(&self, params: &ProgressNotificationParam) {
+        if !atty::is(atty::Stream::Stderr) {
+            return;
+        }
+
+        let mut active = self.active.lock().unwrap();
+        let Some(progress) = active.as_mut() else {
+            return;
+        };
+        if progress.token != params.progress_token {
+            return;
+        }
+
+        let spinner = SPINNER_FRAMES[progress.frame % SPINNER_FRAMES.len()];
+        progress.frame += 1;
+
+        let amount = match params.total {
+            Some(total) if total > 0.0 => {
+                let ratio = (params.progress / total).clamp(0.0, 1.0);
+                let filled = (ratio * BAR_WIDTH as f64).round() as usize;
+                format!(
+                    "[{}{}] {:>3.0}%",
+                    "#".repeat(filled),
+                    "-".repeat(BAR_WIDTH - filled),
+                    ratio * 100.0
+                )
+            }
+            _ => format!("{}", params.progress),
+        };
+        let message = params.message.as_deref().unwrap_or("");
+
+        eprint!("\r\x1b[2K{spinner} {} {amount} {message}", progress.label);
+        let _ = std::io::stderr().flush();
+        progress.drawn = true;
+    }
+
+    /// Stops tracking the current call and clears the progress line if one was drawn.
+    pub fn finish(&self) {
+        if let Some(progress) = self.active.lock().unwrap().take()
+            && progress.drawn
+        {
+            eprint!("\r\x1b[2K");
+            let _ = std::io::stderr().flush();
+        }
+    }
+}
@@ -0,0 +1,242 @@
+use serde_json::{Map, Value, json};
+
+/// Tool-call arguments that passed validation, possibly after light repairs.
+pub struct ValidatedArguments {
+    pub value: Value,
+    /// Human readable description of every repair applied to the raw arguments.
+    pub repairs: Vec<String>,
+}
+
+/// Why a tool call's arguments were rejected.
+pub struct ArgumentError {
+    pub problems: Vec<String>,
+}
+
+impl ArgumentError {
+    fn new(problem: String) -> Self {
+        Self {
+            problems: vec![problem],
+        }
+    }
+
+    /// Formats the error as the tool result sent back to the model, so it can
+    /// correct the call instead of seeing a raw parser message.
+    pub fn to_tool_result(&self, tool_name: &str) -> String {
+        json!({
+            "error": "invalid_arguments",
+            "tool": tool_name,
+            "problems": self.problems,
+            "hint": "Fix the listed problems and call the tool again with arguments matching its schema."
+        })
+        .to_string()
+    }
+}
+
+/// Parses the model-produced `arguments` and checks them against a JSON schema.
+/// Trailing commas, stringified numbers/booleans and numbers passed where strings
+/// are expected are repaired rather than rejected.
+pub fn validate_arguments(
+    arguments: &str,
+    schema: Option<&Value>,
+) -> Result<ValidatedArguments, ArgumentError> {
+    let mut repairs = Vec::new();
+    let mut value = parse_arguments(arguments, &mut repairs)?;
+
+    if let Some(schema) = schema {
+        let mut problems = Vec::new();
+        check_value(&mut value, schema, "arguments", &mut problems, &mut repairs);
+        if !problems.is_empty() {
+            return Err(ArgumentError { problems });
+        }
+    }
+
+    Ok(ValidatedArguments { value, repairs })
+}
+
+fn parse_arguments(arguments: &str, repairs: &mut Vec<String>) -> Result<Value, ArgumentError> {
+    if arguments.trim().is_empty() {
+        repairs.push("treated empty arguments as {}".to_string());
+        return Ok(Value::Object(Map::new()));
+    }
+
+    match serde_json::from_str(arguments) {
+        Ok(value) => Ok(value),
+        Err(original) => {
+            let repaired = remove_trailing_commas(arguments);
+            match serde_json::from_str(&repaired) {
+                Ok(value) => {
+                    repairs.push("removed trailing commas".to_string());
+                    Ok(value)
+                }
+                Err(_) => Err(ArgumentError::new(format!(
+                    "arguments are not valid JSON: {original}"
+                ))),
+            }
+        }
+    }
+}
+
+/// `json` without commas directly before a closing `}` or `]`. Commas inside strings are
+/// kept.
+fn remove_trailing_commas(json: &str) -> String {
+    let chars: Vec<char> = json.chars().collect();
+    let mut output = String::with_capacity(json.len());
+    let mut in_string = false;
+    let mut escaped = false;
+    for (i, &c) in chars.iter().enumerate() {
+        if in_string {
+            if escaped {
+                escaped = false;
+            } else if c == '\\' {
+                escaped = true;
+            } else if c == '"' {
+                in_string = false;
+            }
+        } else if c == '"' {
+            in_string = true;
+        } else if c == ','
+            && chars[i + 1..]
+                .iter()
+                .find(|c| !c.is_whitespace())
+                .is_some_and(|next| matches!(next, '}' | ']'))
+        {
+            continue;
+        }
+        output.push(c);
+    }
+    output
+}
+
+fn schema_types(schema: &Value) -> Vec<&str> {
+    match schema.get("type") {
+        Some(Value::String(t)) => vec![t.as_str()],
+        Some(Value::Array(types)) => types.iter().filter_map(|t| t.as_str()).collect(),
+        _ => Vec::new(),
+    }
+}
+
+fn matches_type(value: &Value, expected: &str) -> bool {
+    match expected {
+        "string" => value.is_string(),
+        "number" => value.is_number(),
+        "integer" => value.is_i64() || value.is_u64(),
+        "boolean" => value.is_boolean(),
+        "array" => value.is_array(),
+        "object" => value.is_object(),
+        "null" => value.is_null(),
+        _ => true,
+    }
+}
+
+fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+/// Attempts to convert `value` into one of the `expected` types.
+fn coerce(value: &Value, expected: &[&str]) -> Option<Value> {
+    for ty in expected {
+        let coerced = match (*ty, value) {
+            ("integer", Value::String(s)) => s.trim().parse::<i64>().ok().map(Value::from),
+            ("number", Value::String(s)) => s
+                .trim()
+                .parse::<f64>()
+                .ok()
+                .and_then(serde_json::Number::from_f64)
+                .map(Value::Number),
+            ("boolean", Value::String(s)) => match s.trim() {
+                "true" => Some(Value::Bool(true)),
+                "false" => Some(Value::Bool(false)),
+                _ => None,
+            },
+            ("string", Value::Number(n)) => Some(Value::String(n.to_string())),
+            ("string", Value::Bool(b)) => Some(Value::String(b.to_string())),
+            _ => None,
+        };
+        if coerced.is_some() {
+            return coerced;
+        }
+    }
+    None
+}
+
+fn check_value(
+    value: &mut Value,
+    schema: &Value,
+    path: &str,
+    problems: &mut Vec<String>,
+    repairs: &mut Vec<String>,
+) {
+    let types = schema_types(schema);
+    if !types.is_empty() && !types.iter().any(|t| matches_type(value, t)) {
+        match coerce(value, &types) {
+            Some(coerced) => {
+                repairs.push(format!(
+                    "converted {path} from {} to {}",
+                    type_name(value),
+                    type_name(&coerced)
+                ));
+                *value = coerced;
+            }
+            None => {
+                problems.push(format!(
+                    "{path} must be of type {}, got {}",
+                    types.join(" or "),
+                    type_name(value)
+                ));
+                return;
+            }
+        }
+    }
+
+    if let Some(Value::Array(allowed)) = schema.get("enum")
+        && !allowed.contains(value)
+    {
+        let allowed: Vec<String> = allowed.iter().map(|v| v.to_string()).collect();
+        problems.push(format!("{path} must be one of: {}", allowed.join(", ")));
+    }
+
+    match value {
+        Value::Object(object) => {
+            if let Some(Value::Array(required)) = schema.get("required") {
+                for field in required.iter().filter_map(|f| f.as_str()) {
+                    if !object.contains_key(field) {
+                        problems.push(format!("{path}.{field} is required but missing"));
+                    }
+                }
+            }
+
+            if let Some(Value::Object(properties)) = schema.get("properties") {
+                for (key, property_schema) in properties {
+                    if let Some(property) = object.get_mut(key) {
+                        let property_path = format!("{path}.{key}");
+                        check_value(property, property_schema, &property_path, problems, repairs);
+                    }
+                }
+
+                if schema.get("additionalProperties") == Some(&Value::Bool(false)) {
+                    for key in object.keys() {
+                        if !properties.contains_key(key) {
+                            problems.push(format!("{path}.{key} is not an allowed property"));
+                        }
+                    }
+                }
+            }
+        }
+        Value::Array(items) => {
+            if let Some(item_schema) = schema.get("items") {
+                for (index, item) in items.iter_mut().enumerate() {
+                    let item_path = format!("{path}[{index}]");
+                    check_value(item, item_schema, &item_path, problems, repairs);
+                }
+            }
+        }
+        _ => {}
+    }
+}
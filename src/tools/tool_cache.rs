@@ -1,8 +1,9 @@
-use crate::tools::mcp::{McpServerConfig, get_mcp_tools};
+use crate::tools::mcp::{McpServerConfig, McpToolDefinition, ToolRoute, get_mcp_tools};
+use crate::tools::mcp_client::AskClientHandler;
 use async_openai::types::ChatCompletionTool;
 use rmcp::RoleClient;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::fs;
 use std::path::PathBuf;
 
@@ -14,14 +15,22 @@ pub struct ToolCache {
 #[derive(Serialize, Deserialize)]
 pub struct CacheEntry {
     pub config_hash: String,
-    pub tools: Vec<ChatCompletionTool>,
+    pub tools: Vec<McpToolDefinition>,
 }
 
-pub type McpService = rmcp::service::RunningService<RoleClient, ()>;
+pub type McpService = rmcp::service::RunningService<RoleClient, AskClientHandler>;
 
 pub struct McpRegistry {
     pub servers: HashMap<String, McpServerConfig>,
     pub services: HashMap<String, McpService>,
+    /// Exposed tool name -> originating server and tool name.
+    pub tools: HashMap<String, ToolRoute>,
+    /// Number of times each server has been restarted after crashing.
+    pub restarts: HashMap<String, u32>,
+    /// Handler cloned into every service to answer server-initiated requests.
+    pub handler: AskClientHandler,
+    /// Names of the tools `ask` provides itself, which MCP tools may not take.
+    pub builtin_tools: HashSet<String>,
 }
 
 fn get_cache_path() -> PathBuf {
@@ -87,9 +96,11 @@ pub async fn populate_cache_if_needed(
             if let Some(config) = registry.servers().get(name) {
                 let config_clone = config.clone();
                 let name_clone = name.clone();
+                let handler = registry.handler.for_server(name);
                 tasks.push(async move {
                     let result =
-                        crate::tools::mcp::create_mcp_service(&config_clone, verbose).await;
+                        crate::tools::mcp::create_mcp_service(&config_clone, handler, verbose)
+                            .await;
                     (name_clone, config_clone, result)
                 });
             }
@@ -106,6 +117,7 @@ pub async fn populate_cache_if_needed(
                             eprintln!("  Cached tools for '{name}'");
                         }
                     }
+                    let _ = service.cancel().await;
                 }
                 Err(e) => {
                     eprintln!("Warning: Failed to initialize MCP server '{name}': {e}");
@@ -121,17 +133,29 @@ pub async fn populate_cache_if_needed(
     Ok(())
 }
 
-pub fn load_cached_tools(registry: &McpRegistry, verbose: bool) -> Vec<ChatCompletionTool> {
+pub fn load_cached_tools(
+    registry: &mut McpRegistry,
+    verbose: bool,
+) -> anyhow::Result<Vec<ChatCompletionTool>> {
     let cache = load_cache();
     let mut all_tools = Vec::new();
     let mut loaded_count = 0;
 
-    for (name, config) in registry.servers() {
+    // Register servers in a stable order so collisions are reported consistently.
+    let mut servers: Vec<(String, McpServerConfig)> = registry
+        .servers()
+        .iter()
+        .map(|(name, config)| (name.clone(), config.clone()))
+        .collect();
+    servers.sort_by(|a, b| a.0.cmp(&b.0));
+
+    for (name, config) in &servers {
         let config_hash = config.hash();
 
         if let Some(entry) = cache.entries.get(name)
             && entry.config_hash == config_hash
         {
+            registry.register_tools(name, &entry.tools)?;
             if verbose {
                 eprintln!(
                     "Loaded {} tools from cache for '{}'",
@@ -139,7 +163,7 @@ pub fn load_cached_tools(registry: &McpRegistry, verbose: bool) -> Vec<ChatCompl
                     name
                 );
             }
-            all_tools.extend(entry.tools.clone());
+            all_tools.extend(entry.tools.iter().map(|t| t.definition.clone()));
             loaded_count += 1;
             continue;
         }
@@ -153,13 +177,13 @@ pub fn load_cached_tools(registry: &McpRegistry, verbose: bool) -> Vec<ChatCompl
         eprintln!("Loaded {loaded_count} MCP server(s) from cache");
     }
 
-    all_tools
+    Ok(all_tools)
 }
 
 pub fn update_cache_for_server(
     server_name: &str,
     config: &McpServerConfig,
-    tools: Vec<ChatCompletionTool>,
+    tools: Vec<McpToolDefinition>,
 ) {
     let mut cache = load_cache();
     cache.entries.insert(